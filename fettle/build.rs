@@ -0,0 +1,64 @@
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// Sources that define what a strategy sees of fettle: the `Strategy` trait,
+/// its context and the types they use. A plugin shares their layout with the
+/// server, so their hash is part of the plugin build id.
+const STRATEGY_SOURCES: &[&str] = &[
+    "Cargo.toml",
+    "src/errors.rs",
+    "src/models.rs",
+    "src/models/candles.rs",
+    "src/models/exchange.rs",
+    "src/strategy.rs",
+    "src/strategy/context.rs",
+    "src/strategy/fill.rs",
+    "src/strategy/output.rs",
+    "src/strategy/plugin.rs",
+    "src/strategy/slippage.rs",
+];
+
+fn main() {
+    // Plugins and the server must be built by the same compiler to share
+    // `dyn Strategy`, so the compiler version is part of the plugin build id.
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".into());
+    let version = Command::new(rustc)
+        .arg("--version")
+        .output()
+        .ok()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .unwrap_or_default();
+    println!("cargo:rustc-env=FETTLE_RUSTC_VERSION={}", version.trim());
+    println!("cargo:rerun-if-env-changed=RUSTC");
+
+    // Plugins also share fettle's dependencies with the server, so the lock
+    // file of the server's workspace counts as a source too. The strategy
+    // manager pins the strategies workspace to it.
+    const SERVER_LOCK: &str = "../Cargo.lock";
+    let lock = fs::read(SERVER_LOCK).unwrap_or_default();
+    let out_dir = std::env::var("OUT_DIR").unwrap();
+    fs::write(Path::new(&out_dir).join("Cargo.lock"), &lock).unwrap();
+
+    let mut hasher = Sha256::new();
+    let sources = STRATEGY_SOURCES.iter().map(|path| {
+        let content = fs::read(path).unwrap_or_else(|e| panic!("Failed to read {}: {}", path, e));
+        (*path, content)
+    });
+    for (path, content) in sources.chain([(SERVER_LOCK, lock)]) {
+        hasher.update(path.as_bytes());
+        hasher.update([0]);
+        hasher.update((content.len() as u64).to_le_bytes());
+        hasher.update(&content);
+        println!("cargo:rerun-if-changed={}", path);
+    }
+    let hash = format!("{:x}", hasher.finalize());
+    println!("cargo:rustc-env=FETTLE_SOURCE_HASH={}", &hash[..16]);
+
+    // Set by Cargo for every build script.
+    for (var, env) in [("TARGET", "FETTLE_TARGET"), ("PROFILE", "FETTLE_PROFILE")] {
+        let value = std::env::var(var).unwrap_or_default();
+        println!("cargo:rustc-env={}={}", env, value);
+    }
+}
@@ -1,6 +1,8 @@
 use crate::AppResult;
-use crate::services::tasks::{load_backtest_tasks, load_fetch_candles_tasks};
-use crate::tasks::{BacktestTask, FetchCandlesTask};
+use crate::services::tasks::{
+    load_backtest_tasks, load_fetch_candles_tasks, load_optimization_tasks, load_walk_forward_tasks,
+};
+use crate::tasks::{BacktestTask, FetchCandlesTask, OptimizationTask, WalkForwardTask, WorkerPool};
 use crate::{handlers, strategy::StrategyManager};
 use axum::{
     Router,
@@ -8,6 +10,7 @@ use axum::{
 };
 use sqlx::PgPool;
 use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::sync::{RwLock, broadcast};
 use tokio_util::sync::CancellationToken;
@@ -20,12 +23,22 @@ pub struct AppState {
     pub fetch_candles_tasks: Arc<RwLock<HashMap<Uuid, Arc<RwLock<FetchCandlesTask>>>>>,
     pub backtest_event_tx: broadcast::Sender<BacktestTask>,
     pub backtest_tasks: Arc<RwLock<HashMap<Uuid, Arc<RwLock<BacktestTask>>>>>,
+    pub optimization_event_tx: broadcast::Sender<OptimizationTask>,
+    pub optimization_tasks: Arc<RwLock<HashMap<Uuid, Arc<RwLock<OptimizationTask>>>>>,
+    pub walk_forward_event_tx: broadcast::Sender<WalkForwardTask>,
+    pub walk_forward_tasks: Arc<RwLock<HashMap<Uuid, Arc<RwLock<WalkForwardTask>>>>>,
     pub strategy_manager: StrategyManager,
     pub db_pool: PgPool,
+    pub workers: WorkerPool,
     pub shutdown_token: CancellationToken,
 }
 
-pub async fn create_app(db_pool: PgPool, shutdown_token: CancellationToken) -> AppResult<Router> {
+pub async fn create_app(
+    db_pool: PgPool,
+    workers: WorkerPool,
+    strategy_runner: Option<PathBuf>,
+    shutdown_token: CancellationToken,
+) -> AppResult<Router> {
     let (fetch_candles_event_tx, _) = broadcast::channel(1000);
     let mut fetch_candles_tasks = HashMap::new();
     let loaded_fetch_candles_tasks = load_fetch_candles_tasks(&db_pool).await?;
@@ -44,15 +57,41 @@ pub async fn create_app(db_pool: PgPool, shutdown_token: CancellationToken) -> A
         backtest_tasks.insert(task_id, task);
     }
 
-    let strategy_manager = StrategyManager::new().expect("Failed to create StrategyManager");
+    let (optimization_event_tx, _) = broadcast::channel(1000);
+    let mut optimization_tasks = HashMap::new();
+    let loaded_optimization_tasks = load_optimization_tasks(&db_pool).await?;
+    for task in loaded_optimization_tasks {
+        let task_id = task.id;
+        let task = Arc::new(RwLock::new(task));
+        optimization_tasks.insert(task_id, task);
+    }
+
+    let (walk_forward_event_tx, _) = broadcast::channel(1000);
+    let mut walk_forward_tasks = HashMap::new();
+    let loaded_walk_forward_tasks = load_walk_forward_tasks(&db_pool).await?;
+    for task in loaded_walk_forward_tasks {
+        let task_id = task.id;
+        let task = Arc::new(RwLock::new(task));
+        walk_forward_tasks.insert(task_id, task);
+    }
+
+    let strategy_manager = StrategyManager::new()
+        .expect("Failed to create StrategyManager")
+        .with_runner(strategy_runner)
+        .with_load_timeout(workers.budget().call);
 
     let state = AppState {
         fetch_candles_event_tx,
         fetch_candles_tasks: Arc::new(RwLock::new(fetch_candles_tasks)),
         backtest_event_tx,
         backtest_tasks: Arc::new(RwLock::new(backtest_tasks)),
+        optimization_event_tx,
+        optimization_tasks: Arc::new(RwLock::new(optimization_tasks)),
+        walk_forward_event_tx,
+        walk_forward_tasks: Arc::new(RwLock::new(walk_forward_tasks)),
         strategy_manager,
         db_pool,
+        workers,
         shutdown_token,
     };
 
@@ -76,10 +115,49 @@ pub async fn create_app(db_pool: PgPool, shutdown_token: CancellationToken) -> A
         .route("/tasks/backtest", get(handlers::backtest::get_all_tasks))
         .route("/tasks/backtest", post(handlers::backtest::create_task))
         .route("/tasks/backtest/{id}", get(handlers::backtest::get_task))
+        .route(
+            "/tasks/backtest/{id}/rerun",
+            post(handlers::backtest::rerun_task),
+        )
+        .route(
+            "/tasks/backtest/{id}/cancel",
+            post(handlers::backtest::cancel_task),
+        )
+        .route(
+            "/tasks/backtest/{id}/pause",
+            post(handlers::backtest::pause_task),
+        )
+        .route(
+            "/tasks/backtest/{id}/resume",
+            post(handlers::backtest::resume_task),
+        )
         .route(
             "/tasks/backtest/stream",
             get(handlers::backtest::stream_tasks),
         )
+        .route("/tasks/optimize", get(handlers::optimize::get_all_tasks))
+        .route("/tasks/optimize", post(handlers::optimize::create_task))
+        .route("/tasks/optimize/{id}", get(handlers::optimize::get_task))
+        .route(
+            "/tasks/optimize/stream",
+            get(handlers::optimize::stream_tasks),
+        )
+        .route(
+            "/tasks/walk-forward",
+            get(handlers::walk_forward::get_all_tasks),
+        )
+        .route(
+            "/tasks/walk-forward",
+            post(handlers::walk_forward::create_task),
+        )
+        .route(
+            "/tasks/walk-forward/{id}",
+            get(handlers::walk_forward::get_task),
+        )
+        .route(
+            "/tasks/walk-forward/stream",
+            get(handlers::walk_forward::stream_tasks),
+        )
         .route("/candles", get(handlers::candles::get_candles))
         .route(
             "/candles/available",
@@ -87,6 +165,10 @@ pub async fn create_app(db_pool: PgPool, shutdown_token: CancellationToken) -> A
         )
         .route("/strategy/list", get(handlers::strategy::list_strategies))
         .route("/strategy/add", post(handlers::strategy::add_strategy))
+        .route(
+            "/strategy/parameters",
+            get(handlers::strategy::get_parameters),
+        )
         .route("/strategy/source/get", get(handlers::source::get_source))
         .route("/strategy/source/save", post(handlers::source::save_source))
         .route(
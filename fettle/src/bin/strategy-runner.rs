@@ -0,0 +1,22 @@
+//! Hosts a strategy plugin for the server, which starts it with the library
+//! path as its argument and a connected Unix socket as its standard input.
+
+#[cfg(unix)]
+fn main() -> fettle::AppResult<()> {
+    use std::os::fd::AsFd;
+    use std::os::unix::net::UnixStream;
+    use std::path::PathBuf;
+
+    let Some(lib_path) = std::env::args_os().nth(1).map(PathBuf::from) else {
+        eprintln!("Usage: strategy-runner <library>");
+        std::process::exit(2);
+    };
+    let socket = UnixStream::from(std::io::stdin().as_fd().try_clone_to_owned()?);
+    fettle::strategy::runner::serve(&lib_path, socket)
+}
+
+#[cfg(not(unix))]
+fn main() {
+    eprintln!("Strategy runners are only supported on Unix");
+    std::process::exit(1);
+}
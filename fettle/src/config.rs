@@ -1,9 +1,12 @@
 use serde::Deserialize;
+use std::path::PathBuf;
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Config {
     pub server: ServerConfig,
     pub database: DatabaseConfig,
+    #[serde(default)]
+    pub simulation: SimulationConfig,
     pub log_level: String,
 }
 
@@ -19,6 +22,23 @@ pub struct DatabaseConfig {
     pub max_connections: u32,
 }
 
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SimulationConfig {
+    /// Backtests simulated at once; defaults to the number of cores.
+    pub workers: Option<usize>,
+    /// Longest a strategy may spend in one tick or hook; unlimited when unset.
+    pub call_timeout_ms: Option<u64>,
+    /// Longest a strategy may spend in all calls of one backtest; unlimited
+    /// when unset.
+    pub total_timeout_secs: Option<u64>,
+    /// Runs each strategy in a runner process instead of loading it into the
+    /// server.
+    #[serde(default)]
+    pub isolate_strategies: bool,
+    /// Runner binary; defaults to the one next to the server binary.
+    pub runner_path: Option<PathBuf>,
+}
+
 impl Config {
     pub fn load() -> Result<Self, config::ConfigError> {
         let settings = config::Config::builder()
@@ -2,5 +2,7 @@ pub mod backtest;
 pub mod candles;
 pub mod fetch_candles;
 pub mod info;
+pub mod optimize;
 pub mod source;
 pub mod strategy;
+pub mod walk_forward;
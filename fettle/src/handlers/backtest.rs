@@ -1,8 +1,10 @@
 use crate::app::AppState;
-use crate::errors::{ApiResult, AppError};
+use crate::errors::{ApiResult, AppError, AppResult};
 use crate::exchange::ccxt::CCXT;
-use crate::models::Timeframe;
-use crate::tasks::{BacktestStatus, BacktestTask};
+use crate::models::{MarketPrecision, Timeframe, TradingFees};
+use crate::strategy::{FillModel, LiquidityConfig, MarginConfig, SlippageModel};
+use crate::tasks::backtest::default_initial_capital;
+use crate::tasks::{BacktestConfig, BacktestStatus, BacktestTask, TaskControl};
 use axum::{
     extract::{Path, State},
     response::{
@@ -10,7 +12,8 @@ use axum::{
         sse::{Event, KeepAlive, Sse},
     },
 };
-use chrono::Utc;
+use bigdecimal::{BigDecimal, Zero};
+use chrono::{DateTime, Utc, serde::ts_milliseconds_option};
 use futures::stream::Stream;
 use serde::{Deserialize, Serialize};
 use std::convert::Infallible;
@@ -25,7 +28,48 @@ pub struct CreateBacktestTaskRequest {
     pub name: String,
     pub exchange: String,
     pub symbol: String,
+    /// Additional symbols traded alongside `symbol` from one shared balance.
+    #[serde(default)]
+    #[ts(optional)]
+    pub symbols: Option<Vec<String>>,
     pub timeframe: Timeframe,
+    /// Object deserialized into the strategy's parameters.
+    #[serde(default)]
+    #[ts(optional)]
+    pub parameters: Option<serde_json::Value>,
+    #[serde(default, with = "ts_milliseconds_option")]
+    #[ts(optional, type = "number")]
+    pub start: Option<DateTime<Utc>>,
+    #[serde(default, with = "ts_milliseconds_option")]
+    #[ts(optional, type = "number")]
+    pub end: Option<DateTime<Utc>>,
+    #[serde(default)]
+    #[ts(optional, type = "string")]
+    pub initial_capital: Option<BigDecimal>,
+    /// Annual rate, e.g. `0.04`; zero when unset.
+    #[serde(default)]
+    #[ts(optional, type = "string")]
+    pub risk_free_rate: Option<BigDecimal>,
+    /// Overrides the exchange's fees for every symbol.
+    #[serde(default)]
+    #[ts(optional)]
+    pub fees: Option<TradingFees>,
+    /// Overrides the exchange's precision for `symbol`.
+    #[serde(default)]
+    #[ts(optional)]
+    pub precision: Option<MarketPrecision>,
+    #[serde(default)]
+    #[ts(optional)]
+    pub margin: Option<MarginConfig>,
+    #[serde(default)]
+    #[ts(optional)]
+    pub fill_model: Option<FillModel>,
+    #[serde(default)]
+    #[ts(optional)]
+    pub slippage_model: Option<SlippageModel>,
+    #[serde(default)]
+    #[ts(optional)]
+    pub liquidity: Option<LiquidityConfig>,
 }
 
 #[derive(Debug, Serialize, TS)]
@@ -34,12 +78,99 @@ pub struct CreateBacktestTaskResponse {
     pub task_id: Uuid,
 }
 
+/// Validates the request and resolves its defaults.
+pub(crate) fn backtest_config(request: &CreateBacktestTaskRequest) -> AppResult<BacktestConfig> {
+    let initial_capital = request
+        .initial_capital
+        .clone()
+        .unwrap_or_else(default_initial_capital);
+    if initial_capital <= BigDecimal::zero() {
+        return Err(AppError::BadRequest(format!(
+            "Initial capital must be positive, got {}",
+            initial_capital
+        )));
+    }
+    let risk_free_rate = request.risk_free_rate.clone().unwrap_or_default();
+    if risk_free_rate <= BigDecimal::from(-1) || risk_free_rate >= BigDecimal::from(1) {
+        return Err(AppError::BadRequest(format!(
+            "Risk-free rate must be between -1 and 1, got {}",
+            risk_free_rate
+        )));
+    }
+    if let (Some(start), Some(end)) = (request.start, request.end)
+        && start >= end
+    {
+        return Err(AppError::BadRequest(
+            "Backtest start must be before its end".into(),
+        ));
+    }
+    if let Some(fees) = &request.fees {
+        fees.validate()?;
+    }
+    if let Some(parameters) = &request.parameters
+        && !parameters.is_object()
+    {
+        return Err(AppError::BadRequest(
+            "Strategy parameters must be an object".into(),
+        ));
+    }
+
+    let margin = request.margin.clone().unwrap_or_default();
+    margin.validate()?;
+    let fill_model = request.fill_model.unwrap_or_default();
+    fill_model.validate(request.timeframe)?;
+    let slippage_model = request.slippage_model.clone().unwrap_or_default();
+    slippage_model.validate()?;
+    if let Some(liquidity) = &request.liquidity {
+        liquidity.validate()?;
+    }
+
+    let symbols = request.symbols.clone().unwrap_or_default();
+    for (i, symbol) in symbols.iter().enumerate() {
+        if *symbol == request.symbol || symbols[..i].contains(symbol) {
+            return Err(AppError::BadRequest(format!(
+                "Symbol {} is listed more than once",
+                symbol
+            )));
+        }
+    }
+
+    let ccxt = CCXT::with_exchange(&request.exchange)?;
+    let precision = match &request.precision {
+        Some(precision) => {
+            precision.validate()?;
+            precision.clone()
+        }
+        None => ccxt.precision(&request.symbol)?,
+    };
+    for symbol in &symbols {
+        ccxt.precision(symbol)?;
+    }
+
+    Ok(BacktestConfig {
+        exchange: request.exchange.clone(),
+        symbol: request.symbol.clone(),
+        symbols,
+        timeframe: request.timeframe,
+        parameters: request.parameters.clone(),
+        start: request.start,
+        end: request.end,
+        initial_capital,
+        risk_free_rate,
+        fees: request.fees.clone(),
+        precision,
+        margin,
+        fill_model,
+        slippage_model,
+        liquidity: request.liquidity.clone(),
+    })
+}
+
 pub async fn create_task(
     State(state): State<AppState>,
     Json(request): Json<CreateBacktestTaskRequest>,
 ) -> ApiResult<CreateBacktestTaskResponse> {
-    let ccxt = CCXT::with_exchange(&request.exchange)?;
-    let precision = ccxt.precision(&request.symbol)?;
+    let config = backtest_config(&request)?;
 
     let now = Utc::now();
     let task = BacktestTask {
@@ -47,17 +178,19 @@ pub async fn create_task(
         status: BacktestStatus::Pending,
         progress: 0.0,
         name: request.name.clone(),
-        exchange: request.exchange.clone(),
-        symbol: request.symbol.clone(),
-        timeframe: request.timeframe,
-        precision,
+        config,
+        inputs: None,
+        rerun_of: None,
+        reproduction: None,
         statistic: None,
+        output: None,
         error_message: None,
         created_at: now,
         started_at: None,
         completed_at: None,
         updated_at: now,
         event_tx: Some(state.backtest_event_tx.clone()),
+        control: TaskControl::default(),
     };
     task.broadcast();
 
@@ -75,6 +208,8 @@ pub async fn create_task(
             state.strategy_manager,
             request.name,
             state.db_pool,
+            state.workers,
+            None,
         )
         .await;
     });
@@ -82,6 +217,148 @@ pub async fn create_task(
     Ok(Json(CreateBacktestTaskResponse { task_id }))
 }
 
+/// Replays a completed task with its recorded strategy build, candle range,
+/// fees and precisions as a new task.
+pub async fn rerun_task(
+    State(state): State<AppState>,
+    Path(task_id): Path<Uuid>,
+) -> ApiResult<CreateBacktestTaskResponse> {
+    let original = {
+        let backtest_tasks = state.backtest_tasks.read().await;
+        match backtest_tasks.get(&task_id) {
+            Some(task) => task.read().await.clone(),
+            None => {
+                return Err(AppError::NotFound(format!(
+                    "Task with id '{}' is not a Backtest task",
+                    task_id
+                )));
+            }
+        }
+    };
+    let Some(inputs) = &original.inputs else {
+        return Err(AppError::BadRequest(
+            "Task has no recorded inputs to rerun".into(),
+        ));
+    };
+    if original.status != BacktestStatus::Completed {
+        return Err(AppError::BadRequest(
+            "Only completed tasks can be rerun".into(),
+        ));
+    }
+
+    let now = Utc::now();
+    let task = BacktestTask {
+        id: Uuid::new_v4(),
+        status: BacktestStatus::Pending,
+        progress: 0.0,
+        name: original.name.clone(),
+        config: BacktestConfig {
+            start: Some(inputs.candles_start),
+            end: Some(inputs.candles_end),
+            ..original.config.clone()
+        },
+        inputs: None,
+        rerun_of: Some(original.id),
+        reproduction: None,
+        statistic: None,
+        output: None,
+        error_message: None,
+        created_at: now,
+        started_at: None,
+        completed_at: None,
+        updated_at: now,
+        event_tx: Some(state.backtest_event_tx.clone()),
+        control: TaskControl::default(),
+    };
+    task.broadcast();
+
+    let task_id = task.id;
+    let task = Arc::new(RwLock::new(task));
+
+    {
+        let mut tasks = state.backtest_tasks.write().await;
+        tasks.insert(task_id, task.clone());
+    }
+
+    tokio::spawn(async move {
+        let name = original.name.clone();
+        BacktestTask::run(
+            task,
+            state.strategy_manager,
+            name,
+            state.db_pool,
+            state.workers,
+            Some(original),
+        )
+        .await;
+    });
+
+    Ok(Json(CreateBacktestTaskResponse { task_id }))
+}
+
+/// Stops a pending, compiling, running or paused task.
+pub async fn cancel_task(
+    State(state): State<AppState>,
+    Path(task_id): Path<Uuid>,
+) -> ApiResult<()> {
+    let task = backtest_task(&state, task_id).await?;
+    let task = task.read().await;
+    match task.status {
+        BacktestStatus::Pending
+        | BacktestStatus::Compiling
+        | BacktestStatus::Running
+        | BacktestStatus::Paused => {
+            task.control.cancel();
+            Ok(Json(()))
+        }
+        _ => Err(AppError::BadRequest(
+            "Only unfinished tasks can be cancelled".into(),
+        )),
+    }
+}
+
+pub async fn pause_task(State(state): State<AppState>, Path(task_id): Path<Uuid>) -> ApiResult<()> {
+    let task = backtest_task(&state, task_id).await?;
+    let mut task = task.write().await;
+    if task.status != BacktestStatus::Running {
+        return Err(AppError::BadRequest(
+            "Only running tasks can be paused".into(),
+        ));
+    }
+
+    task.control.pause();
+    task.status = BacktestStatus::Paused;
+    task.updated_at = Utc::now();
+    task.broadcast();
+    Ok(Json(()))
+}
+
+pub async fn resume_task(
+    State(state): State<AppState>,
+    Path(task_id): Path<Uuid>,
+) -> ApiResult<()> {
+    let task = backtest_task(&state, task_id).await?;
+    let mut task = task.write().await;
+    if task.status != BacktestStatus::Paused {
+        return Err(AppError::BadRequest(
+            "Only paused tasks can be resumed".into(),
+        ));
+    }
+
+    task.control.resume();
+    task.status = BacktestStatus::Running;
+    task.updated_at = Utc::now();
+    task.broadcast();
+    Ok(Json(()))
+}
+
+async fn backtest_task(state: &AppState, task_id: Uuid) -> AppResult<Arc<RwLock<BacktestTask>>> {
+    let backtest_tasks = state.backtest_tasks.read().await;
+    backtest_tasks.get(&task_id).cloned().ok_or_else(|| {
+        AppError::NotFound(format!("Task with id '{}' is not a Backtest task", task_id))
+    })
+}
+
 pub async fn get_all_tasks(State(state): State<AppState>) -> ApiResult<Vec<BacktestTask>> {
     let mut tasks = Vec::new();
     let backtest_tasks = state.backtest_tasks.read().await;
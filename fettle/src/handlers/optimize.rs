@@ -0,0 +1,186 @@
+use crate::app::AppState;
+use crate::errors::{ApiResult, AppError, AppResult};
+use crate::handlers::backtest::{CreateBacktestTaskRequest, backtest_config};
+use crate::tasks::{
+    BacktestStatus, OptimizationMetric, OptimizationTask, ParameterRange, SearchMethod,
+};
+use axum::{
+    extract::{Path, State},
+    response::{
+        Json,
+        sse::{Event, KeepAlive, Sse},
+    },
+};
+use chrono::Utc;
+use futures::stream::Stream;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use ts_rs::TS;
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize, TS)]
+#[ts(export)]
+pub struct CreateOptimizationTaskRequest {
+    /// Settings shared by every run; `parameters` are kept fixed.
+    #[serde(flatten)]
+    pub backtest: CreateBacktestTaskRequest,
+    pub ranges: BTreeMap<String, ParameterRange>,
+    #[serde(default)]
+    #[ts(optional)]
+    pub search: Option<SearchMethod>,
+    #[serde(default)]
+    #[ts(optional)]
+    pub metric: Option<OptimizationMetric>,
+}
+
+#[derive(Debug, Serialize, TS)]
+#[ts(export)]
+pub struct CreateOptimizationTaskResponse {
+    pub task_id: Uuid,
+}
+
+/// Validates the ranges and fixes the seed of a random search.
+pub(crate) fn search_method(
+    ranges: &BTreeMap<String, ParameterRange>,
+    search: Option<SearchMethod>,
+) -> AppResult<SearchMethod> {
+    if ranges.is_empty() {
+        return Err(AppError::BadRequest(
+            "At least one parameter range is required".into(),
+        ));
+    }
+    for (name, range) in ranges {
+        range.validate(name)?;
+    }
+
+    match search.unwrap_or_default() {
+        SearchMethod::Random { samples: 0, .. } => Err(AppError::BadRequest(
+            "Random search needs at least one sample".into(),
+        )),
+        SearchMethod::Random { samples, seed } => Ok(SearchMethod::Random {
+            samples,
+            seed: Some(seed.unwrap_or_else(rand::random)),
+        }),
+        SearchMethod::Grid => Ok(SearchMethod::Grid),
+    }
+}
+
+pub async fn create_task(
+    State(state): State<AppState>,
+    Json(request): Json<CreateOptimizationTaskRequest>,
+) -> ApiResult<CreateOptimizationTaskResponse> {
+    let config = backtest_config(&request.backtest)?;
+    let search = search_method(&request.ranges, request.search)?;
+
+    let now = Utc::now();
+    let mut task = OptimizationTask {
+        id: Uuid::new_v4(),
+        status: BacktestStatus::Pending,
+        progress: 0.0,
+        name: request.backtest.name.clone(),
+        config,
+        ranges: request.ranges,
+        search,
+        metric: request.metric.unwrap_or_default(),
+        total_runs: 0,
+        results: Vec::new(),
+        error_message: None,
+        created_at: now,
+        started_at: None,
+        completed_at: None,
+        updated_at: now,
+        event_tx: Some(state.optimization_event_tx.clone()),
+    };
+    task.total_runs = task.combinations()?.len();
+    task.broadcast();
+
+    let task_id = task.id;
+    let task = Arc::new(RwLock::new(task));
+
+    {
+        let mut tasks = state.optimization_tasks.write().await;
+        tasks.insert(task_id, task.clone());
+    }
+
+    tokio::spawn(async move {
+        OptimizationTask::run(task, state.strategy_manager, state.db_pool, state.workers).await;
+    });
+
+    Ok(Json(CreateOptimizationTaskResponse { task_id }))
+}
+
+pub async fn get_all_tasks(State(state): State<AppState>) -> ApiResult<Vec<OptimizationTask>> {
+    let mut tasks = Vec::new();
+    let optimization_tasks = state.optimization_tasks.read().await;
+    for task in optimization_tasks.values() {
+        let task = task.read().await;
+        tasks.push(task.clone());
+    }
+
+    Ok(Json(tasks))
+}
+
+pub async fn get_task(
+    State(state): State<AppState>,
+    Path(task_id): Path<Uuid>,
+) -> ApiResult<OptimizationTask> {
+    let optimization_tasks = state.optimization_tasks.read().await;
+    let task = optimization_tasks.get(&task_id);
+
+    match task {
+        Some(task) => {
+            let task = task.read().await;
+            Ok(Json(task.clone()))
+        }
+        _ => Err(AppError::NotFound(format!(
+            "Task with id '{}' is not an Optimization task",
+            task_id
+        ))),
+    }
+}
+
+pub async fn stream_tasks(
+    State(state): State<AppState>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let mut rx = state.optimization_event_tx.subscribe();
+    let mut initial_events = Vec::new();
+    {
+        let optimization_tasks = state.optimization_tasks.read().await;
+        for task in optimization_tasks.values() {
+            let task = task.read().await;
+            if let Ok(data) = serde_json::to_string(&*task) {
+                initial_events.push(data);
+            }
+        }
+    }
+
+    let stream = async_stream::stream! {
+        for data in initial_events {
+            yield Ok(Event::default().data(data));
+        }
+
+        loop {
+            tokio::select! {
+                _ = state.shutdown_token.cancelled() => {
+                    break;
+                }
+                result = rx.recv() => {
+                    let Ok(task) = result else {
+                        break;
+                    };
+
+                    let Ok(data) = serde_json::to_string(&task) else {
+                        continue;
+                    };
+
+                    yield Ok(Event::default().data(data));
+                }
+            }
+        }
+    };
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
@@ -1,5 +1,8 @@
 use crate::{app::AppState, errors::ApiResult};
-use axum::{Json, extract::State};
+use axum::{
+    Json,
+    extract::{Query, State},
+};
 use serde::{Deserialize, Serialize};
 use std::fs;
 use toml_edit::DocumentMut;
@@ -21,6 +24,34 @@ pub async fn add_strategy(
     Ok(Json(()))
 }
 
+#[derive(Debug, Clone, Deserialize, TS)]
+#[ts(export)]
+pub struct GetParametersQuery {
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize, TS)]
+#[ts(export)]
+pub struct GetParametersResponse {
+    /// JSON schema of the strategy's parameters; absent when it takes none.
+    #[ts(optional)]
+    pub schema: Option<serde_json::Value>,
+}
+
+/// Builds the strategy to read the parameter schema it exports.
+pub async fn get_parameters(
+    State(state): State<AppState>,
+    Query(query): Query<GetParametersQuery>,
+) -> ApiResult<GetParametersResponse> {
+    let strategy_handle = state
+        .strategy_manager
+        .load_strategy(&query.name, None)
+        .await?;
+    let schema = strategy_handle.parameters_schema().cloned();
+
+    Ok(Json(GetParametersResponse { schema }))
+}
+
 #[derive(Debug, Clone, Serialize, TS)]
 #[ts(export)]
 pub struct ListStrategiesResponse {
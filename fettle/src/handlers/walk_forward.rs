@@ -0,0 +1,170 @@
+use crate::app::AppState;
+use crate::errors::{ApiResult, AppError};
+use crate::handlers::backtest::{CreateBacktestTaskRequest, backtest_config};
+use crate::handlers::optimize::search_method;
+use crate::tasks::{
+    BacktestStatus, OptimizationMetric, ParameterRange, SearchMethod, WalkForwardTask,
+};
+use axum::{
+    extract::{Path, State},
+    response::{
+        Json,
+        sse::{Event, KeepAlive, Sse},
+    },
+};
+use chrono::Utc;
+use futures::stream::Stream;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use ts_rs::TS;
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize, TS)]
+#[ts(export)]
+pub struct CreateWalkForwardTaskRequest {
+    /// Settings shared by every run; `parameters` are kept fixed.
+    #[serde(flatten)]
+    pub backtest: CreateBacktestTaskRequest,
+    pub ranges: BTreeMap<String, ParameterRange>,
+    #[serde(default)]
+    #[ts(optional)]
+    pub search: Option<SearchMethod>,
+    #[serde(default)]
+    #[ts(optional)]
+    pub metric: Option<OptimizationMetric>,
+    pub in_sample_candles: usize,
+    pub out_of_sample_candles: usize,
+}
+
+#[derive(Debug, Serialize, TS)]
+#[ts(export)]
+pub struct CreateWalkForwardTaskResponse {
+    pub task_id: Uuid,
+}
+
+pub async fn create_task(
+    State(state): State<AppState>,
+    Json(request): Json<CreateWalkForwardTaskRequest>,
+) -> ApiResult<CreateWalkForwardTaskResponse> {
+    let config = backtest_config(&request.backtest)?;
+    let search = search_method(&request.ranges, request.search)?;
+    if request.in_sample_candles == 0 || request.out_of_sample_candles == 0 {
+        return Err(AppError::BadRequest(
+            "In-sample and out-of-sample windows must not be empty".into(),
+        ));
+    }
+
+    let now = Utc::now();
+    let task = WalkForwardTask {
+        id: Uuid::new_v4(),
+        status: BacktestStatus::Pending,
+        progress: 0.0,
+        name: request.backtest.name.clone(),
+        config,
+        ranges: request.ranges,
+        search,
+        metric: request.metric.unwrap_or_default(),
+        in_sample_candles: request.in_sample_candles,
+        out_of_sample_candles: request.out_of_sample_candles,
+        windows: Vec::new(),
+        equity_curve: Vec::new(),
+        error_message: None,
+        created_at: now,
+        started_at: None,
+        completed_at: None,
+        updated_at: now,
+        event_tx: Some(state.walk_forward_event_tx.clone()),
+    };
+    task.combinations()?;
+    task.broadcast();
+
+    let task_id = task.id;
+    let task = Arc::new(RwLock::new(task));
+
+    {
+        let mut tasks = state.walk_forward_tasks.write().await;
+        tasks.insert(task_id, task.clone());
+    }
+
+    tokio::spawn(async move {
+        WalkForwardTask::run(task, state.strategy_manager, state.db_pool, state.workers).await;
+    });
+
+    Ok(Json(CreateWalkForwardTaskResponse { task_id }))
+}
+
+pub async fn get_all_tasks(State(state): State<AppState>) -> ApiResult<Vec<WalkForwardTask>> {
+    let mut tasks = Vec::new();
+    let walk_forward_tasks = state.walk_forward_tasks.read().await;
+    for task in walk_forward_tasks.values() {
+        let task = task.read().await;
+        tasks.push(task.clone());
+    }
+
+    Ok(Json(tasks))
+}
+
+pub async fn get_task(
+    State(state): State<AppState>,
+    Path(task_id): Path<Uuid>,
+) -> ApiResult<WalkForwardTask> {
+    let walk_forward_tasks = state.walk_forward_tasks.read().await;
+    let task = walk_forward_tasks.get(&task_id);
+
+    match task {
+        Some(task) => {
+            let task = task.read().await;
+            Ok(Json(task.clone()))
+        }
+        _ => Err(AppError::NotFound(format!(
+            "Task with id '{}' is not a Walk-forward task",
+            task_id
+        ))),
+    }
+}
+
+pub async fn stream_tasks(
+    State(state): State<AppState>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let mut rx = state.walk_forward_event_tx.subscribe();
+    let mut initial_events = Vec::new();
+    {
+        let walk_forward_tasks = state.walk_forward_tasks.read().await;
+        for task in walk_forward_tasks.values() {
+            let task = task.read().await;
+            if let Ok(data) = serde_json::to_string(&*task) {
+                initial_events.push(data);
+            }
+        }
+    }
+
+    let stream = async_stream::stream! {
+        for data in initial_events {
+            yield Ok(Event::default().data(data));
+        }
+
+        loop {
+            tokio::select! {
+                _ = state.shutdown_token.cancelled() => {
+                    break;
+                }
+                result = rx.recv() => {
+                    let Ok(task) = result else {
+                        break;
+                    };
+
+                    let Ok(data) = serde_json::to_string(&task) else {
+                        continue;
+                    };
+
+                    yield Ok(Event::default().data(data));
+                }
+            }
+        }
+    };
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
@@ -17,7 +17,10 @@ pub mod tasks;
 #[doc(hidden)]
 pub mod utils;
 
-pub use crate::errors::AppResult;
+pub use crate::errors::{AppError, AppResult};
 pub use crate::models::{Candle, MarketPrecision, Timeframe, TradingFees};
-pub use crate::strategy::{Order, OrderType, Strategy, StrategyContext, Trade, TradeType};
+pub use crate::strategy::{
+    Bracket, FillModel, LiquidityConfig, LogLevel, MarginConfig, Order, OrderType, SlippageModel,
+    Strategy, StrategyContext, SymbolContext, Trade, TradeType,
+};
 pub use strategy_macro::strategy;
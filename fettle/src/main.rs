@@ -2,10 +2,14 @@ mod config;
 
 use fettle::app::create_app;
 use fettle::errors::{AppError, AppResult};
+use fettle::strategy::runner::RUNNER_BINARY_NAME;
+use fettle::tasks::{TimeBudget, WorkerPool};
 use sqlx::postgres::PgPoolOptions;
 use std::{
+    env,
     net::{Ipv4Addr, SocketAddrV4},
     str::FromStr,
+    time::Duration,
 };
 use tokio_util::sync::CancellationToken;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
@@ -33,8 +37,34 @@ async fn main() -> AppResult<()> {
 
     sqlx::migrate!("./migrations").run(&db_pool).await?;
 
+    let simulation = &config.simulation;
+    let workers = simulation
+        .workers
+        .map(WorkerPool::new)
+        .unwrap_or_default()
+        .with_budget(TimeBudget {
+            call: simulation.call_timeout_ms.map(Duration::from_millis),
+            total: simulation.total_timeout_secs.map(Duration::from_secs),
+        });
+    tracing::info!("Simulating up to {} backtests at once", workers.size());
+
+    let strategy_runner = if simulation.isolate_strategies {
+        let runner = match &simulation.runner_path {
+            Some(runner) => runner.clone(),
+            None => std::env::current_exe()?.with_file_name(format!(
+                "{}{}",
+                RUNNER_BINARY_NAME,
+                env::consts::EXE_SUFFIX
+            )),
+        };
+        tracing::info!("Running strategies in {}", runner.display());
+        Some(runner)
+    } else {
+        None
+    };
+
     let token = CancellationToken::new();
-    let app = create_app(db_pool, token.clone()).await?;
+    let app = create_app(db_pool, workers, strategy_runner, token.clone()).await?;
 
     let Ok(host) = Ipv4Addr::from_str(&config.server.host) else {
         return Err(AppError::Internal(format!(
@@ -7,7 +7,7 @@ use sqlx::{FromRow, Type};
 use std::{hash::Hash, str::FromStr};
 use ts_rs::TS;
 
-#[derive(Debug, Clone, Serialize, FromRow, TS)]
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, TS)]
 #[ts(export)]
 pub struct Candle {
     #[serde(with = "ts_milliseconds")]
@@ -126,6 +126,10 @@ impl Timeframe {
     pub fn to_ms(&self) -> u64 {
         self.to_delta().num_milliseconds() as u64
     }
+    /// Candles in a 365-day year; markets are assumed to trade around the clock.
+    pub fn periods_per_year(&self) -> f64 {
+        TimeDelta::days(365).num_milliseconds() as f64 / self.to_ms() as f64
+    }
     pub fn to_delta(&self) -> TimeDelta {
         match self {
             Timeframe::S1 => TimeDelta::seconds(1),
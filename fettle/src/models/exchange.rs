@@ -1,13 +1,38 @@
-use bigdecimal::{BigDecimal, RoundingMode, Zero};
+use crate::errors::{AppError, AppResult};
+use bigdecimal::{BigDecimal, One, RoundingMode, Zero};
 use serde::{Deserialize, Serialize};
 use ts_rs::TS;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
 pub struct TradingFees {
+    #[ts(type = "string")]
     pub maker: BigDecimal,
+    #[ts(type = "string")]
     pub taker: BigDecimal,
 }
 
+impl TradingFees {
+    /// Fee rates are fractions of notional. A negative maker fee is a rebate.
+    pub fn validate(&self) -> AppResult<()> {
+        if self.taker < BigDecimal::zero() || self.taker >= BigDecimal::one() {
+            return Err(AppError::BadRequest(format!(
+                "Taker fee must be between 0 and 1, got {}",
+                self.taker
+            )));
+        }
+
+        if self.maker <= -BigDecimal::one() || self.maker >= BigDecimal::one() {
+            return Err(AppError::BadRequest(format!(
+                "Maker fee must be between -1 and 1, got {}",
+                self.maker
+            )));
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, TS)]
 #[ts(export)]
 pub struct MarketPrecision {
@@ -18,6 +43,18 @@ pub struct MarketPrecision {
 }
 
 impl MarketPrecision {
+    /// Steps must not be negative; a zero step disables rounding.
+    pub fn validate(&self) -> AppResult<()> {
+        if self.price_precision < BigDecimal::zero() || self.amount_precision < BigDecimal::zero() {
+            return Err(AppError::BadRequest(format!(
+                "Precision must not be negative, got price {} and amount {}",
+                self.price_precision, self.amount_precision
+            )));
+        }
+
+        Ok(())
+    }
+
     pub fn round_price(&self, value: &BigDecimal, mode: RoundingMode) -> BigDecimal {
         if self.price_precision.is_zero() {
             return value.clone();
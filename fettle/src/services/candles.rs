@@ -1,7 +1,11 @@
 use crate::errors::AppResult;
 use crate::models::{Candle, Timeframe, AvailableCandleInfo};
 use chrono::{DateTime, Utc};
-use sqlx::PgPool;
+use sqlx::{PgPool, Postgres, QueryBuilder};
+
+const CANDLE_COLUMNS: &str =
+    "timestamp, exchange, symbol, timeframe, open, high, low, close, volume";
+const CANDLE_PAGE_SIZE: i64 = 10000;
 
 pub async fn insert_candles(pool: &PgPool, candles: &[Candle]) -> AppResult<()> {
     if candles.is_empty() {
@@ -37,20 +41,35 @@ pub async fn insert_candles(pool: &PgPool, candles: &[Candle]) -> AppResult<()>
     Ok(())
 }
 
-pub async fn get_candles(
-    pool: &PgPool,
-    exchange: &str,
-    symbol: &str,
+/// Selects the candles of one series, optionally within `[start, end]`.
+fn candles_query<'a>(
+    columns: &str,
+    exchange: &'a str,
+    symbol: &'a str,
     timeframe: Timeframe,
     start: Option<DateTime<Utc>>,
     end: Option<DateTime<Utc>>,
-) -> AppResult<Vec<Candle>> {
-    let mut query_builder = sqlx::QueryBuilder::new(
-        "SELECT timestamp, exchange, symbol, timeframe, open, high, low, close, volume
+) -> QueryBuilder<'a, Postgres> {
+    let mut query_builder = QueryBuilder::new(format!(
+        "SELECT {}
            FROM candles
            WHERE exchange = ",
-    );
+        columns
+    ));
+
+    push_series_filter(&mut query_builder, exchange, symbol, timeframe, start, end);
+    query_builder
+}
 
+/// Pushes the conditions after `WHERE exchange = ` selecting one series.
+fn push_series_filter<'a>(
+    query_builder: &mut QueryBuilder<'a, Postgres>,
+    exchange: &'a str,
+    symbol: &'a str,
+    timeframe: Timeframe,
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+) {
     query_builder.push_bind(exchange);
     query_builder.push(" AND symbol = ");
     query_builder.push_bind(symbol);
@@ -66,7 +85,17 @@ pub async fn get_candles(
         query_builder.push(" AND timestamp <= ");
         query_builder.push_bind(e);
     }
+}
 
+pub async fn get_candles(
+    pool: &PgPool,
+    exchange: &str,
+    symbol: &str,
+    timeframe: Timeframe,
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+) -> AppResult<Vec<Candle>> {
+    let mut query_builder = candles_query(CANDLE_COLUMNS, exchange, symbol, timeframe, start, end);
     query_builder.push(" ORDER BY timestamp ASC");
 
     let candles = query_builder
@@ -77,24 +106,127 @@ pub async fn get_candles(
     Ok(candles)
 }
 
-pub async fn get_latest_candle(
+/// How much of a range one stored series covers.
+#[derive(Debug, Clone)]
+pub struct CandleCoverage {
+    pub count: i64,
+    pub first: DateTime<Utc>,
+    pub last: DateTime<Utc>,
+    /// Widest gap between the timestamps of consecutive candles, in
+    /// milliseconds; zero for a single candle.
+    pub widest_gap_ms: i64,
+}
+
+/// Coverage of the stored candles of one series within `[start, end]`, or
+/// `None` if there are none.
+pub async fn get_candle_coverage(
     pool: &PgPool,
     exchange: &str,
     symbol: &str,
     timeframe: Timeframe,
-) -> AppResult<Option<Candle>> {
-    let mut query_builder = sqlx::QueryBuilder::new(
-        "SELECT timestamp, exchange, symbol, timeframe, open, high, low, close, volume
-           FROM candles
-           WHERE exchange = ",
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+) -> AppResult<Option<CandleCoverage>> {
+    let mut query_builder = QueryBuilder::new(
+        "SELECT COUNT(*), MIN(timestamp), MAX(timestamp), COALESCE(MAX(gap_ms), 0)
+           FROM (
+             SELECT timestamp,
+                    (EXTRACT(EPOCH FROM timestamp - LAG(timestamp) OVER (ORDER BY timestamp))
+                      * 1000)::BIGINT AS gap_ms
+               FROM candles
+               WHERE exchange = ",
     );
+    push_series_filter(&mut query_builder, exchange, symbol, timeframe, start, end);
+    query_builder.push(") AS series");
 
-    query_builder.push_bind(exchange);
-    query_builder.push(" AND symbol = ");
-    query_builder.push_bind(symbol);
-    query_builder.push(" AND timeframe = ");
-    query_builder.push_bind(timeframe);
+    let (count, first, last, widest_gap_ms): (
+        i64,
+        Option<DateTime<Utc>>,
+        Option<DateTime<Utc>>,
+        i64,
+    ) = query_builder.build_query_as().fetch_one(pool).await?;
+
+    Ok(first.zip(last).map(|(first, last)| CandleCoverage {
+        count,
+        first,
+        last,
+        widest_gap_ms,
+    }))
+}
+
+/// Reads the candles of one series in timestamp order, a page at a time, so
+/// long ranges never have to be held in memory at once.
+#[derive(Debug)]
+pub struct CandleCursor {
+    pool: PgPool,
+    exchange: String,
+    symbol: String,
+    timeframe: Timeframe,
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+    /// Timestamp of the last candle read.
+    after: Option<DateTime<Utc>>,
+    page_size: i64,
+}
 
+impl CandleCursor {
+    pub fn new(
+        pool: PgPool,
+        exchange: &str,
+        symbol: &str,
+        timeframe: Timeframe,
+        start: Option<DateTime<Utc>>,
+        end: Option<DateTime<Utc>>,
+    ) -> Self {
+        Self {
+            pool,
+            exchange: exchange.to_string(),
+            symbol: symbol.to_string(),
+            timeframe,
+            start,
+            end,
+            after: None,
+            page_size: CANDLE_PAGE_SIZE,
+        }
+    }
+
+    /// The next candles after those already read; empty once the range is exhausted.
+    pub async fn next_page(&mut self) -> AppResult<Vec<Candle>> {
+        let mut query_builder = candles_query(
+            CANDLE_COLUMNS,
+            &self.exchange,
+            &self.symbol,
+            self.timeframe,
+            self.start,
+            self.end,
+        );
+        if let Some(after) = self.after {
+            query_builder.push(" AND timestamp > ");
+            query_builder.push_bind(after);
+        }
+        query_builder.push(" ORDER BY timestamp ASC LIMIT ");
+        query_builder.push_bind(self.page_size);
+
+        let candles = query_builder
+            .build_query_as::<Candle>()
+            .fetch_all(&self.pool)
+            .await?;
+
+        if let Some(last) = candles.last() {
+            self.after = Some(last.timestamp);
+        }
+
+        Ok(candles)
+    }
+}
+
+pub async fn get_latest_candle(
+    pool: &PgPool,
+    exchange: &str,
+    symbol: &str,
+    timeframe: Timeframe,
+) -> AppResult<Option<Candle>> {
+    let mut query_builder = candles_query(CANDLE_COLUMNS, exchange, symbol, timeframe, None, None);
     query_builder.push(" ORDER BY timestamp DESC");
     query_builder.push(" LIMIT 1");
 
@@ -127,3 +259,132 @@ pub async fn get_available_candles(pool: &PgPool) -> AppResult<Vec<AvailableCand
 
     Ok(result)
 }
+
+/// Aggregates candles into `timeframe` buckets aligned to the Unix epoch.
+/// Buckets are timestamped at their start.
+pub fn resample_candles<'a>(
+    candles: impl IntoIterator<Item = &'a Candle>,
+    timeframe: Timeframe,
+) -> Vec<Candle> {
+    let mut resampler = Resampler::new(timeframe);
+    let mut resampled: Vec<Candle> = candles
+        .into_iter()
+        .filter_map(|c| resampler.push(c))
+        .collect();
+    resampled.extend(resampler.finish());
+    resampled
+}
+
+/// Resamples candles as they arrive; see [`resample_candles`].
+#[derive(Debug, Clone)]
+pub struct Resampler {
+    timeframe: Timeframe,
+    bucket: Option<Candle>,
+}
+
+impl Resampler {
+    pub fn new(timeframe: Timeframe) -> Self {
+        Self {
+            timeframe,
+            bucket: None,
+        }
+    }
+
+    /// Adds `candle` to its bucket and returns the previous bucket if
+    /// `candle` starts a new one.
+    pub fn push(&mut self, candle: &Candle) -> Option<Candle> {
+        let bucket_ms = self.timeframe.to_ms() as i64;
+        let millis = candle.timestamp.timestamp_millis();
+        let start = millis - millis.rem_euclid(bucket_ms);
+
+        match &mut self.bucket {
+            Some(bucket) if bucket.timestamp.timestamp_millis() == start => {
+                if candle.high > bucket.high {
+                    bucket.high = candle.high.clone();
+                }
+                if candle.low < bucket.low {
+                    bucket.low = candle.low.clone();
+                }
+                bucket.close = candle.close.clone();
+                bucket.volume += &candle.volume;
+                None
+            }
+            _ => {
+                let timestamp = DateTime::from_timestamp_millis(start)?;
+                self.bucket.replace(Candle {
+                    timestamp,
+                    timeframe: self.timeframe,
+                    ..candle.clone()
+                })
+            }
+        }
+    }
+
+    /// Takes the current bucket if it ends by `time`.
+    pub fn close_by(&mut self, time: DateTime<Utc>) -> Option<Candle> {
+        self.bucket
+            .take_if(|bucket| bucket.timestamp + self.timeframe.to_delta() <= time)
+    }
+
+    /// The last bucket, complete or not.
+    pub fn finish(self) -> Option<Candle> {
+        self.bucket
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bigdecimal::BigDecimal;
+
+    fn candle(minute: i64, open: i64, high: i64, low: i64, close: i64) -> Candle {
+        Candle {
+            timestamp: DateTime::from_timestamp(minute * 60, 0).unwrap(),
+            exchange: "test".into(),
+            symbol: "BTC/USDT".into(),
+            timeframe: Timeframe::M1,
+            open: BigDecimal::from(open),
+            high: BigDecimal::from(high),
+            low: BigDecimal::from(low),
+            close: BigDecimal::from(close),
+            volume: BigDecimal::from(10),
+        }
+    }
+
+    #[test]
+    fn resample_candles_aggregates_aligned_buckets() {
+        let candles = [
+            candle(4, 10, 12, 9, 11),
+            candle(5, 11, 15, 10, 14),
+            candle(6, 14, 14, 8, 9),
+            candle(10, 9, 10, 7, 8),
+        ];
+
+        let resampled = resample_candles(&candles, Timeframe::M5);
+        assert_eq!(resampled.len(), 3);
+
+        let bucket = &resampled[1];
+        assert_eq!(bucket.timestamp, DateTime::from_timestamp(300, 0).unwrap());
+        assert_eq!(bucket.timeframe, Timeframe::M5);
+        assert_eq!(bucket.open, BigDecimal::from(11));
+        assert_eq!(bucket.high, BigDecimal::from(15));
+        assert_eq!(bucket.low, BigDecimal::from(8));
+        assert_eq!(bucket.close, BigDecimal::from(9));
+        assert_eq!(bucket.volume, BigDecimal::from(20));
+    }
+
+    #[test]
+    fn resampler_closes_a_bucket_once_it_ends() {
+        let mut resampler = Resampler::new(Timeframe::M5);
+        assert!(resampler.push(&candle(3, 10, 12, 9, 11)).is_none());
+        assert!(resampler.push(&candle(4, 11, 13, 10, 12)).is_none());
+
+        let minute = |m: i64| DateTime::from_timestamp(m * 60, 0).unwrap();
+        assert!(resampler.close_by(minute(4)).is_none());
+        let bucket = resampler.close_by(minute(5)).unwrap();
+        assert_eq!(bucket.timestamp, minute(0));
+        assert_eq!(bucket.high, BigDecimal::from(13));
+        assert_eq!(bucket.close, BigDecimal::from(12));
+        assert!(resampler.finish().is_none());
+    }
+}
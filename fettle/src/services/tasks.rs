@@ -1,5 +1,5 @@
 use crate::errors::AppResult;
-use crate::tasks::{BacktestTask, FetchCandlesTask};
+use crate::tasks::{BacktestTask, FetchCandlesTask, OptimizationTask, WalkForwardTask};
 use sqlx::PgPool;
 
 pub async fn save_fetch_candles_task(pool: &PgPool, task: &FetchCandlesTask) -> AppResult<()> {
@@ -85,3 +85,87 @@ pub async fn load_backtest_tasks(pool: &PgPool) -> AppResult<Vec<BacktestTask>>
 
     Ok(tasks)
 }
+
+pub async fn save_optimization_task(pool: &PgPool, task: &OptimizationTask) -> AppResult<()> {
+    let data = serde_json::to_value(task)?;
+    let completed_at = task.completed_at.ok_or("Task not completed yet")?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO optimization_tasks (id, data, completed_at)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (id) DO UPDATE SET
+            data = EXCLUDED.data,
+            completed_at = EXCLUDED.completed_at
+        "#,
+        task.id,
+        data,
+        completed_at
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn load_optimization_tasks(pool: &PgPool) -> AppResult<Vec<OptimizationTask>> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT data
+        FROM optimization_tasks
+        ORDER BY completed_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut tasks = Vec::new();
+    for row in rows {
+        let task = serde_json::from_value(row.data)?;
+        tasks.push(task);
+    }
+
+    Ok(tasks)
+}
+
+pub async fn save_walk_forward_task(pool: &PgPool, task: &WalkForwardTask) -> AppResult<()> {
+    let data = serde_json::to_value(task)?;
+    let completed_at = task.completed_at.ok_or("Task not completed yet")?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO walk_forward_tasks (id, data, completed_at)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (id) DO UPDATE SET
+            data = EXCLUDED.data,
+            completed_at = EXCLUDED.completed_at
+        "#,
+        task.id,
+        data,
+        completed_at
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn load_walk_forward_tasks(pool: &PgPool) -> AppResult<Vec<WalkForwardTask>> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT data
+        FROM walk_forward_tasks
+        ORDER BY completed_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut tasks = Vec::new();
+    for row in rows {
+        let task = serde_json::from_value(row.data)?;
+        tasks.push(task);
+    }
+
+    Ok(tasks)
+}
@@ -1,12 +1,84 @@
 mod context;
+mod fill;
 mod handle;
 mod manager;
+mod output;
+pub mod plugin;
+#[cfg(unix)]
+pub mod runner;
+mod slippage;
 
 use crate::errors::AppResult;
-pub use context::{Order, OrderType, StrategyContext, Trade, TradeType};
-pub use handle::StrategyHandle;
-pub use manager::{STRATEGY_WORKDIR_NAME, StrategyManager};
+use crate::models::Timeframe;
+pub(crate) use context::{CandleWindow, Market};
+#[cfg(test)]
+pub(crate) use handle::Clock;
+pub use context::{
+    Bracket, LiquidityConfig, MarginConfig, Order, OrderType, StrategyContext, SymbolContext,
+    Trade, TradeType,
+};
+pub use fill::FillModel;
+pub use handle::{StrategyHandle, StrategyWatch};
+pub use manager::{STRATEGY_WORKDIR_NAME, StrategyBuild, StrategyManager};
+pub use output::{LogEntry, LogLevel, PlotPoint, PlotSeries, StrategyOutput};
+pub use slippage::SlippageModel;
+
+/// Candles of each series a strategy sees unless it overrides
+/// [`Strategy::lookback`].
+pub const DEFAULT_LOOKBACK: usize = 10_000;
 
 pub trait Strategy: Send {
+    /// Extra timeframes the strategy reads through [`StrategyContext::candles_for`].
+    fn timeframes(&self) -> Vec<Timeframe> {
+        Vec::new()
+    }
+
+    /// Most candles of each series the strategy reads at once. Backtests keep
+    /// only that many of the latest candles, [`DEFAULT_LOOKBACK`] unless
+    /// overridden; `None` keeps every candle.
+    fn lookback(&self) -> Option<usize> {
+        Some(DEFAULT_LOOKBACK)
+    }
+
+    /// Checks the parameters the strategy was built with. A strategy that
+    /// fails it is rejected when it is loaded, with the returned error.
+    fn validate(&self) -> AppResult<()> {
+        Ok(())
+    }
+
+    /// Called once before the first candle. Symbols, balance, fees and
+    /// precisions are available, candles are not.
+    fn on_start(&mut self, _context: &StrategyContext) -> AppResult<()> {
+        Ok(())
+    }
+
     fn tick(&mut self, context: &mut StrategyContext) -> AppResult<()>;
+
+    /// Called when a resting order fills, before the candle's tick, with the
+    /// part of the order that filled and the trade it produced.
+    fn on_order_filled(
+        &mut self,
+        _context: &mut StrategyContext,
+        _order: &Order,
+        _trade: &Trade,
+    ) -> AppResult<()> {
+        Ok(())
+    }
+
+    /// Called when the backtest cancels a resting order: a one-cancels-other
+    /// sibling, the exits of a cancelled entry, an order the margin could not
+    /// cover, or an order still open at the end. Orders the strategy cancels
+    /// itself are not reported.
+    fn on_order_cancelled(
+        &mut self,
+        _context: &mut StrategyContext,
+        _order: &Order,
+    ) -> AppResult<()> {
+        Ok(())
+    }
+
+    /// Called once after the last candle, when every order has been cancelled.
+    fn on_end(&mut self, _context: &StrategyContext) -> AppResult<()> {
+        Ok(())
+    }
 }
@@ -1,8 +1,14 @@
+use super::Strategy;
+use super::fill::{self, FillModel, fall, rise};
+use super::output::{LogEntry, LogLevel, PlotPoint, StrategyOutput};
+use super::slippage::SlippageModel;
 use crate::errors::{AppError, AppResult};
-use crate::models::{Candle, MarketPrecision, TradingFees};
-use bigdecimal::{BigDecimal, RoundingMode, Zero};
+use crate::models::{Candle, MarketPrecision, Timeframe, TradingFees};
+use bigdecimal::{BigDecimal, One, RoundingMode, Zero};
 use chrono::{DateTime, Utc, serde::ts_milliseconds};
 use serde::{Deserialize, Serialize};
+use std::mem;
+use std::ops::{Deref, DerefMut};
 use ts_rs::TS;
 use uuid::Uuid;
 
@@ -14,6 +20,31 @@ pub enum TradeType {
     MarketSell,
     LimitBuy,
     LimitSell,
+    StopMarketBuy,
+    StopMarketSell,
+    StopLimitBuy,
+    StopLimitSell,
+    TakeProfitBuy,
+    TakeProfitSell,
+    TrailingStopBuy,
+    TrailingStopSell,
+    LiquidationBuy,
+    LiquidationSell,
+}
+
+impl TradeType {
+    pub fn is_buy(&self) -> bool {
+        matches!(
+            self,
+            TradeType::MarketBuy
+                | TradeType::LimitBuy
+                | TradeType::StopMarketBuy
+                | TradeType::StopLimitBuy
+                | TradeType::TakeProfitBuy
+                | TradeType::TrailingStopBuy
+                | TradeType::LiquidationBuy
+        )
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, TS)]
@@ -22,6 +53,8 @@ pub struct Trade {
     #[serde(with = "ts_milliseconds")]
     #[ts(type = "number")]
     pub timestamp: DateTime<Utc>,
+    #[serde(default)]
+    pub symbol: String,
     pub trade_type: TradeType,
     #[ts(type = "string")]
     pub price: BigDecimal,
@@ -29,6 +62,18 @@ pub struct Trade {
     pub amount: BigDecimal,
     #[ts(type = "string")]
     pub fee: BigDecimal,
+    /// Per-unit price move against the trade caused by slippage.
+    #[serde(default)]
+    #[ts(type = "string")]
+    pub slippage: BigDecimal,
+    /// Slippage model the trade was filled under; `none` for maker fills.
+    #[serde(default)]
+    pub slippage_model: SlippageModel,
+    /// Resting order the trade executed, if any. A partially filled order
+    /// produces one trade per execution.
+    #[serde(default)]
+    #[ts(optional)]
+    pub order_id: Option<Uuid>,
     #[ts(optional, type = "string")]
     pub profit: Option<BigDecimal>,
 }
@@ -39,109 +84,600 @@ pub struct Trade {
 pub enum OrderType {
     LimitBuy,
     LimitSell,
+    StopMarketBuy,
+    StopMarketSell,
+    StopLimitBuy,
+    StopLimitSell,
+    TakeProfitBuy,
+    TakeProfitSell,
+    TrailingStopBuy,
+    TrailingStopSell,
 }
 
-#[derive(Debug, Clone)]
+impl OrderType {
+    pub fn is_buy(&self) -> bool {
+        matches!(
+            self,
+            OrderType::LimitBuy
+                | OrderType::StopMarketBuy
+                | OrderType::StopLimitBuy
+                | OrderType::TakeProfitBuy
+                | OrderType::TrailingStopBuy
+        )
+    }
+
+    /// Whether the order fills as a market order once triggered.
+    pub fn is_taker(&self) -> bool {
+        !matches!(
+            self,
+            OrderType::LimitBuy
+                | OrderType::LimitSell
+                | OrderType::StopLimitBuy
+                | OrderType::StopLimitSell
+        )
+    }
+
+    pub fn trade_type(&self) -> TradeType {
+        match self {
+            OrderType::LimitBuy => TradeType::LimitBuy,
+            OrderType::LimitSell => TradeType::LimitSell,
+            OrderType::StopMarketBuy => TradeType::StopMarketBuy,
+            OrderType::StopMarketSell => TradeType::StopMarketSell,
+            OrderType::StopLimitBuy => TradeType::StopLimitBuy,
+            OrderType::StopLimitSell => TradeType::StopLimitSell,
+            OrderType::TakeProfitBuy => TradeType::TakeProfitBuy,
+            OrderType::TakeProfitSell => TradeType::TakeProfitSell,
+            OrderType::TrailingStopBuy => TradeType::TrailingStopBuy,
+            OrderType::TrailingStopSell => TradeType::TrailingStopSell,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Order {
     pub id: Uuid,
     pub order_type: OrderType,
+    /// Limit price for limit and stop-limit orders, otherwise the expected fill price.
     pub price: BigDecimal,
+    /// Amount still resting on the book.
     pub amount: BigDecimal,
+    /// Amount already executed by partial fills.
+    pub filled: BigDecimal,
     pub fee: BigDecimal,
+    /// Quote reserved for the part of the order that opens or extends a position.
+    pub margin: BigDecimal,
+    /// Stop or take-profit trigger; for trailing stops, the current stop level.
+    pub trigger_price: Option<BigDecimal>,
+    /// Distance a trailing stop keeps from the best price seen since placement.
+    pub trail: Option<BigDecimal>,
+    /// Set once a stop-limit order has been triggered and rests as a limit order.
+    pub triggered: bool,
+    /// Entry order this order waits on; it stays inactive until the entry fills.
+    pub parent_id: Option<Uuid>,
+    /// Orders sharing a group are cancelled as soon as one of them fills.
+    pub oco_group: Option<Uuid>,
+    /// Inactive orders hold no funds and are not matched against candles.
+    pub active: bool,
+}
+
+impl Order {
+    /// Returns where the order executes on a price segment moving from `from`
+    /// to `to`, as `(event_price, fill_price)`. The event price orders
+    /// executions along the path.
+    fn matches(&self, from: &BigDecimal, to: &BigDecimal) -> Option<(BigDecimal, BigDecimal)> {
+        let trigger = self.trigger_price.as_ref().unwrap_or(&self.price);
+
+        match self.order_type {
+            OrderType::LimitBuy => fall(from, to, &self.price).map(|e| (e, self.price.clone())),
+            OrderType::LimitSell => rise(from, to, &self.price).map(|e| (e, self.price.clone())),
+            OrderType::StopMarketBuy | OrderType::TrailingStopBuy | OrderType::TakeProfitSell => {
+                rise(from, to, trigger).map(|e| (e.clone(), e))
+            }
+            OrderType::StopMarketSell | OrderType::TrailingStopSell | OrderType::TakeProfitBuy => {
+                fall(from, to, trigger).map(|e| (e.clone(), e))
+            }
+            OrderType::StopLimitBuy => {
+                let start = if self.triggered {
+                    from.clone()
+                } else {
+                    rise(from, to, trigger)?
+                };
+                fall(&start, to, &self.price).map(|e| (e, self.price.clone()))
+            }
+            OrderType::StopLimitSell => {
+                let start = if self.triggered {
+                    from.clone()
+                } else {
+                    fall(from, to, trigger)?
+                };
+                rise(&start, to, &self.price).map(|e| (e, self.price.clone()))
+            }
+        }
+    }
+
+    /// Splits off `amount` for a partial fill, moving the matching share of the
+    /// reserved fee and margin to the returned order.
+    fn split(&mut self, amount: &BigDecimal, precision: &MarketPrecision) -> Order {
+        let fee = precision.round_amount(&(&self.fee * amount / &self.amount), RoundingMode::Down);
+        let margin = &self.margin * amount / &self.amount;
+
+        self.amount -= amount;
+        self.filled += amount;
+        self.fee -= &fee;
+        self.margin -= &margin;
+
+        Order {
+            amount: amount.clone(),
+            fee,
+            margin,
+            ..self.clone()
+        }
+    }
+
+    /// Marks a stop-limit order as triggered once the segment reaches its trigger.
+    fn arm(&mut self, from: &BigDecimal, to: &BigDecimal) {
+        let Some(trigger) = &self.trigger_price else {
+            return;
+        };
+
+        self.triggered |= match self.order_type {
+            OrderType::StopLimitBuy => rise(from, to, trigger).is_some(),
+            OrderType::StopLimitSell => fall(from, to, trigger).is_some(),
+            _ => false,
+        };
+    }
+
+    /// Moves a trailing stop toward the bar's best price. The stop never loosens.
+    fn trail(&mut self, bar: &Candle, precision: &MarketPrecision) {
+        let (Some(trail), Some(stop)) = (&self.trail, &self.trigger_price) else {
+            return;
+        };
+
+        let (candidate, tighter) = if self.order_type.is_buy() {
+            let candidate = precision.round_price(&(&bar.low + trail), RoundingMode::Down);
+            let tighter = candidate < *stop;
+            (candidate, tighter)
+        } else {
+            let candidate = precision.round_price(&(&bar.high - trail), RoundingMode::Up);
+            let tighter = candidate > *stop;
+            (candidate, tighter)
+        };
+
+        if tighter {
+            self.price = candidate.clone();
+            self.trigger_price = Some(candidate);
+        }
+    }
+}
+
+/// Next thing to happen along a price path.
+enum PathEvent {
+    Fill(Uuid, BigDecimal),
+    Liquidation,
 }
 
+/// Order ids of a bracket. `entry` is `None` when the entry filled immediately.
 #[derive(Debug, Clone)]
-pub struct StrategyContext<'a> {
-    pub(crate) candles: &'a [Candle],
-    pub(crate) balance: BigDecimal,
+pub struct Bracket {
+    pub entry: Option<Uuid>,
+    pub stop_loss: Uuid,
+    pub take_profit: Uuid,
+    pub oco_group: Uuid,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct MarginConfig {
+    #[ts(type = "string")]
+    pub leverage: BigDecimal,
+    #[ts(type = "string")]
+    pub maintenance_margin_rate: BigDecimal,
+}
+
+impl MarginConfig {
+    pub fn validate(&self) -> AppResult<()> {
+        if self.leverage < BigDecimal::one() {
+            return Err(AppError::BadRequest(format!(
+                "Leverage must be at least 1, got {}",
+                self.leverage
+            )));
+        }
+
+        if self.maintenance_margin_rate < BigDecimal::zero()
+            || &self.maintenance_margin_rate * &self.leverage >= BigDecimal::one()
+        {
+            return Err(AppError::BadRequest(format!(
+                "Maintenance margin rate must be between 0 and 1 / leverage, got {}",
+                self.maintenance_margin_rate
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for MarginConfig {
+    fn default() -> Self {
+        Self {
+            leverage: BigDecimal::one(),
+            maintenance_margin_rate: BigDecimal::new(5.into(), 3),
+        }
+    }
+}
+
+/// Caps how much of a candle's volume resting limit orders may fill.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct LiquidityConfig {
+    /// Fraction of each candle's volume available to limit and stop-limit fills.
+    #[ts(type = "string")]
+    pub max_volume_fraction: BigDecimal,
+}
+
+impl LiquidityConfig {
+    pub fn validate(&self) -> AppResult<()> {
+        if self.max_volume_fraction <= BigDecimal::zero()
+            || self.max_volume_fraction > BigDecimal::one()
+        {
+            return Err(AppError::BadRequest(format!(
+                "Max volume fraction must be in (0, 1], got {}",
+                self.max_volume_fraction
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum CandleWindow<'a> {
+    Borrowed(&'a [Candle]),
+    Owned { candles: Vec<Candle>, start: usize },
+}
+
+impl CandleWindow<'_> {
+    pub(crate) fn push(&mut self, candle: Candle, lookback: Option<usize>) {
+        if let Self::Borrowed(candles) = self {
+            *self = Self::Owned {
+                candles: candles.to_vec(),
+                start: 0,
+            };
+        }
+        let Self::Owned { candles, start } = self else {
+            return;
+        };
+
+        candles.push(candle);
+        if let Some(lookback) = lookback {
+            *start = candles.len().saturating_sub(lookback);
+            // Hidden candles are dropped in batches so pushes stay cheap.
+            if *start >= lookback.max(1) {
+                candles.drain(..*start);
+                *start = 0;
+            }
+        }
+    }
+}
+
+impl Default for CandleWindow<'_> {
+    fn default() -> Self {
+        Self::Borrowed(&[])
+    }
+}
+
+impl Deref for CandleWindow<'_> {
+    type Target = [Candle];
+
+    fn deref(&self) -> &[Candle] {
+        match self {
+            Self::Borrowed(candles) => candles,
+            Self::Owned { candles, start } => &candles[*start..],
+        }
+    }
+}
+
+impl<'a> From<&'a [Candle]> for CandleWindow<'a> {
+    fn from(candles: &'a [Candle]) -> Self {
+        Self::Borrowed(candles)
+    }
+}
+
+impl From<Vec<Candle>> for CandleWindow<'_> {
+    fn from(candles: Vec<Candle>) -> Self {
+        Self::Owned { candles, start: 0 }
+    }
+}
+
+/// Per-symbol state of a [`StrategyContext`].
+#[derive(Debug, Clone)]
+pub(crate) struct Market<'a> {
+    pub(crate) symbol: String,
+    pub(crate) candles: CandleWindow<'a>,
+    pub(crate) higher_candles: Vec<(Timeframe, CandleWindow<'a>)>,
     pub(crate) position: BigDecimal,
-    pub(crate) trades: Vec<Trade>,
     pub(crate) orders: Vec<Order>,
     pub(crate) fees: TradingFees,
     pub(crate) precision: MarketPrecision,
+    /// Base volume limit orders may still fill within the current candle.
+    pub(crate) volume_left: Option<BigDecimal>,
+    pub(crate) sub_candles: CandleWindow<'a>,
+}
+
+impl Market<'_> {
+    pub(crate) fn new(symbol: String, fees: TradingFees, precision: MarketPrecision) -> Self {
+        Self {
+            symbol,
+            candles: CandleWindow::default(),
+            higher_candles: Vec::new(),
+            position: BigDecimal::zero(),
+            orders: Vec::new(),
+            fees,
+            precision,
+            volume_left: None,
+            sub_candles: CandleWindow::default(),
+        }
+    }
+
+    /// Latest close, used to value the position while other markets trade.
+    fn mark_price(&self) -> Option<&BigDecimal> {
+        self.candles.last().map(|c| &c.close)
+    }
+}
+
+/// What a candle's price path changes, swapped out by
+/// [`FillModel::Pessimistic`] to run the other path.
+struct PathState {
+    balance: BigDecimal,
+    trades: Vec<Trade>,
+    events: Vec<OrderEvent>,
+    output: StrategyOutput,
+    position: BigDecimal,
+    orders: Vec<Order>,
+    volume_left: Option<BigDecimal>,
+}
+
+/// Fill or cancellation of a resting order made by the backtest rather than
+/// the strategy.
+#[derive(Debug, Clone)]
+pub(crate) enum OrderEvent {
+    Filled(Order, Box<Trade>),
+    Cancelled(Order),
+}
+
+/// Account state handed to a strategy. Balance and margin are shared by every
+/// symbol of the backtest; candles, positions and orders are per symbol. Calls
+/// act on the primary symbol unless made through [`StrategyContext::symbol`].
+#[derive(Debug, Clone)]
+pub struct StrategyContext<'a> {
+    pub(crate) balance: BigDecimal,
+    pub(crate) trades: Vec<Trade>,
+    pub(crate) markets: Vec<Market<'a>>,
+    /// Index of the market calls act on.
+    pub(crate) current: usize,
+    pub(crate) timeframe: Timeframe,
+    pub(crate) margin: MarginConfig,
+    pub(crate) fill_model: FillModel,
+    pub(crate) slippage_model: SlippageModel,
+    pub(crate) liquidity: Option<LiquidityConfig>,
+    /// Order events not yet reported to the strategy's hooks.
+    pub(crate) events: Vec<OrderEvent>,
+    pub(crate) output: StrategyOutput,
+}
+
+/// A [`StrategyContext`] switched to another symbol. The previous symbol is
+/// restored when it is dropped.
+pub struct SymbolContext<'c, 'a> {
+    context: &'c mut StrategyContext<'a>,
+    previous: usize,
+}
+
+impl<'a> Deref for SymbolContext<'_, 'a> {
+    type Target = StrategyContext<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        self.context
+    }
+}
+
+impl DerefMut for SymbolContext<'_, '_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.context
+    }
+}
+
+impl Drop for SymbolContext<'_, '_> {
+    fn drop(&mut self) {
+        self.context.current = self.previous;
+    }
 }
 
-impl StrategyContext<'_> {
+impl<'a> StrategyContext<'a> {
     pub(crate) fn new(
         balance: BigDecimal,
-        fees: TradingFees,
-        precision: MarketPrecision,
+        markets: Vec<Market<'a>>,
+        timeframe: Timeframe,
+        margin: MarginConfig,
+        fill_model: FillModel,
+        slippage_model: SlippageModel,
+        liquidity: Option<LiquidityConfig>,
     ) -> AppResult<Self> {
+        if markets.is_empty() {
+            return Err("A strategy context needs at least one market".into());
+        }
+
         Ok(Self {
-            candles: &[],
             balance,
-            position: BigDecimal::zero(),
             trades: Vec::new(),
-            orders: Vec::new(),
-            fees,
-            precision,
+            markets,
+            current: 0,
+            timeframe,
+            margin,
+            fill_model,
+            slippage_model,
+            liquidity,
+            events: Vec::new(),
+            output: StrategyOutput::default(),
         })
     }
 
-    pub(crate) fn before(&mut self) -> AppResult<()> {
+    pub(crate) fn market(&self) -> &Market<'a> {
+        &self.markets[self.current]
+    }
+
+    pub(crate) fn market_mut(&mut self) -> &mut Market<'a> {
+        &mut self.markets[self.current]
+    }
+
+    /// Matches the market's resting orders against its latest candle and
+    /// reports the resulting fills and cancellations to `strategy`.
+    pub(crate) fn before(&mut self, strategy: &mut dyn Strategy) -> AppResult<()> {
+        self.events.clear();
         let candle = self.candle()?;
-        let mut orders_to_execute = Vec::new();
-
-        for order in &self.orders {
-            match order.order_type {
-                OrderType::LimitBuy => {
-                    if order.price >= candle.low {
-                        orders_to_execute.push((
-                            order.id,
-                            OrderType::LimitBuy,
-                            order.price.clone(),
-                            order.amount.clone(),
-                            order.fee.clone(),
-                        ));
-                    }
-                }
-                OrderType::LimitSell => {
-                    if order.price <= candle.high {
-                        orders_to_execute.push((
-                            order.id,
-                            OrderType::LimitSell,
-                            order.price.clone(),
-                            order.amount.clone(),
-                            order.fee.clone(),
-                        ));
-                    }
-                }
-            }
-        }
+        self.market_mut().volume_left = self
+            .liquidity
+            .as_ref()
+            .map(|l| &l.max_volume_fraction * &candle.volume);
 
-        for (order_id, order_type, price, amount, fee) in orders_to_execute {
-            match order_type {
-                OrderType::LimitBuy => {
-                    self.execute_limit_buy(&candle, &price, &amount, &fee);
-                }
-                OrderType::LimitSell => {
-                    self.execute_limit_sell(&candle, &price, &amount, &fee);
+        match self.fill_model {
+            FillModel::OpenHighLowClose => {
+                self.run_bar(&candle, &candle, &fill::open_high_low_close(&candle))
+            }
+            FillModel::OpenLowHighClose => {
+                self.run_bar(&candle, &candle, &fill::open_low_high_close(&candle))
+            }
+            FillModel::Pessimistic => self.run_pessimistic(&candle)?,
+            FillModel::LowerTimeframe(_) => {
+                let paths = fill::lower_timeframe_paths(&candle, &self.market().sub_candles);
+                for (path, bar) in paths {
+                    self.run_bar(&candle, &bar, &path);
                 }
             }
-            self.orders.retain(|o| o.id != order_id);
         }
 
-        Ok(())
+        self.notify(strategy)
     }
 
     pub(crate) fn after(&mut self) -> AppResult<()> {
         Ok(())
     }
 
-    pub(crate) fn end(&mut self) -> AppResult<()> {
-        let order_ids: Vec<Uuid> = self.orders.iter().map(|o| o.id).collect();
-        for id in order_ids {
-            self.cancel_order(id);
+    /// Cancels every open order, reporting each to `strategy`, then calls
+    /// its `on_end`. Orders placed by the cancellation hooks are cancelled
+    /// without being reported.
+    pub(crate) fn end(&mut self, strategy: &mut dyn Strategy) -> AppResult<()> {
+        self.events.clear();
+        self.cancel_all();
+        self.notify(strategy)?;
+
+        self.cancel_all();
+        self.events.clear();
+        strategy.on_end(self)
+    }
+
+    /// Closes the position in every symbol with a market order at its latest
+    /// close.
+    pub(crate) fn close_positions(&mut self) -> AppResult<()> {
+        for index in 0..self.markets.len() {
+            let mut context = self.select(index);
+            let position = context.market().position.clone();
+            if position > BigDecimal::zero() {
+                context.market_sell(&position)?;
+            } else if position < BigDecimal::zero() {
+                context.market_buy(&-position)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn cancel_all(&mut self) {
+        for index in 0..self.markets.len() {
+            let mut context = self.select(index);
+            let order_ids: Vec<Uuid> = context.market().orders.iter().map(|o| o.id).collect();
+            for id in order_ids {
+                context.cancel_order(id);
+            }
+        }
+    }
+
+    /// Hands the pending order events to the strategy's hooks, in the order
+    /// they happened.
+    fn notify(&mut self, strategy: &mut dyn Strategy) -> AppResult<()> {
+        for event in mem::take(&mut self.events) {
+            match event {
+                OrderEvent::Filled(order, trade) => {
+                    strategy.on_order_filled(self, &order, &trade)?
+                }
+                OrderEvent::Cancelled(order) => strategy.on_order_cancelled(self, &order)?,
+            }
         }
         Ok(())
     }
 
+    pub(crate) fn select(&mut self, index: usize) -> SymbolContext<'_, 'a> {
+        let previous = mem::replace(&mut self.current, index);
+        SymbolContext {
+            context: self,
+            previous,
+        }
+    }
+
+    /// Symbols of the backtest, the primary symbol first.
+    pub fn symbols(&self) -> Vec<&str> {
+        self.markets.iter().map(|m| m.symbol.as_str()).collect()
+    }
+
+    /// Symbol that candle, position and order calls currently act on.
+    pub fn current_symbol(&self) -> &str {
+        &self.market().symbol
+    }
+
+    /// Switches calls to `symbol` until the returned context is dropped:
+    /// `ctx.symbol("ETH/USDT")?.market_buy(&amount)?`.
+    pub fn symbol(&mut self, symbol: &str) -> AppResult<SymbolContext<'_, 'a>> {
+        let Some(index) = self.markets.iter().position(|m| m.symbol == symbol) else {
+            return Err(AppError::Strategy(format!(
+                "Symbol {} is not part of this backtest",
+                symbol
+            )));
+        };
+        Ok(self.select(index))
+    }
+
+    /// The last [`Strategy::lookback`](crate::strategy::Strategy::lookback)
+    /// candles up to the latest one.
     pub fn candles(&self) -> &[Candle] {
-        &self.candles
+        &self.market().candles
+    }
+
+    /// Timeframe the strategy ticks on.
+    pub fn timeframe(&self) -> Timeframe {
+        self.timeframe
+    }
+
+    /// Candles of `timeframe` that had closed by the end of the latest candle.
+    /// Timeframes other than the tick timeframe must be declared by
+    /// [`Strategy::timeframes`](crate::strategy::Strategy::timeframes).
+    pub fn candles_for(&self, timeframe: Timeframe) -> AppResult<&[Candle]> {
+        if timeframe == self.timeframe {
+            return Ok(self.candles());
+        }
+
+        self.market()
+            .higher_candles
+            .iter()
+            .find(|(tf, _)| *tf == timeframe)
+            .map(|(_, candles)| &**candles)
+            .ok_or_else(|| {
+                AppError::Strategy(format!(
+                    "Timeframe {} was not declared by the strategy",
+                    timeframe
+                ))
+            })
     }
 
     pub fn candle(&self) -> AppResult<Candle> {
-        self.candles
+        self.market()
+            .candles
             .last()
             .cloned()
             .ok_or(AppError::Strategy("No candles available".into()))
@@ -151,8 +687,9 @@ impl StrategyContext<'_> {
         self.balance.clone()
     }
 
+    /// Signed base position: positive when long, negative when short.
     pub fn position(&self) -> BigDecimal {
-        self.position.clone()
+        self.market().position.clone()
     }
 
     pub fn trades(&self) -> &[Trade] {
@@ -160,235 +697,1801 @@ impl StrategyContext<'_> {
     }
 
     pub fn orders(&self) -> &[Order] {
-        &self.orders
+        &self.market().orders
     }
 
     pub fn precision(&self) -> &MarketPrecision {
-        &self.precision
+        &self.market().precision
     }
 
-    pub fn cancel_order(&mut self, order_id: Uuid) {
-        if let Some(pos) = self.orders.iter().position(|o| o.id == order_id) {
-            let order = &self.orders[pos];
-            match order.order_type {
-                OrderType::LimitBuy => {
-                    let refund = &order.price * &order.amount + &order.fee;
-                    self.balance += &refund;
-                }
-                OrderType::LimitSell => {
-                    self.position += &order.amount;
-                    self.balance += &order.fee;
-                }
-            }
-            self.orders.remove(pos);
-        }
+    pub fn fees(&self) -> &TradingFees {
+        &self.market().fees
     }
 
-    pub fn market_buy(&mut self, amount: &BigDecimal) -> AppResult<()> {
-        let amount = self.precision.round_amount(amount, RoundingMode::Down);
+    pub fn margin(&self) -> &MarginConfig {
+        &self.margin
+    }
 
-        if amount <= BigDecimal::zero() {
-            return Err(AppError::Strategy("Amount must be positive".into()));
-        }
+    pub fn slippage_model(&self) -> &SlippageModel {
+        &self.slippage_model
+    }
 
+    /// Account value at the latest closes, including quote held by open orders.
+    pub fn equity(&self) -> AppResult<BigDecimal> {
         let candle = self.candle()?;
-        let price = candle.close;
-
-        let cost = &price * &amount;
-        let fee = &cost * &self.fees.taker;
-        let fee = self.precision.round_amount(&fee, RoundingMode::Up);
-        let total = &cost + &fee;
-
-        if total > self.balance {
-            return Err(AppError::Strategy("Insufficient funds".into()));
-        }
+        Ok(self.total_balance()
+            + &self.market().position * &candle.close
+            + self.other_markets_value(BigDecimal::zero()))
+    }
 
-        self.balance -= &total;
-        self.position += &amount;
+    /// Quote still available to open new exposure at the latest close.
+    pub fn free_margin(&self) -> AppResult<BigDecimal> {
+        let candle = self.candle()?;
+        Ok(self.free_margin_with(&self.balance, &self.market().position, &candle.close))
+    }
 
-        self.trades.push(Trade {
-            timestamp: candle.timestamp,
-            trade_type: TradeType::MarketBuy,
-            price,
-            amount,
-            fee,
-            profit: None,
-        });
+    /// Records `message` with the backtest, timestamped with the current
+    /// symbol's latest candle.
+    pub fn log(&mut self, level: LogLevel, message: impl Into<String>) {
+        let entry = LogEntry {
+            timestamp: self.now(),
+            level,
+            symbol: self.market().symbol.clone(),
+            message: message.into(),
+        };
+        self.output.log(entry);
+    }
 
-        Ok(())
+    /// Adds `value` to the series `name` of the current symbol at its latest
+    /// candle, for the chart to draw over the candles. Plotting a series twice
+    /// in one candle keeps the last value; NaN and infinite values are skipped.
+    pub fn plot(&mut self, name: &str, value: f64) {
+        let point = PlotPoint {
+            timestamp: self.now(),
+            value,
+        };
+        let symbol = self.market().symbol.clone();
+        self.output.plot(name, &symbol, point);
     }
 
-    pub fn market_sell(&mut self, amount: &BigDecimal) -> AppResult<()> {
-        let amount = self.precision.round_amount(amount, RoundingMode::Down);
+    /// Open time of the current symbol's latest candle, or of the latest
+    /// candle of any symbol if it has none yet.
+    fn now(&self) -> DateTime<Utc> {
+        self.market()
+            .candles
+            .last()
+            .or_else(|| {
+                self.markets
+                    .iter()
+                    .filter_map(|m| m.candles.last())
+                    .max_by_key(|c| c.timestamp)
+            })
+            .map(|c| c.timestamp)
+            .unwrap_or_default()
+    }
 
-        if amount <= BigDecimal::zero() {
-            return Err(AppError::Strategy("Amount must be positive".into()));
+    /// Cancels an order and refunds what it reserved. Cancelling a bracket
+    /// entry also cancels the exits waiting on it.
+    pub fn cancel_order(&mut self, order_id: Uuid) {
+        if let Some(pos) = self.market().orders.iter().position(|o| o.id == order_id) {
+            let order = self.market_mut().orders.remove(pos);
+            self.balance += &order.margin + &order.fee;
+            let order_id = order.id;
+            self.events.push(OrderEvent::Cancelled(order));
+            self.cancel_children(order_id);
         }
+    }
 
-        if amount > self.position {
+    /// Links resting orders so that a fill of any one cancels the rest.
+    /// Returns the id of the group.
+    pub fn one_cancels_other(&mut self, order_ids: &[Uuid]) -> AppResult<Uuid> {
+        if order_ids.len() < 2 {
             return Err(AppError::Strategy(
-                "Insufficient base asset amount to sell".into(),
+                "One-cancels-other needs at least two orders".into(),
             ));
         }
 
-        let candle = self.candle()?;
-        let price = candle.close;
-
-        let proceeds = &price * &amount;
-        let fee = self
-            .precision
-            .round_amount(&(&proceeds * &self.fees.taker), RoundingMode::Up);
-        let revenue = &proceeds - &fee;
-
-        if revenue < BigDecimal::zero() {
-            return Err(AppError::Strategy("Revenue cannot be negative".into()));
+        for id in order_ids {
+            let Some(order) = self.market().orders.iter().find(|o| o.id == *id) else {
+                return Err(AppError::Strategy(format!("Order {} not found", id)));
+            };
+            if order.oco_group.is_some() {
+                return Err(AppError::Strategy(format!(
+                    "Order {} is already in a one-cancels-other group",
+                    id
+                )));
+            }
         }
 
-        self.position -= &amount;
-        self.balance += &revenue;
+        let group = Uuid::new_v4();
+        for order in self.market_mut().orders.iter_mut() {
+            if order_ids.contains(&order.id) {
+                order.oco_group = Some(group);
+            }
+        }
 
-        self.trades.push(Trade {
-            timestamp: candle.timestamp,
-            trade_type: TradeType::MarketSell,
-            price,
-            amount,
-            fee,
-            profit: None,
-        });
+        Ok(group)
+    }
 
-        Ok(())
+    /// Opens a long with a stop-loss and take-profit attached. The entry is a
+    /// limit order at `price`, or a market order when `price` is `None`. The
+    /// exits become active once the entry fills and cancel each other.
+    pub fn bracket_buy(
+        &mut self,
+        price: Option<&BigDecimal>,
+        amount: &BigDecimal,
+        stop_loss: &BigDecimal,
+        take_profit: &BigDecimal,
+    ) -> AppResult<Bracket> {
+        self.bracket(true, price, amount, stop_loss, take_profit)
     }
 
-    pub fn limit_buy(
+    /// Opens a short with a stop-loss and take-profit attached. See [`Self::bracket_buy`].
+    pub fn bracket_sell(
         &mut self,
-        price: &BigDecimal,
+        price: Option<&BigDecimal>,
         amount: &BigDecimal,
-    ) -> AppResult<Option<Uuid>> {
-        let price = self.precision.round_amount(price, RoundingMode::Down);
-        let amount = self.precision.round_amount(amount, RoundingMode::Down);
+        stop_loss: &BigDecimal,
+        take_profit: &BigDecimal,
+    ) -> AppResult<Bracket> {
+        self.bracket(false, price, amount, stop_loss, take_profit)
+    }
 
-        if amount <= BigDecimal::zero() {
-            return Err(AppError::Strategy("Amount must be positive".into()));
-        }
+    pub fn market_buy(&mut self, amount: &BigDecimal) -> AppResult<()> {
+        self.buy_now(amount, None)
+    }
 
+    pub fn market_sell(&mut self, amount: &BigDecimal) -> AppResult<()> {
+        self.sell_now(amount, None)
+    }
+
+    pub fn limit_buy(
+        &mut self,
+        price: &BigDecimal,
+        amount: &BigDecimal,
+    ) -> AppResult<Option<Uuid>> {
+        let price = self
+            .market()
+            .precision
+            .round_price(price, RoundingMode::Down);
         let candle = self.candle()?;
 
         if price >= candle.close {
-            self.market_buy(&amount)?;
+            self.buy_now(amount, Some(&price))?;
             return Ok(None);
         };
 
-        let cost = &amount * &price;
-        let fee = &cost * &self.fees.maker;
-        let fee = self.precision.round_amount(&fee, RoundingMode::Up);
-        let total = &cost + &fee;
+        let order_id = self.place_order(OrderType::LimitBuy, price, None, None, amount)?;
+        Ok(Some(order_id))
+    }
 
-        if total > self.balance {
-            return Err(AppError::Strategy("Insufficient funds".into()));
+    pub fn limit_sell(
+        &mut self,
+        price: &BigDecimal,
+        amount: &BigDecimal,
+    ) -> AppResult<Option<Uuid>> {
+        let price = self.market().precision.round_price(price, RoundingMode::Up);
+        let candle = self.candle()?;
+
+        if price <= candle.close {
+            self.sell_now(amount, Some(&price))?;
+            return Ok(None);
+        };
+
+        let order_id = self.place_order(OrderType::LimitSell, price, None, None, amount)?;
+        Ok(Some(order_id))
+    }
+
+    /// Buys at market once the price rises to `trigger`.
+    pub fn stop_market_buy(
+        &mut self,
+        trigger: &BigDecimal,
+        amount: &BigDecimal,
+    ) -> AppResult<Option<Uuid>> {
+        let trigger = self
+            .market()
+            .precision
+            .round_price(trigger, RoundingMode::Up);
+        let candle = self.candle()?;
+
+        if trigger <= candle.close {
+            self.market_buy(amount)?;
+            return Ok(None);
         }
 
-        self.balance -= &total;
+        let order_id = self.place_order(
+            OrderType::StopMarketBuy,
+            trigger.clone(),
+            Some(trigger),
+            None,
+            amount,
+        )?;
+        Ok(Some(order_id))
+    }
 
-        let order_id = Uuid::new_v4();
-        self.orders.push(Order {
-            id: order_id,
-            order_type: OrderType::LimitBuy,
-            price,
+    /// Sells at market once the price falls to `trigger`.
+    pub fn stop_market_sell(
+        &mut self,
+        trigger: &BigDecimal,
+        amount: &BigDecimal,
+    ) -> AppResult<Option<Uuid>> {
+        let trigger = self
+            .market()
+            .precision
+            .round_price(trigger, RoundingMode::Down);
+        let candle = self.candle()?;
+
+        if trigger >= candle.close {
+            self.market_sell(amount)?;
+            return Ok(None);
+        }
+
+        let order_id = self.place_order(
+            OrderType::StopMarketSell,
+            trigger.clone(),
+            Some(trigger),
+            None,
             amount,
-            fee,
-        });
+        )?;
+        Ok(Some(order_id))
+    }
 
+    /// Places a limit buy at `price` once the price rises to `trigger`.
+    pub fn stop_limit_buy(
+        &mut self,
+        trigger: &BigDecimal,
+        price: &BigDecimal,
+        amount: &BigDecimal,
+    ) -> AppResult<Option<Uuid>> {
+        let trigger = self
+            .market()
+            .precision
+            .round_price(trigger, RoundingMode::Up);
+        let price = self
+            .market()
+            .precision
+            .round_price(price, RoundingMode::Down);
+        let candle = self.candle()?;
+
+        if trigger <= candle.close {
+            return self.limit_buy(&price, amount);
+        }
+
+        let order_id =
+            self.place_order(OrderType::StopLimitBuy, price, Some(trigger), None, amount)?;
         Ok(Some(order_id))
     }
 
-    pub fn limit_sell(
+    /// Places a limit sell at `price` once the price falls to `trigger`.
+    pub fn stop_limit_sell(
         &mut self,
+        trigger: &BigDecimal,
         price: &BigDecimal,
         amount: &BigDecimal,
     ) -> AppResult<Option<Uuid>> {
-        let price = self.precision.round_amount(price, RoundingMode::Down);
-        let amount = self.precision.round_amount(amount, RoundingMode::Down);
+        let trigger = self
+            .market()
+            .precision
+            .round_price(trigger, RoundingMode::Down);
+        let price = self.market().precision.round_price(price, RoundingMode::Up);
+        let candle = self.candle()?;
+
+        if trigger >= candle.close {
+            return self.limit_sell(&price, amount);
+        }
+
+        let order_id =
+            self.place_order(OrderType::StopLimitSell, price, Some(trigger), None, amount)?;
+        Ok(Some(order_id))
+    }
+
+    /// Buys at market once the price falls to `trigger`, typically to close a short.
+    pub fn take_profit_buy(
+        &mut self,
+        trigger: &BigDecimal,
+        amount: &BigDecimal,
+    ) -> AppResult<Option<Uuid>> {
+        let trigger = self
+            .market()
+            .precision
+            .round_price(trigger, RoundingMode::Down);
+        let candle = self.candle()?;
+
+        if trigger >= candle.close {
+            self.market_buy(amount)?;
+            return Ok(None);
+        }
+
+        let order_id = self.place_order(
+            OrderType::TakeProfitBuy,
+            trigger.clone(),
+            Some(trigger),
+            None,
+            amount,
+        )?;
+        Ok(Some(order_id))
+    }
+
+    /// Sells at market once the price rises to `trigger`, typically to close a long.
+    pub fn take_profit_sell(
+        &mut self,
+        trigger: &BigDecimal,
+        amount: &BigDecimal,
+    ) -> AppResult<Option<Uuid>> {
+        let trigger = self
+            .market()
+            .precision
+            .round_price(trigger, RoundingMode::Up);
+        let candle = self.candle()?;
+
+        if trigger <= candle.close {
+            self.market_sell(amount)?;
+            return Ok(None);
+        }
+
+        let order_id = self.place_order(
+            OrderType::TakeProfitSell,
+            trigger.clone(),
+            Some(trigger),
+            None,
+            amount,
+        )?;
+        Ok(Some(order_id))
+    }
+
+    /// Buys at market once the price rises `trail` above the lowest low since placement.
+    pub fn trailing_stop_buy(
+        &mut self,
+        trail: &BigDecimal,
+        amount: &BigDecimal,
+    ) -> AppResult<Uuid> {
+        if *trail <= BigDecimal::zero() {
+            return Err(AppError::Strategy("Trail must be positive".into()));
+        }
+
+        let candle = self.candle()?;
+        let stop = self
+            .market()
+            .precision
+            .round_price(&(&candle.close + trail), RoundingMode::Down);
+
+        self.place_order(
+            OrderType::TrailingStopBuy,
+            stop.clone(),
+            Some(stop),
+            Some(trail.clone()),
+            amount,
+        )
+    }
+
+    /// Sells at market once the price falls `trail` below the highest high since placement.
+    pub fn trailing_stop_sell(
+        &mut self,
+        trail: &BigDecimal,
+        amount: &BigDecimal,
+    ) -> AppResult<Uuid> {
+        if *trail <= BigDecimal::zero() {
+            return Err(AppError::Strategy("Trail must be positive".into()));
+        }
+
+        let candle = self.candle()?;
+        let stop = self
+            .market()
+            .precision
+            .round_price(&(&candle.close - trail), RoundingMode::Up);
+
+        self.place_order(
+            OrderType::TrailingStopSell,
+            stop.clone(),
+            Some(stop),
+            Some(trail.clone()),
+            amount,
+        )
+    }
+
+    /// Buys `amount` at the latest close, slipped but no higher than `limit`.
+    fn buy_now(&mut self, amount: &BigDecimal, limit: Option<&BigDecimal>) -> AppResult<()> {
+        let amount = self
+            .market()
+            .precision
+            .round_amount(amount, RoundingMode::Down);
 
         if amount <= BigDecimal::zero() {
             return Err(AppError::Strategy("Amount must be positive".into()));
         }
 
-        if amount > self.position {
-            return Err(AppError::Strategy(
-                "Insufficient base asset amount to sell".into(),
-            ));
+        let candle = self.candle()?;
+        self.check_liquidity(&candle)?;
+        let price = self.slip(&candle, &candle.close, &amount, true);
+        let price = limit.map_or(price.clone(), |limit| price.min(limit.clone()));
+        let slippage = &price - &candle.close;
+
+        let cost = &price * &amount;
+        let fee = &cost * &self.market().fees.taker;
+        let fee = self.market().precision.round_amount(&fee, RoundingMode::Up);
+
+        let balance = &self.balance - &cost - &fee;
+        let position = &self.market().position + &amount;
+        if self.free_margin_with(&balance, &position, &price) < BigDecimal::zero() {
+            return Err(AppError::Strategy("Insufficient funds".into()));
+        }
+
+        self.balance = balance;
+        self.market_mut().position = position;
+
+        self.trades.push(Trade {
+            timestamp: candle.timestamp,
+            symbol: self.market().symbol.clone(),
+            trade_type: TradeType::MarketBuy,
+            price,
+            amount,
+            fee,
+            slippage,
+            slippage_model: self.slippage_model.clone(),
+            order_id: None,
+            profit: None,
+        });
+
+        Ok(())
+    }
+
+    /// Sells `amount` at the latest close, slipped but no lower than `limit`.
+    fn sell_now(&mut self, amount: &BigDecimal, limit: Option<&BigDecimal>) -> AppResult<()> {
+        let amount = self
+            .market()
+            .precision
+            .round_amount(amount, RoundingMode::Down);
+
+        if amount <= BigDecimal::zero() {
+            return Err(AppError::Strategy("Amount must be positive".into()));
         }
 
         let candle = self.candle()?;
-        if price <= candle.close {
-            self.market_sell(&amount)?;
-            return Ok(None);
-        };
+        self.check_liquidity(&candle)?;
+        let price = self.slip(&candle, &candle.close, &amount, false);
+        let price = limit.map_or(price.clone(), |limit| price.max(limit.clone()));
+        let slippage = &candle.close - &price;
+
+        if price <= BigDecimal::zero() {
+            return Err(AppError::Strategy("Slippage exceeds the price".into()));
+        }
 
         let proceeds = &price * &amount;
         let fee = self
+            .market()
             .precision
-            .round_amount(&(&proceeds * &self.fees.maker), RoundingMode::Up);
+            .round_amount(&(&proceeds * &self.market().fees.taker), RoundingMode::Up);
+        let revenue = &proceeds - &fee;
+
+        if revenue < BigDecimal::zero() {
+            return Err(AppError::Strategy("Revenue cannot be negative".into()));
+        }
 
-        if fee > self.balance {
-            return Err(AppError::Strategy("Insufficient funds to cover fee".into()));
+        let balance = &self.balance + &revenue;
+        let position = &self.market().position - &amount;
+        if self.free_margin_with(&balance, &position, &price) < BigDecimal::zero() {
+            return Err(AppError::Strategy("Insufficient margin to sell".into()));
         }
 
-        self.position -= &amount;
-        self.balance -= &fee;
+        self.balance = balance;
+        self.market_mut().position = position;
 
-        let order_id = Uuid::new_v4();
-        self.orders.push(Order {
-            id: order_id,
-            order_type: OrderType::LimitSell,
+        self.trades.push(Trade {
+            timestamp: candle.timestamp,
+            symbol: self.market().symbol.clone(),
+            trade_type: TradeType::MarketSell,
             price,
             amount,
             fee,
+            slippage,
+            slippage_model: self.slippage_model.clone(),
+            order_id: None,
+            profit: None,
         });
 
-        Ok(Some(order_id))
+        Ok(())
     }
 
-    fn execute_limit_buy(
+    /// Reserves fee and margin for a resting order and adds it to the book.
+    /// `price` is the price the order is expected to fill at.
+    fn place_order(
         &mut self,
-        candle: &Candle,
-        price: &BigDecimal,
+        order_type: OrderType,
+        price: BigDecimal,
+        trigger_price: Option<BigDecimal>,
+        trail: Option<BigDecimal>,
         amount: &BigDecimal,
-        fee: &BigDecimal,
-    ) {
-        self.position += amount;
+    ) -> AppResult<Uuid> {
+        let mut order = self.new_order(order_type, price, trigger_price, trail, amount)?;
+        self.reserve(&mut order)?;
 
-        let trade = Trade {
-            timestamp: candle.timestamp,
-            trade_type: TradeType::LimitBuy,
-            price: price.clone(),
-            amount: amount.clone(),
-            fee: fee.clone(),
-            profit: None,
+        let order_id = order.id;
+        self.market_mut().orders.push(order);
+
+        Ok(order_id)
+    }
+
+    fn new_order(
+        &self,
+        order_type: OrderType,
+        price: BigDecimal,
+        trigger_price: Option<BigDecimal>,
+        trail: Option<BigDecimal>,
+        amount: &BigDecimal,
+    ) -> AppResult<Order> {
+        let amount = self
+            .market()
+            .precision
+            .round_amount(amount, RoundingMode::Down);
+
+        if amount <= BigDecimal::zero() {
+            return Err(AppError::Strategy("Amount must be positive".into()));
+        }
+
+        if price <= BigDecimal::zero() {
+            return Err(AppError::Strategy("Price must be positive".into()));
+        }
+
+        Ok(Order {
+            id: Uuid::new_v4(),
+            order_type,
+            price,
+            amount,
+            filled: BigDecimal::zero(),
+            fee: BigDecimal::zero(),
+            margin: BigDecimal::zero(),
+            trigger_price,
+            trail,
+            triggered: false,
+            parent_id: None,
+            oco_group: None,
+            active: true,
+        })
+    }
+
+    /// Takes the order's fee and margin out of the balance.
+    fn reserve(&mut self, order: &mut Order) -> AppResult<()> {
+        let candle = self.candle()?;
+        let (fee, margin) = self.reservation(order);
+
+        let total = &margin + &fee;
+        if total > self.free_margin_with(&self.balance, &self.market().position, &candle.close) {
+            return Err(AppError::Strategy("Insufficient funds".into()));
+        }
+
+        self.take_reservation(order, fee, margin);
+        Ok(())
+    }
+
+    /// Fee and margin `order` has to reserve.
+    fn reservation(&self, order: &Order) -> (BigDecimal, BigDecimal) {
+        let fee_rate = if order.order_type.is_taker() {
+            &self.market().fees.taker
+        } else {
+            &self.market().fees.maker
         };
+        let fee = self
+            .market()
+            .precision
+            .round_amount(&(&order.price * &order.amount * fee_rate), RoundingMode::Up);
+        let margin = self.opening_amount(order) * &order.price / &self.margin.leverage;
+        (fee, margin)
+    }
 
-        self.trades.push(trade);
+    fn take_reservation(&mut self, order: &mut Order, fee: BigDecimal, margin: BigDecimal) {
+        self.balance -= &margin + &fee;
+        order.fee = fee;
+        order.margin = margin;
+        order.active = true;
     }
 
-    fn execute_limit_sell(
+    fn bracket(
         &mut self,
+        is_buy: bool,
+        price: Option<&BigDecimal>,
+        amount: &BigDecimal,
+        stop_loss: &BigDecimal,
+        take_profit: &BigDecimal,
+    ) -> AppResult<Bracket> {
+        let candle = self.candle()?;
+        let reference = price.cloned().unwrap_or_else(|| candle.close.clone());
+        let (stop_loss, take_profit) = if is_buy {
+            (
+                self.market()
+                    .precision
+                    .round_price(stop_loss, RoundingMode::Down),
+                self.market()
+                    .precision
+                    .round_price(take_profit, RoundingMode::Up),
+            )
+        } else {
+            (
+                self.market()
+                    .precision
+                    .round_price(stop_loss, RoundingMode::Up),
+                self.market()
+                    .precision
+                    .round_price(take_profit, RoundingMode::Down),
+            )
+        };
+
+        let ordered = if is_buy {
+            stop_loss < reference && reference < take_profit
+        } else {
+            take_profit < reference && reference < stop_loss
+        };
+        if !ordered {
+            return Err(AppError::Strategy(
+                "Stop-loss and take-profit must lie on either side of the entry price".into(),
+            ));
+        }
+
+        let amount = self
+            .market()
+            .precision
+            .round_amount(amount, RoundingMode::Down);
+        let (stop_type, take_profit_type) = if is_buy {
+            (OrderType::StopMarketSell, OrderType::TakeProfitSell)
+        } else {
+            (OrderType::StopMarketBuy, OrderType::TakeProfitBuy)
+        };
+        let mut stop_order =
+            self.new_order(stop_type, stop_loss.clone(), Some(stop_loss), None, &amount)?;
+        let mut take_profit_order = self.new_order(
+            take_profit_type,
+            take_profit.clone(),
+            Some(take_profit),
+            None,
+            &amount,
+        )?;
+
+        let (balance, position, trades) = (
+            self.balance.clone(),
+            self.market().position.clone(),
+            self.trades.len(),
+        );
+        let entry = match (price, is_buy) {
+            (Some(price), true) => self.limit_buy(price, &amount)?,
+            (Some(price), false) => self.limit_sell(price, &amount)?,
+            (None, true) => self.market_buy(&amount).map(|_| None)?,
+            (None, false) => self.market_sell(&amount).map(|_| None)?,
+        };
+
+        let oco_group = Uuid::new_v4();
+        for order in [&mut stop_order, &mut take_profit_order] {
+            order.oco_group = Some(oco_group);
+            order.parent_id = entry;
+            order.active = false;
+        }
+
+        // An entry that filled at once is undone if its exits cannot be
+        // reserved, rather than leaving the position unprotected.
+        if entry.is_none()
+            && let Err(e) = self
+                .reserve(&mut stop_order)
+                .and_then(|_| self.reserve(&mut take_profit_order))
+        {
+            self.balance = balance;
+            self.market_mut().position = position;
+            self.trades.truncate(trades);
+            return Err(e);
+        }
+
+        let bracket = Bracket {
+            entry,
+            stop_loss: stop_order.id,
+            take_profit: take_profit_order.id,
+            oco_group,
+        };
+        self.market_mut().orders.push(stop_order);
+        self.market_mut().orders.push(take_profit_order);
+
+        Ok(bracket)
+    }
+
+    /// Cancels the other members of a filled order's group and activates the
+    /// orders waiting on it. They protect the position the filled order
+    /// opened, so they are activated even if free margin no longer covers
+    /// their reservation.
+    fn settle_linked_orders(&mut self, filled: &Order) {
+        self.cancel_group(filled);
+
+        let children: Vec<Uuid> = self
+            .market()
+            .orders
+            .iter()
+            .filter(|o| o.parent_id == Some(filled.id))
+            .map(|o| o.id)
+            .collect();
+
+        for id in children {
+            let Some(pos) = self.market().orders.iter().position(|o| o.id == id) else {
+                continue;
+            };
+            let mut order = self.market_mut().orders.remove(pos);
+            order.parent_id = None;
+            let (fee, margin) = self.reservation(&order);
+            self.take_reservation(&mut order, fee, margin);
+            self.market_mut().orders.push(order);
+        }
+    }
+
+    /// Activates exits for the part of an entry that filled, in a group of
+    /// their own. The entry's exits keep waiting on the rest of it.
+    fn activate_partial_exits(&mut self, part: &Order) {
+        let group = Uuid::new_v4();
+        let exits: Vec<Order> = self
+            .market_mut()
+            .orders
+            .iter_mut()
+            .filter(|o| o.parent_id == Some(part.id))
+            .map(|waiting| {
+                waiting.amount -= &part.amount;
+                Order {
+                    id: Uuid::new_v4(),
+                    amount: part.amount.clone(),
+                    parent_id: None,
+                    oco_group: Some(group),
+                    ..waiting.clone()
+                }
+            })
+            .collect();
+
+        for mut order in exits {
+            let (fee, margin) = self.reservation(&order);
+            self.take_reservation(&mut order, fee, margin);
+            self.market_mut().orders.push(order);
+        }
+    }
+
+    /// Cancels the other members of a filled order's one-cancels-other group.
+    fn cancel_group(&mut self, filled: &Order) {
+        let Some(group) = filled.oco_group else {
+            return;
+        };
+
+        let siblings: Vec<Uuid> = self
+            .market()
+            .orders
+            .iter()
+            .filter(|o| o.oco_group == Some(group) && o.id != filled.id)
+            .map(|o| o.id)
+            .collect();
+        for id in siblings {
+            self.cancel_order(id);
+        }
+    }
+
+    fn cancel_children(&mut self, parent_id: Uuid) {
+        let children: Vec<Uuid> = self
+            .market()
+            .orders
+            .iter()
+            .filter(|o| o.parent_id == Some(parent_id))
+            .map(|o| o.id)
+            .collect();
+        for id in children {
+            self.cancel_order(id);
+        }
+    }
+
+    fn total_balance(&self) -> BigDecimal {
+        self.markets
+            .iter()
+            .flat_map(|m| m.orders.iter())
+            .fold(self.balance.clone(), |total, o| total + &o.margin + &o.fee)
+    }
+
+    /// Value of the positions in the other markets at their latest closes,
+    /// less `rate` of their absolute value.
+    fn other_markets_value(&self, rate: BigDecimal) -> BigDecimal {
+        self.markets
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| *index != self.current)
+            .filter_map(|(_, m)| m.mark_price().map(|price| &m.position * price))
+            .fold(BigDecimal::zero(), |total, value| {
+                let haircut = value.abs() * &rate;
+                total + value - haircut
+            })
+    }
+
+    /// Free margin if the current market held `position` at `price`.
+    fn free_margin_with(
+        &self,
+        balance: &BigDecimal,
+        position: &BigDecimal,
+        price: &BigDecimal,
+    ) -> BigDecimal {
+        let rate = BigDecimal::one() / &self.margin.leverage;
+        let value = position * price;
+        let required = value.abs() * &rate;
+        balance + value - required + self.other_markets_value(rate)
+    }
+
+    /// Part of `order` that would open or extend exposure rather than reduce it.
+    /// Resting orders on the same side claim the position they would close
+    /// first, so several reducing orders cannot flip it unreserved. Members of
+    /// the order's one-cancels-other group claim nothing, as only one fills.
+    fn opening_amount(&self, order: &Order) -> BigDecimal {
+        let is_buy = order.order_type.is_buy();
+        let claimed = self
+            .market()
+            .orders
+            .iter()
+            .filter(|o| o.active && o.id != order.id && o.order_type.is_buy() == is_buy)
+            .filter(|o| o.oco_group.is_none() || o.oco_group != order.oco_group)
+            .fold(BigDecimal::zero(), |total, o| total + &o.amount);
+        let reducible = if is_buy {
+            -&self.market().position
+        } else {
+            self.market().position.clone()
+        } - claimed;
+
+        if reducible <= BigDecimal::zero() {
+            order.amount.clone()
+        } else if reducible >= order.amount {
+            BigDecimal::zero()
+        } else {
+            &order.amount - reducible
+        }
+    }
+
+    /// Stops only trail completed bars, so never a price they could still be
+    /// filled against.
+    fn run_bar(&mut self, candle: &Candle, bar: &Candle, path: &[BigDecimal]) {
+        self.run_path(candle, path);
+
+        let market = self.market_mut();
+        for order in market.orders.iter_mut().filter(|o| o.active) {
+            order.trail(bar, &market.precision);
+        }
+    }
+
+    /// Walks the price path, executing orders and liquidations in the order
+    /// the path reaches them. Orders win ties with a liquidation.
+    fn run_path(&mut self, candle: &Candle, path: &[BigDecimal]) {
+        for segment in path.windows(2) {
+            let mut from = segment[0].clone();
+            let to = &segment[1];
+            let rising = *to >= from;
+
+            while let Some((price, event)) = self.next_event(&from, to, rising) {
+                self.advance(&from, &price);
+
+                match event {
+                    PathEvent::Fill(order_id, fill_price) => {
+                        let Some(pos) = self.market().orders.iter().position(|o| o.id == order_id)
+                        else {
+                            continue;
+                        };
+                        let fill_amount = self.fillable_amount(&self.market().orders[pos]);
+                        if fill_amount < self.market().orders[pos].amount {
+                            let market = self.market_mut();
+                            let part = market.orders[pos].split(&fill_amount, &market.precision);
+                            if self.execute_order(candle, &part, fill_price) {
+                                self.cancel_group(&part);
+                                self.activate_partial_exits(&part);
+                            } else {
+                                self.cancel_order(order_id);
+                            }
+                        } else {
+                            let order = self.market_mut().orders.remove(pos);
+                            if self.execute_order(candle, &order, fill_price) {
+                                self.settle_linked_orders(&order);
+                            } else {
+                                let order_id = order.id;
+                                self.events.push(OrderEvent::Cancelled(order));
+                                self.cancel_children(order_id);
+                            }
+                        }
+                    }
+                    PathEvent::Liquidation => self.liquidate(candle, &price),
+                }
+
+                from = price;
+            }
+
+            self.advance(&from, to);
+        }
+    }
+
+    /// Runs both candle paths and keeps whichever leaves the lower equity at the close.
+    fn run_pessimistic(&mut self, candle: &Candle) -> AppResult<()> {
+        let trades = mem::take(&mut self.trades);
+        let output = mem::take(&mut self.output);
+
+        let start = self.path_state();
+        self.run_bar(candle, candle, &fill::open_high_low_close(candle));
+        let high_first_equity = self.equity()?;
+        let high_first = self.replace_path_state(start);
+        self.run_bar(candle, candle, &fill::open_low_high_close(candle));
+
+        if self.equity()? > high_first_equity {
+            self.replace_path_state(high_first);
+        }
+
+        let new_trades = mem::replace(&mut self.trades, trades);
+        self.trades.extend(new_trades);
+        let new_output = mem::replace(&mut self.output, output);
+        for entry in new_output.logs {
+            self.output.log(entry);
+        }
+
+        Ok(())
+    }
+
+    fn path_state(&self) -> PathState {
+        let market = self.market();
+        PathState {
+            balance: self.balance.clone(),
+            trades: self.trades.clone(),
+            events: self.events.clone(),
+            output: self.output.clone(),
+            position: market.position.clone(),
+            orders: market.orders.clone(),
+            volume_left: market.volume_left.clone(),
+        }
+    }
+
+    fn replace_path_state(&mut self, state: PathState) -> PathState {
+        let market = &mut self.markets[self.current];
+        PathState {
+            balance: mem::replace(&mut self.balance, state.balance),
+            trades: mem::replace(&mut self.trades, state.trades),
+            events: mem::replace(&mut self.events, state.events),
+            output: mem::replace(&mut self.output, state.output),
+            position: mem::replace(&mut market.position, state.position),
+            orders: mem::replace(&mut market.orders, state.orders),
+            volume_left: mem::replace(&mut market.volume_left, state.volume_left),
+        }
+    }
+
+    fn next_event(
+        &self,
+        from: &BigDecimal,
+        to: &BigDecimal,
+        rising: bool,
+    ) -> Option<(BigDecimal, PathEvent)> {
+        let earlier = |a: &BigDecimal, b: &BigDecimal| if rising { a < b } else { a > b };
+
+        let mut next: Option<(BigDecimal, PathEvent)> = None;
+        for order in self.market().orders.iter().filter(|o| o.active) {
+            if self.fillable_amount(order) <= BigDecimal::zero() {
+                continue;
+            }
+            let Some((event, fill_price)) = order.matches(from, to) else {
+                continue;
+            };
+            if next
+                .as_ref()
+                .is_none_or(|(price, _)| earlier(&event, price))
+            {
+                next = Some((event, PathEvent::Fill(order.id, fill_price)));
+            }
+        }
+
+        if let Some(liquidation_price) = self.liquidation_price() {
+            let event = if self.market().position > BigDecimal::zero() {
+                fall(from, to, &liquidation_price)
+            } else {
+                rise(from, to, &liquidation_price)
+            };
+            if let Some(event) = event
+                && next
+                    .as_ref()
+                    .is_none_or(|(price, _)| earlier(&event, price))
+            {
+                next = Some((event, PathEvent::Liquidation));
+            }
+        }
+
+        next
+    }
+
+    fn advance(&mut self, from: &BigDecimal, to: &BigDecimal) {
+        for order in self.market_mut().orders.iter_mut().filter(|o| o.active) {
+            order.arm(from, to);
+        }
+    }
+
+    /// Price at which the market's position breaches the maintenance margin,
+    /// holding the other markets at their latest closes.
+    fn liquidation_price(&self) -> Option<BigDecimal> {
+        if self.market().position.is_zero() {
+            return None;
+        }
+
+        let size = self.market().position.abs();
+        let mmr = &self.margin.maintenance_margin_rate;
+        let balance = self.total_balance() + self.other_markets_value(mmr.clone());
+
+        if self.market().position > BigDecimal::zero() {
+            if balance >= BigDecimal::zero() {
+                return None;
+            }
+            Some(-&balance / (&size * (BigDecimal::one() - mmr)))
+        } else {
+            Some(&balance / (&size * (BigDecimal::one() + mmr)))
+        }
+    }
+
+    /// Closes the market's whole position at `price`. The market's open orders
+    /// are cancelled first, as an exchange would.
+    fn liquidate(&mut self, candle: &Candle, price: &BigDecimal) {
+        let is_long = self.market().position > BigDecimal::zero();
+        let size = self.market().position.abs();
+        let price = self
+            .market()
+            .precision
+            .round_price(price, RoundingMode::HalfUp);
+
+        let order_ids: Vec<Uuid> = self.market().orders.iter().map(|o| o.id).collect();
+        for id in order_ids {
+            self.cancel_order(id);
+        }
+
+        let notional = &price * &size;
+        let fee = self
+            .market()
+            .precision
+            .round_amount(&(&notional * &self.market().fees.taker), RoundingMode::Up);
+
+        let trade_type = if is_long {
+            self.balance += &notional - &fee;
+            TradeType::LiquidationSell
+        } else {
+            self.balance -= &notional + &fee;
+            TradeType::LiquidationBuy
+        };
+        self.market_mut().position = BigDecimal::zero();
+
+        self.trades.push(Trade {
+            timestamp: candle.timestamp,
+            symbol: self.market().symbol.clone(),
+            trade_type,
+            price,
+            amount: size,
+            fee,
+            slippage: BigDecimal::zero(),
+            slippage_model: SlippageModel::None,
+            order_id: None,
+            profit: None,
+        });
+    }
+
+    fn fillable_amount(&self, order: &Order) -> BigDecimal {
+        if order.order_type.is_taker() {
+            let liquid = self
+                .market()
+                .candles
+                .last()
+                .is_none_or(|candle| self.slippage_model.has_liquidity(candle));
+            return if liquid {
+                order.amount.clone()
+            } else {
+                BigDecimal::zero()
+            };
+        }
+
+        match &self.market().volume_left {
+            Some(volume_left) => self
+                .market()
+                .precision
+                .round_amount(volume_left, RoundingMode::Down)
+                .min(order.amount.clone()),
+            None => order.amount.clone(),
+        }
+    }
+
+    fn check_liquidity(&self, candle: &Candle) -> AppResult<()> {
+        if self.slippage_model.has_liquidity(candle) {
+            Ok(())
+        } else {
+            Err(AppError::Strategy(
+                "No volume in the latest candle to fill a market order against".into(),
+            ))
+        }
+    }
+
+    /// Moves a market execution's price against the order by the configured
+    /// slippage, but never beyond the candle's high or low.
+    fn slip(
+        &self,
         candle: &Candle,
         price: &BigDecimal,
         amount: &BigDecimal,
-        fee: &BigDecimal,
-    ) {
-        let proceeds = price * amount;
-        self.balance += &proceeds;
+        is_buy: bool,
+    ) -> BigDecimal {
+        let slippage = self.slippage_model.slippage(candle, price, amount);
+        if slippage.is_zero() {
+            price.clone()
+        } else if is_buy {
+            self.market()
+                .precision
+                .round_price(&(price + slippage), RoundingMode::Up)
+                .min(candle.high.clone().max(price.clone()))
+        } else {
+            self.market()
+                .precision
+                .round_price(&(price - slippage), RoundingMode::Down)
+                .max(candle.low.clone().min(price.clone()))
+        }
+    }
+
+    /// Returns false, cancelling the order, if filling it would breach the
+    /// margin requirement.
+    fn execute_order(&mut self, candle: &Candle, order: &Order, price: BigDecimal) -> bool {
+        let is_taker = order.order_type.is_taker();
+        let (price, slippage, slippage_model) = if is_taker {
+            let quoted = price;
+            let price = self.slip(candle, &quoted, &order.amount, order.order_type.is_buy());
+            let slippage = (&price - &quoted).abs();
+            (price, slippage, self.slippage_model.clone())
+        } else {
+            (price, BigDecimal::zero(), SlippageModel::None)
+        };
+
+        let notional = &price * &order.amount;
+        let fee = if is_taker {
+            self.market()
+                .precision
+                .round_amount(&(&notional * &self.market().fees.taker), RoundingMode::Up)
+        } else {
+            order.fee.clone()
+        };
+
+        let released = &order.margin + &order.fee - &fee;
+        let (balance, position) = if order.order_type.is_buy() {
+            (
+                &self.balance + &released - &notional,
+                &self.market().position + &order.amount,
+            )
+        } else {
+            (
+                &self.balance + &released + &notional,
+                &self.market().position - &order.amount,
+            )
+        };
+
+        if self.free_margin_with(&balance, &position, &price) < BigDecimal::zero() {
+            self.balance += &order.margin + &order.fee;
+            self.log(
+                LogLevel::Warn,
+                format!(
+                    "Cancelled {:?} order {} at {}: insufficient margin to fill",
+                    order.order_type, order.id, price
+                ),
+            );
+            return false;
+        }
+
+        self.balance = balance;
+        self.market_mut().position = position;
+        if !is_taker && let Some(volume_left) = &mut self.market_mut().volume_left {
+            *volume_left -= &order.amount;
+        }
 
         let trade = Trade {
             timestamp: candle.timestamp,
-            trade_type: TradeType::LimitSell,
-            price: price.clone(),
-            amount: amount.clone(),
-            fee: fee.clone(),
+            symbol: self.market().symbol.clone(),
+            trade_type: order.order_type.trade_type(),
+            price,
+            amount: order.amount.clone(),
+            fee,
+            slippage,
+            slippage_model,
+            order_id: Some(order.id),
             profit: None,
         };
-
+        self.events
+            .push(OrderEvent::Filled(order.clone(), Box::new(trade.clone())));
         self.trades.push(trade);
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn decimal(value: &str) -> BigDecimal {
+        BigDecimal::from_str(value).unwrap()
+    }
+
+    fn candle(minute: i64, open: &str, high: &str, low: &str, close: &str) -> Candle {
+        Candle {
+            timestamp: DateTime::from_timestamp(minute * 60, 0).unwrap(),
+            exchange: "test".into(),
+            symbol: "BTC/USDT".into(),
+            timeframe: Timeframe::M1,
+            open: decimal(open),
+            high: decimal(high),
+            low: decimal(low),
+            close: decimal(close),
+            volume: decimal("1000"),
+        }
+    }
+
+    fn market(symbol: &str) -> Market<'static> {
+        Market::new(
+            symbol.into(),
+            TradingFees {
+                maker: BigDecimal::zero(),
+                taker: BigDecimal::zero(),
+            },
+            MarketPrecision {
+                price_precision: decimal("0.01"),
+                amount_precision: decimal("0.001"),
+            },
+        )
+    }
+
+    /// Strategy that only records what its hooks were told.
+    #[derive(Default)]
+    struct Hooks {
+        filled: Vec<Uuid>,
+        cancelled: Vec<Uuid>,
+        ended: bool,
+    }
+
+    impl Strategy for Hooks {
+        fn tick(&mut self, _context: &mut StrategyContext) -> AppResult<()> {
+            Ok(())
+        }
+
+        fn on_order_filled(
+            &mut self,
+            _context: &mut StrategyContext,
+            order: &Order,
+            trade: &Trade,
+        ) -> AppResult<()> {
+            assert_eq!(trade.order_id, Some(order.id));
+            self.filled.push(order.id);
+            Ok(())
+        }
+
+        fn on_order_cancelled(
+            &mut self,
+            _context: &mut StrategyContext,
+            order: &Order,
+        ) -> AppResult<()> {
+            self.cancelled.push(order.id);
+            Ok(())
+        }
+
+        fn on_end(&mut self, _context: &StrategyContext) -> AppResult<()> {
+            self.ended = true;
+            Ok(())
+        }
+    }
+
+    fn context(margin: MarginConfig) -> StrategyContext<'static> {
+        StrategyContext::new(
+            decimal("1000"),
+            vec![market("BTC/USDT")],
+            Timeframe::M1,
+            margin,
+            FillModel::default(),
+            SlippageModel::default(),
+            None,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn candle_window_keeps_the_latest_lookback() {
+        let candles: Vec<_> = (0..5).map(|m| candle(m, "1", "1", "1", "1")).collect();
+        let mut window = CandleWindow::from(&candles[..2]);
+        for candle in &candles[2..] {
+            window.push(candle.clone(), Some(2));
+        }
+        assert_eq!(window.len(), 2);
+        assert_eq!(window[0].timestamp, candles[3].timestamp);
+        assert_eq!(window[1].timestamp, candles[4].timestamp);
+
+        let mut unbounded = CandleWindow::default();
+        for candle in &candles {
+            unbounded.push(candle.clone(), None);
+        }
+        assert_eq!(unbounded.len(), 5);
+    }
+
+    #[test]
+    fn market_sell_opens_short_within_margin() {
+        let candles = [candle(0, "100", "100", "100", "100")];
+        let mut ctx = context(MarginConfig::default());
+        ctx.market_mut().candles = candles.as_slice().into();
+
+        ctx.market_sell(&decimal("5")).unwrap();
+        assert_eq!(ctx.position(), decimal("-5"));
+        assert_eq!(ctx.balance(), decimal("1500"));
+
+        assert!(ctx.market_sell(&decimal("6")).is_err());
+    }
+
+    #[test]
+    fn market_orders_slip_against_the_trader() {
+        let candles = [candle(0, "100", "101", "99", "100")];
+        let mut ctx = context(MarginConfig::default());
+        ctx.slippage_model = SlippageModel::FixedBps { bps: decimal("50") };
+        ctx.market_mut().candles = candles.as_slice().into();
+
+        ctx.market_buy(&decimal("1")).unwrap();
+        ctx.market_sell(&decimal("1")).unwrap();
+
+        let (buy, sell) = (&ctx.trades()[0], &ctx.trades()[1]);
+        assert_eq!(buy.price, decimal("100.5"));
+        assert_eq!(sell.price, decimal("99.5"));
+        assert_eq!(sell.slippage, decimal("0.5"));
+        assert_eq!(
+            sell.slippage_model,
+            SlippageModel::FixedBps { bps: decimal("50") }
+        );
+        assert_eq!(ctx.balance(), decimal("999"));
+    }
+
+    #[test]
+    fn slippage_stops_at_the_candle_range() {
+        let candles = [candle(0, "100", "101", "99", "100")];
+        let mut ctx = context(MarginConfig::default());
+        ctx.slippage_model = SlippageModel::FixedBps {
+            bps: decimal("20000"),
+        };
+        ctx.market_mut().candles = candles.as_slice().into();
+
+        ctx.market_buy(&decimal("1")).unwrap();
+        ctx.market_sell(&decimal("1")).unwrap();
+
+        assert_eq!(ctx.trades()[0].price, decimal("101"));
+        assert_eq!(ctx.trades()[1].price, decimal("99"));
+        assert_eq!(ctx.trades()[1].slippage, decimal("1"));
+    }
+
+    #[test]
+    fn marketable_limit_orders_slip_no_further_than_their_limit() {
+        let candles = [candle(0, "100", "101", "99", "100")];
+        let mut ctx = context(MarginConfig::default());
+        ctx.slippage_model = SlippageModel::FixedBps { bps: decimal("50") };
+        ctx.market_mut().candles = candles.as_slice().into();
+
+        assert!(
+            ctx.limit_buy(&decimal("100.2"), &decimal("1"))
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            ctx.limit_sell(&decimal("99.9"), &decimal("1"))
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(ctx.trades()[0].price, decimal("100.2"));
+        assert_eq!(ctx.trades()[1].price, decimal("99.9"));
+    }
+
+    #[test]
+    fn volume_slippage_does_not_fill_in_candles_without_volume() {
+        let mut candles = [
+            candle(0, "100", "100", "100", "100"),
+            candle(1, "100", "100", "90", "95"),
+            candle(2, "95", "95", "90", "92"),
+        ];
+        candles[1].volume = BigDecimal::zero();
+        let mut ctx = context(MarginConfig::default());
+        ctx.slippage_model = SlippageModel::VolumeParticipation {
+            impact: decimal("0.1"),
+        };
+        ctx.market_mut().candles = (&candles[..1]).into();
+        ctx.market_buy(&decimal("1")).unwrap();
+        ctx.stop_market_sell(&decimal("95"), &decimal("1")).unwrap();
+
+        ctx.market_mut().candles = (&candles[..2]).into();
+        ctx.before(&mut Hooks::default()).unwrap();
+        assert!(ctx.market_buy(&decimal("1")).is_err());
+        assert_eq!(ctx.trades().len(), 1);
+        assert_eq!(ctx.orders().len(), 1);
+
+        ctx.market_mut().candles = candles.as_slice().into();
+        ctx.before(&mut Hooks::default()).unwrap();
+        assert!(ctx.position().is_zero());
+        assert!(ctx.orders().is_empty());
+    }
+
+    #[test]
+    fn limit_fills_are_capped_by_candle_volume() {
+        let candles = [
+            candle(0, "100", "100", "100", "100"),
+            candle(1, "100", "100", "94", "96"),
+            candle(2, "96", "97", "93", "95"),
+        ];
+        let mut ctx = context(MarginConfig::default());
+        ctx.liquidity = Some(LiquidityConfig {
+            max_volume_fraction: decimal("0.004"),
+        });
+        ctx.market_mut().candles = (&candles[..1]).into();
+        let order_id = ctx.limit_buy(&decimal("95"), &decimal("6")).unwrap();
+
+        ctx.market_mut().candles = (&candles[..2]).into();
+        ctx.before(&mut Hooks::default()).unwrap();
+        assert_eq!(ctx.position(), decimal("4"));
+        assert_eq!(ctx.orders()[0].amount, decimal("2"));
+        assert_eq!(ctx.orders()[0].filled, decimal("4"));
+        assert_eq!(ctx.balance(), decimal("430"));
+
+        ctx.market_mut().candles = candles.as_slice().into();
+        ctx.before(&mut Hooks::default()).unwrap();
+        assert_eq!(ctx.position(), decimal("6"));
+        assert!(ctx.orders().is_empty());
+        assert!(ctx.trades().iter().all(|t| t.order_id == order_id));
+        assert_eq!(ctx.balance(), decimal("430"));
+    }
+
+    #[test]
+    fn reducing_orders_claim_the_position_they_close() {
+        let candles = [candle(0, "100", "100", "100", "100")];
+        let mut ctx = context(MarginConfig::default());
+        ctx.market_mut().candles = candles.as_slice().into();
+        ctx.market_buy(&decimal("2")).unwrap();
+
+        ctx.limit_sell(&decimal("110"), &decimal("2")).unwrap();
+        assert!(ctx.orders()[0].margin.is_zero());
+        assert_eq!(ctx.balance(), decimal("800"));
+
+        ctx.limit_sell(&decimal("120"), &decimal("2")).unwrap();
+        assert_eq!(ctx.orders()[1].margin, decimal("240"));
+        assert_eq!(ctx.balance(), decimal("560"));
+    }
+
+    #[test]
+    fn fills_beyond_margin_are_cancelled_with_a_warning() {
+        let candles = [
+            candle(0, "100", "100", "100", "100"),
+            candle(1, "100", "115", "100", "112"),
+        ];
+        let mut ctx = context(MarginConfig::default());
+        let mut hooks = Hooks::default();
+        ctx.slippage_model = SlippageModel::FixedBps {
+            bps: decimal("200"),
+        };
+        ctx.market_mut().candles = (&candles[..1]).into();
+        let stop = ctx
+            .stop_market_buy(&decimal("110"), &decimal("9"))
+            .unwrap()
+            .unwrap();
+
+        ctx.market_mut().candles = candles.as_slice().into();
+        ctx.before(&mut hooks).unwrap();
+        assert!(ctx.trades().is_empty());
+        assert!(ctx.orders().is_empty());
+        assert_eq!(ctx.balance(), decimal("1000"));
+        assert_eq!(hooks.cancelled, [stop]);
+        assert_eq!(ctx.output.logs.len(), 1);
+        assert_eq!(ctx.output.logs[0].level, LogLevel::Warn);
+    }
+
+    #[test]
+    fn symbols_share_one_balance() {
+        let btc = [candle(0, "100", "100", "100", "100")];
+        let eth = [candle(0, "10", "10", "10", "10")];
+        let mut ctx = StrategyContext::new(
+            decimal("1000"),
+            vec![market("BTC/USDT"), market("ETH/USDT")],
+            Timeframe::M1,
+            MarginConfig::default(),
+            FillModel::default(),
+            SlippageModel::default(),
+            None,
+        )
+        .unwrap();
+        ctx.markets[0].candles = btc.as_slice().into();
+        ctx.markets[1].candles = eth.as_slice().into();
+
+        ctx.market_buy(&decimal("6")).unwrap();
+        {
+            let mut eth_ctx = ctx.symbol("ETH/USDT").unwrap();
+            assert!(eth_ctx.market_buy(&decimal("50")).is_err());
+            eth_ctx.market_sell(&decimal("30")).unwrap();
+            assert_eq!(eth_ctx.trades()[1].symbol, "ETH/USDT");
+        }
+
+        assert_eq!(ctx.current_symbol(), "BTC/USDT");
+        assert_eq!(ctx.position(), decimal("6"));
+        assert_eq!(ctx.balance(), decimal("700"));
+        assert_eq!(ctx.free_margin().unwrap(), decimal("100"));
+        assert_eq!(ctx.equity().unwrap(), decimal("1000"));
+    }
+
+    #[test]
+    fn leveraged_long_is_liquidated_at_liquidation_price() {
+        let candles = [
+            candle(0, "100", "100", "100", "100"),
+            candle(1, "100", "100", "80", "90"),
+        ];
+        let mut ctx = context(MarginConfig {
+            leverage: decimal("5"),
+            maintenance_margin_rate: BigDecimal::zero(),
+        });
+        ctx.market_mut().candles = (&candles[..1]).into();
+        ctx.market_buy(&decimal("50")).unwrap();
+        assert_eq!(ctx.balance(), decimal("-4000"));
+
+        ctx.market_mut().candles = candles.as_slice().into();
+        ctx.before(&mut Hooks::default()).unwrap();
+
+        let liquidation = ctx.trades().last().unwrap();
+        assert!(matches!(liquidation.trade_type, TradeType::LiquidationSell));
+        assert_eq!(liquidation.price, decimal("80"));
+        assert!(ctx.position().is_zero());
+        assert!(ctx.balance().is_zero());
+    }
+
+    #[test]
+    fn short_survives_candle_below_liquidation_price() {
+        let candles = [
+            candle(0, "100", "100", "100", "100"),
+            candle(1, "100", "150", "95", "120"),
+        ];
+        let mut ctx = context(MarginConfig::default());
+        ctx.market_mut().candles = (&candles[..1]).into();
+        ctx.market_sell(&decimal("10")).unwrap();
+
+        ctx.market_mut().candles = candles.as_slice().into();
+        ctx.before(&mut Hooks::default()).unwrap();
+        assert_eq!(ctx.position(), decimal("-10"));
+    }
+
+    #[test]
+    fn stop_market_sell_fills_at_trigger_or_gap_open() {
+        let candles = [
+            candle(0, "100", "100", "100", "100"),
+            candle(1, "100", "101", "94", "96"),
+            candle(2, "90", "91", "85", "88"),
+        ];
+        let mut ctx = context(MarginConfig::default());
+        ctx.market_mut().candles = (&candles[..1]).into();
+        ctx.market_buy(&decimal("2")).unwrap();
+        ctx.stop_market_sell(&decimal("95"), &decimal("1")).unwrap();
+        ctx.stop_market_sell(&decimal("92"), &decimal("1")).unwrap();
+
+        ctx.market_mut().candles = (&candles[..2]).into();
+        ctx.before(&mut Hooks::default()).unwrap();
+        let stop = ctx.trades().last().unwrap();
+        assert!(matches!(stop.trade_type, TradeType::StopMarketSell));
+        assert_eq!(stop.price, decimal("95"));
+
+        ctx.market_mut().candles = candles.as_slice().into();
+        ctx.before(&mut Hooks::default()).unwrap();
+        assert_eq!(ctx.trades().last().unwrap().price, decimal("90"));
+        assert!(ctx.position().is_zero());
+    }
+
+    #[test]
+    fn trailing_stop_sell_follows_highs() {
+        let candles = [
+            candle(0, "100", "100", "100", "100"),
+            candle(1, "100", "120", "99", "118"),
+            candle(2, "118", "119", "109", "110"),
+        ];
+        let mut ctx = context(MarginConfig::default());
+        ctx.market_mut().candles = (&candles[..1]).into();
+        ctx.market_buy(&decimal("1")).unwrap();
+        ctx.trailing_stop_sell(&decimal("10"), &decimal("1"))
+            .unwrap();
+
+        ctx.market_mut().candles = (&candles[..2]).into();
+        ctx.before(&mut Hooks::default()).unwrap();
+        assert_eq!(ctx.orders()[0].trigger_price, Some(decimal("110")));
+
+        ctx.market_mut().candles = candles.as_slice().into();
+        ctx.before(&mut Hooks::default()).unwrap();
+        let stop = ctx.trades().last().unwrap();
+        assert!(matches!(stop.trade_type, TradeType::TrailingStopSell));
+        assert_eq!(stop.price, decimal("110"));
+    }
+
+    #[test]
+    fn trailing_stop_tightens_within_a_candle_on_lower_timeframe_bars() {
+        let candles = [
+            candle(0, "100", "100", "100", "100"),
+            candle(1, "100", "120", "99", "105"),
+        ];
+        let sub_candles = [
+            candle(1, "100", "120", "100", "118"),
+            candle(1, "118", "118", "99", "105"),
+        ];
+        let mut ctx = context(MarginConfig::default());
+        ctx.fill_model = FillModel::LowerTimeframe(Timeframe::M1);
+        ctx.market_mut().candles = (&candles[..1]).into();
+        ctx.market_buy(&decimal("1")).unwrap();
+        ctx.trailing_stop_sell(&decimal("10"), &decimal("1"))
+            .unwrap();
+
+        ctx.market_mut().candles = candles.as_slice().into();
+        ctx.market_mut().sub_candles = sub_candles.as_slice().into();
+        ctx.before(&mut Hooks::default()).unwrap();
+        let stop = ctx.trades().last().unwrap();
+        assert!(matches!(stop.trade_type, TradeType::TrailingStopSell));
+        assert_eq!(stop.price, decimal("110"));
+        assert!(ctx.position().is_zero());
+    }
+
+    #[test]
+    fn fill_model_decides_which_exit_of_a_wide_candle_fills() {
+        let candles = [
+            candle(0, "100", "100", "100", "100"),
+            candle(1, "100", "106", "94", "100"),
+        ];
+        let cases = [
+            (FillModel::OpenHighLowClose, "105"),
+            (FillModel::OpenLowHighClose, "95"),
+            (FillModel::Pessimistic, "95"),
+        ];
+
+        for (fill_model, exit_price) in cases {
+            let mut ctx = context(MarginConfig::default());
+            ctx.fill_model = fill_model;
+            ctx.market_mut().candles = (&candles[..1]).into();
+            ctx.market_buy(&decimal("1")).unwrap();
+            let stop = ctx.stop_market_sell(&decimal("95"), &decimal("1")).unwrap();
+            let take_profit = ctx
+                .take_profit_sell(&decimal("105"), &decimal("1"))
+                .unwrap();
+            ctx.one_cancels_other(&[stop.unwrap(), take_profit.unwrap()])
+                .unwrap();
+
+            ctx.market_mut().candles = candles.as_slice().into();
+            ctx.before(&mut Hooks::default()).unwrap();
+            assert_eq!(ctx.trades().len(), 2);
+            assert_eq!(ctx.trades()[1].price, decimal(exit_price));
+            assert!(ctx.position().is_zero());
+            assert!(ctx.orders().is_empty());
+        }
+    }
+
+    #[test]
+    fn bracket_exits_activate_on_entry_and_cancel_each_other() {
+        let candles = [
+            candle(0, "100", "100", "100", "100"),
+            candle(1, "100", "100", "95", "97"),
+            candle(2, "97", "111", "96", "108"),
+        ];
+        let mut ctx = context(MarginConfig::default());
+        ctx.market_mut().candles = (&candles[..1]).into();
+        let bracket = ctx
+            .bracket_buy(
+                Some(&decimal("96")),
+                &decimal("1"),
+                &decimal("90"),
+                &decimal("110"),
+            )
+            .unwrap();
+        assert!(bracket.entry.is_some());
+        assert_eq!(ctx.balance(), decimal("904"));
+        assert_eq!(ctx.orders().iter().filter(|o| !o.active).count(), 2);
+
+        ctx.market_mut().candles = (&candles[..2]).into();
+        ctx.before(&mut Hooks::default()).unwrap();
+        assert_eq!(ctx.position(), decimal("1"));
+        assert!(ctx.orders().iter().all(|o| o.active));
+
+        ctx.market_mut().candles = candles.as_slice().into();
+        ctx.before(&mut Hooks::default()).unwrap();
+        let exit = ctx.trades().last().unwrap();
+        assert!(matches!(exit.trade_type, TradeType::TakeProfitSell));
+        assert!(ctx.orders().is_empty());
+        assert_eq!(ctx.balance(), decimal("1014"));
+    }
+
+    #[test]
+    fn partly_filled_bracket_entries_protect_the_filled_amount() {
+        let candles = [
+            candle(0, "100", "100", "100", "100"),
+            candle(1, "100", "100", "94", "96"),
+            candle(2, "96", "97", "89", "92"),
+        ];
+        let mut ctx = context(MarginConfig::default());
+        ctx.liquidity = Some(LiquidityConfig {
+            max_volume_fraction: decimal("0.004"),
+        });
+        ctx.market_mut().candles = (&candles[..1]).into();
+        let bracket = ctx
+            .bracket_buy(
+                Some(&decimal("95")),
+                &decimal("6"),
+                &decimal("90"),
+                &decimal("110"),
+            )
+            .unwrap();
+
+        ctx.market_mut().candles = (&candles[..2]).into();
+        ctx.before(&mut Hooks::default()).unwrap();
+        assert_eq!(ctx.position(), decimal("4"));
+        let active: Vec<_> = ctx.orders().iter().filter(|o| o.active).collect();
+        assert_eq!(active.len(), 3);
+        for exit in active.iter().filter(|o| Some(o.id) != bracket.entry) {
+            assert_eq!(exit.amount, decimal("4"));
+            assert_ne!(exit.oco_group, Some(bracket.oco_group));
+        }
+        let waiting = ctx.orders().iter().filter(|o| !o.active);
+        assert!(waiting.map(|o| &o.amount).all(|amount| *amount == decimal("2")));
+
+        ctx.market_mut().candles = candles.as_slice().into();
+        ctx.before(&mut Hooks::default()).unwrap();
+        let stops: Vec<_> = ctx
+            .trades()
+            .iter()
+            .filter(|t| matches!(t.trade_type, TradeType::StopMarketSell))
+            .map(|t| t.amount.clone())
+            .collect();
+        assert_eq!(stops, [decimal("4"), decimal("2")]);
+        assert!(ctx.position().is_zero());
+        assert!(ctx.orders().is_empty());
+    }
+
+    #[test]
+    fn bracket_entry_is_undone_when_its_exits_cannot_be_reserved() {
+        let candles = [candle(0, "100", "100", "100", "100")];
+        let mut ctx = context(MarginConfig::default());
+        ctx.market_mut().fees.taker = decimal("0.001");
+        ctx.market_mut().candles = candles.as_slice().into();
+
+        let result = ctx.bracket_buy(None, &decimal("9.98"), &decimal("90"), &decimal("110"));
+        assert!(result.is_err());
+        assert!(ctx.position().is_zero());
+        assert!(ctx.trades().is_empty());
+        assert!(ctx.orders().is_empty());
+        assert_eq!(ctx.balance(), decimal("1000"));
+
+        let bracket = ctx
+            .bracket_buy(None, &decimal("9"), &decimal("90"), &decimal("110"))
+            .unwrap();
+        assert!(bracket.entry.is_none());
+        assert_eq!(ctx.position(), decimal("9"));
+        assert!(ctx.orders().iter().all(|o| o.active));
+    }
+
+    #[test]
+    fn hooks_report_fills_and_cancellations_made_by_the_backtest() {
+        let candles = [
+            candle(0, "100", "100", "100", "100"),
+            candle(1, "100", "100", "95", "97"),
+            candle(2, "97", "111", "96", "108"),
+        ];
+        let mut ctx = context(MarginConfig::default());
+        let mut hooks = Hooks::default();
+        ctx.market_mut().candles = (&candles[..1]).into();
+        let bracket = ctx
+            .bracket_buy(
+                Some(&decimal("96")),
+                &decimal("1"),
+                &decimal("90"),
+                &decimal("110"),
+            )
+            .unwrap();
+        let ignored = ctx
+            .limit_buy(&decimal("50"), &decimal("1"))
+            .unwrap()
+            .unwrap();
+        ctx.cancel_order(ignored);
+
+        ctx.market_mut().candles = (&candles[..2]).into();
+        ctx.before(&mut hooks).unwrap();
+        assert_eq!(hooks.filled, [bracket.entry.unwrap()]);
+        assert!(hooks.cancelled.is_empty());
+
+        ctx.market_mut().candles = candles.as_slice().into();
+        ctx.before(&mut hooks).unwrap();
+        assert_eq!(hooks.filled[1], bracket.take_profit);
+        assert_eq!(hooks.cancelled, [bracket.stop_loss]);
+
+        let resting = ctx
+            .limit_buy(&decimal("50"), &decimal("1"))
+            .unwrap()
+            .unwrap();
+        ctx.end(&mut hooks).unwrap();
+        assert_eq!(hooks.cancelled[1], resting);
+        assert!(hooks.ended);
     }
 }
@@ -0,0 +1,112 @@
+use crate::errors::{AppError, AppResult};
+use crate::models::{Candle, Timeframe};
+use bigdecimal::BigDecimal;
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+/// Order in which a candle's prices are assumed to have traded when matching
+/// resting orders.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum FillModel {
+    /// Open, high, low, close.
+    #[default]
+    OpenHighLowClose,
+    /// Open, low, high, close.
+    OpenLowHighClose,
+    /// Simulates both paths and keeps the one that leaves the lower equity.
+    Pessimistic,
+    /// Walks the lower-timeframe candles inside each candle.
+    LowerTimeframe(Timeframe),
+}
+
+impl FillModel {
+    pub fn validate(&self, timeframe: Timeframe) -> AppResult<()> {
+        if let FillModel::LowerTimeframe(lower) = self
+            && lower.to_ms() >= timeframe.to_ms()
+        {
+            return Err(AppError::BadRequest(format!(
+                "Fill timeframe {} must be shorter than the backtest timeframe {}",
+                lower, timeframe
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// Waypoints of a single candle. Bullish candles are assumed to dip first,
+/// bearish candles to rally first.
+pub(crate) fn candle_path(candle: &Candle) -> Vec<BigDecimal> {
+    if candle.close >= candle.open {
+        open_low_high_close(candle)
+    } else {
+        open_high_low_close(candle)
+    }
+}
+
+pub(crate) fn open_high_low_close(candle: &Candle) -> Vec<BigDecimal> {
+    vec![
+        candle.open.clone(),
+        candle.high.clone(),
+        candle.low.clone(),
+        candle.close.clone(),
+    ]
+}
+
+pub(crate) fn open_low_high_close(candle: &Candle) -> Vec<BigDecimal> {
+    vec![
+        candle.open.clone(),
+        candle.low.clone(),
+        candle.high.clone(),
+        candle.close.clone(),
+    ]
+}
+
+/// Paths of the lower-timeframe candles inside `candle`, each paired with
+/// its candle and starting where the previous one ended. Falls back to the
+/// candle's own path when none are available.
+pub(crate) fn lower_timeframe_paths(
+    candle: &Candle,
+    sub_candles: &[Candle],
+) -> Vec<(Vec<BigDecimal>, Candle)> {
+    if sub_candles.is_empty() {
+        return vec![(candle_path(candle), candle.clone())];
+    }
+
+    let mut from = candle.open.clone();
+    let mut paths = Vec::with_capacity(sub_candles.len());
+    for sub_candle in sub_candles {
+        let mut path = vec![from];
+        path.extend(candle_path(sub_candle));
+        from = sub_candle.close.clone();
+        paths.push((path, sub_candle.clone()));
+    }
+    if let Some((path, _)) = paths.last_mut() {
+        path.push(candle.close.clone());
+    }
+    paths
+}
+
+/// Where a segment moving from `from` to `to` first reaches `level` or above.
+pub(crate) fn rise(from: &BigDecimal, to: &BigDecimal, level: &BigDecimal) -> Option<BigDecimal> {
+    if from >= level {
+        Some(from.clone())
+    } else if to >= level {
+        Some(level.clone())
+    } else {
+        None
+    }
+}
+
+/// Where a segment moving from `from` to `to` first reaches `level` or below.
+pub(crate) fn fall(from: &BigDecimal, to: &BigDecimal, level: &BigDecimal) -> Option<BigDecimal> {
+    if from <= level {
+        Some(from.clone())
+    } else if to <= level {
+        Some(level.clone())
+    } else {
+        None
+    }
+}
@@ -1,42 +1,554 @@
-use crate::errors::AppResult;
-use crate::strategy::Strategy;
-use libloading::{Library, Symbol};
-use std::{
-    ops::{Deref, DerefMut},
-    path::PathBuf,
+use crate::errors::{AppError, AppResult};
+use crate::models::Timeframe;
+use crate::strategy::plugin::{
+    self, ABI_VERSION, ABI_VERSION_FUNCTION_NAME, BUILD_ID_FUNCTION_NAME, CREATE_FUNCTION_NAME,
+    DESTROY_FUNCTION_NAME, FREE_STRING_FUNCTION_NAME, LOOKBACK_FUNCTION_NAME,
+    ON_END_FUNCTION_NAME, ON_ORDER_CANCELLED_FUNCTION_NAME, ON_ORDER_FILLED_FUNCTION_NAME,
+    ON_START_FUNCTION_NAME, PARAMETERS_FUNCTION_NAME, PluginAbiVersion, PluginBuildId,
+    PluginCreate, PluginDestroy, PluginFreeString, PluginOnOrderCancelled, PluginOnEnd, PluginOnOrderFilled,
+    PluginOnStart, PluginParameters, PluginQuery, PluginStrategy, PluginTick,
+    TICK_FUNCTION_NAME, TIMEFRAMES_FUNCTION_NAME,
 };
+#[cfg(unix)]
+use crate::strategy::runner::Runner;
+use crate::strategy::{Order, Strategy, StrategyContext, Trade};
+use libloading::{Library, Symbol};
+use serde::de::DeserializeOwned;
+use std::ffi::{CStr, CString, c_char};
+use std::path::{Path, PathBuf};
+use std::process::Child;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-const PLUGIN_CREATE_FUNCTION_NAME: &'static str = "_plugin_create";
-
+/// A strategy loaded from a plugin, into the server or into a runner process.
+/// Every call into the plugin is timed by its [`StrategyWatch`].
 pub struct StrategyHandle {
-    strategy: Box<dyn Strategy>,
+    plugin: Plugin,
+    parameters_schema: Option<serde_json::Value>,
+    timeframes: Vec<Timeframe>,
+    lookback: Option<usize>,
+    watch: Arc<StrategyWatch>,
+}
+
+enum Plugin {
+    Library(PluginLibrary),
+    #[cfg(unix)]
+    Runner(Runner),
+}
+
+/// A strategy built by a plugin loaded into this process, called through the
+/// plugin's exports. They catch the strategy's panics on the plugin side,
+/// since a panic cannot unwind from the plugin into the server.
+struct PluginLibrary {
+    strategy: *mut PluginStrategy,
+    exports: Exports,
+    timeframes: Vec<Timeframe>,
+    lookback: Option<usize>,
     _lib: Library, // Keep the library loaded
 }
 
+struct Exports {
+    free_string: PluginFreeString,
+    destroy: PluginDestroy,
+    on_start: PluginOnStart,
+    tick: PluginTick,
+    on_order_filled: PluginOnOrderFilled,
+    on_order_cancelled: PluginOnOrderCancelled,
+    on_end: PluginOnEnd,
+}
+
+// The plugin's strategy is `Send` like any other, the library only holds it
+// through a pointer.
+unsafe impl Send for PluginLibrary {}
+
 impl StrategyHandle {
-    pub fn try_from_path(path: &PathBuf) -> AppResult<Self> {
+    /// Loads the plugin and builds its strategy from `parameters`, or from its
+    /// defaults when none are given. Plugins built for another plugin
+    /// interface, fettle version or compiler are rejected before any of their
+    /// code runs.
+    pub fn try_from_path(
+        path: &PathBuf,
+        parameters: Option<&serde_json::Value>,
+    ) -> AppResult<Self> {
+        let watch = Arc::new(StrategyWatch::default());
         unsafe {
             let lib = Library::new(path)?;
-            let constructor: Symbol<fn() -> *mut dyn Strategy> =
-                lib.get(PLUGIN_CREATE_FUNCTION_NAME.as_bytes())?;
-            let strategy = Box::from_raw(constructor());
-            Ok(Self {
+            check_abi(&lib)?;
+
+            let free_string: Symbol<PluginFreeString> =
+                lib.get(FREE_STRING_FUNCTION_NAME.as_bytes())?;
+            let take_string = |string: *mut c_char| {
+                (!string.is_null()).then(|| {
+                    let owned = CStr::from_ptr(string).to_string_lossy().into_owned();
+                    free_string(string);
+                    owned
+                })
+            };
+
+            let parameters_fn: Symbol<PluginParameters> =
+                lib.get(PARAMETERS_FUNCTION_NAME.as_bytes())?;
+            let mut schema = std::ptr::null_mut();
+            if let Some(error) = take_string(parameters_fn(&mut schema)) {
+                return Err(AppError::Strategy(error));
+            }
+            let parameters_schema = take_string(schema)
+                .map(|schema| serde_json::from_str(&schema))
+                .transpose()
+                .map_err(|e| AppError::Strategy(format!("Invalid parameter schema: {}", e)))?;
+
+            let takes_parameters = parameters_schema.is_some();
+            let parameters = match parameters {
+                Some(parameters) if takes_parameters => parameters.to_string(),
+                Some(parameters) if parameters.as_object().is_some_and(|o| !o.is_empty()) => {
+                    return Err(AppError::Strategy(
+                        "Strategy does not take parameters".into(),
+                    ));
+                }
+                _ => String::new(),
+            };
+            let parameters = CString::new(parameters)
+                .map_err(|e| AppError::Strategy(format!("Invalid strategy parameters: {}", e)))?;
+
+            let exports = Exports {
+                free_string: *free_string,
+                destroy: *lib.get::<PluginDestroy>(DESTROY_FUNCTION_NAME.as_bytes())?,
+                on_start: *lib.get::<PluginOnStart>(ON_START_FUNCTION_NAME.as_bytes())?,
+                tick: *lib.get::<PluginTick>(TICK_FUNCTION_NAME.as_bytes())?,
+                on_order_filled: *lib.get::<PluginOnOrderFilled>(ON_ORDER_FILLED_FUNCTION_NAME.as_bytes())?,
+                on_order_cancelled: *lib.get::<PluginOnOrderCancelled>(ON_ORDER_CANCELLED_FUNCTION_NAME.as_bytes())?,
+                on_end: *lib.get::<PluginOnEnd>(ON_END_FUNCTION_NAME.as_bytes())?,
+            };
+            let timeframes_fn = *lib.get::<PluginQuery>(TIMEFRAMES_FUNCTION_NAME.as_bytes())?;
+            let lookback_fn = *lib.get::<PluginQuery>(LOOKBACK_FUNCTION_NAME.as_bytes())?;
+            let constructor: Symbol<PluginCreate> = lib.get(CREATE_FUNCTION_NAME.as_bytes())?;
+            let mut strategy = std::ptr::null_mut();
+            if let Some(error) = take_string(constructor(parameters.as_ptr(), &mut strategy)) {
+                return Err(AppError::Strategy(error));
+            }
+
+            let mut library = PluginLibrary {
                 strategy,
+                exports,
+                timeframes: Vec::new(),
+                lookback: None,
                 _lib: lib,
-            })
+            };
+            library.timeframes = library.query(timeframes_fn)?;
+            library.lookback = library.query(lookback_fn)?;
+            Self::new(Plugin::Library(library), parameters_schema, watch)
+        }
+    }
+
+    /// Starts the strategy in a process of the runner at `runner`, which
+    /// loads the plugin at `path` in place of the server. A crash of the
+    /// strategy then only ends its runner. The runner is killed if it takes
+    /// longer than `load_timeout` to build the strategy.
+    #[cfg(unix)]
+    pub fn spawn(
+        runner: &Path,
+        path: &Path,
+        parameters: Option<&serde_json::Value>,
+        load_timeout: Option<Duration>,
+    ) -> AppResult<Self> {
+        let watch = Arc::new(StrategyWatch::default());
+        let (runner, process) = Runner::spawn(runner, path, parameters, load_timeout)?;
+        watch.attach(process);
+        let parameters_schema = runner.parameters_schema().cloned();
+        Self::new(Plugin::Runner(runner), parameters_schema, watch)
+    }
+
+    fn new(
+        plugin: Plugin,
+        parameters_schema: Option<serde_json::Value>,
+        watch: Arc<StrategyWatch>,
+    ) -> AppResult<Self> {
+        let mut handle = Self {
+            plugin,
+            parameters_schema,
+            timeframes: Vec::new(),
+            lookback: None,
+            watch,
+        };
+        let (strategy, watch) = handle.split();
+        let timeframes = watch.call("timeframes", || Ok(strategy.timeframes()))?;
+        let lookback = watch.call("lookback", || Ok(strategy.lookback()))?;
+        handle.timeframes = timeframes;
+        handle.lookback = lookback;
+        Ok(handle)
+    }
+
+    /// JSON schema of the strategy's parameters, if it declares any.
+    pub fn parameters_schema(&self) -> Option<&serde_json::Value> {
+        self.parameters_schema.as_ref()
+    }
+
+    /// The watch timing this strategy's calls, for a watchdog to read.
+    pub fn watch(&self) -> Arc<StrategyWatch> {
+        self.watch.clone()
+    }
+
+    fn split(&mut self) -> (&mut dyn Strategy, &StrategyWatch) {
+        let strategy: &mut dyn Strategy = match &mut self.plugin {
+            Plugin::Library(library) => library,
+            #[cfg(unix)]
+            Plugin::Runner(runner) => runner,
+        };
+        (strategy, &self.watch)
+    }
+}
+
+// The strategy lives until the library is dropped, and `check_abi` made sure
+// the types passed to its exports have the same layout in the plugin.
+impl PluginLibrary {
+    unsafe fn query<T: DeserializeOwned>(&self, query: PluginQuery) -> AppResult<T> {
+        let mut json = std::ptr::null_mut();
+        let error = unsafe { query(self.strategy, &mut json) };
+        self.result(error)?;
+        let value = unsafe { self.take_string(json) }.unwrap_or_default();
+        serde_json::from_str(&value)
+            .map_err(|e| AppError::Strategy(format!("Invalid strategy response: {}", e)))
+    }
+
+    /// Turns an error message returned by the plugin into an error.
+    fn result(&self, error: *mut c_char) -> AppResult<()> {
+        match unsafe { self.take_string(error) } {
+            Some(message) => Err(AppError::Strategy(message)),
+            None => Ok(()),
         }
     }
+
+    unsafe fn take_string(&self, string: *mut c_char) -> Option<String> {
+        (!string.is_null()).then(|| {
+            let owned = unsafe { CStr::from_ptr(string) }
+                .to_string_lossy()
+                .into_owned();
+            unsafe { (self.exports.free_string)(string) };
+            owned
+        })
+    }
+}
+
+impl Strategy for PluginLibrary {
+    fn timeframes(&self) -> Vec<Timeframe> {
+        self.timeframes.clone()
+    }
+
+    fn lookback(&self) -> Option<usize> {
+        self.lookback
+    }
+
+    fn on_start(&mut self, context: &StrategyContext) -> AppResult<()> {
+        self.result(unsafe { (self.exports.on_start)(self.strategy, context) })
+    }
+
+    fn tick(&mut self, context: &mut StrategyContext) -> AppResult<()> {
+        self.result(unsafe { (self.exports.tick)(self.strategy, context) })
+    }
+
+    fn on_order_filled(
+        &mut self,
+        context: &mut StrategyContext,
+        order: &Order,
+        trade: &Trade,
+    ) -> AppResult<()> {
+        let filled = self.exports.on_order_filled;
+        self.result(unsafe { filled(self.strategy, context, order, trade) })
+    }
+
+    fn on_order_cancelled(
+        &mut self,
+        context: &mut StrategyContext,
+        order: &Order,
+    ) -> AppResult<()> {
+        let cancelled = self.exports.on_order_cancelled;
+        self.result(unsafe { cancelled(self.strategy, context, order) })
+    }
+
+    fn on_end(&mut self, context: &StrategyContext) -> AppResult<()> {
+        self.result(unsafe { (self.exports.on_end)(self.strategy, context) })
+    }
+}
+
+impl Drop for PluginLibrary {
+    fn drop(&mut self) {
+        unsafe { (self.exports.destroy)(self.strategy) };
+    }
 }
 
-impl Deref for StrategyHandle {
-    type Target = Box<dyn Strategy>;
-    fn deref(&self) -> &Self::Target {
-        &self.strategy
+/// Fails unless the plugin exports the interface version and build id of
+/// this server.
+unsafe fn check_abi(lib: &Library) -> AppResult<()> {
+    let rebuild = "rebuild the strategy with this version of fettle";
+    let abi_version: Symbol<PluginAbiVersion> =
+        unsafe { lib.get(ABI_VERSION_FUNCTION_NAME.as_bytes()) }.map_err(|_| {
+            AppError::Strategy(format!(
+                "Strategy plugin does not export a plugin interface version, {}",
+                rebuild
+            ))
+        })?;
+    let version = unsafe { abi_version() };
+    if version != ABI_VERSION {
+        return Err(AppError::Strategy(format!(
+            "Strategy plugin uses plugin interface version {} but fettle expects {}, {}",
+            version, ABI_VERSION, rebuild
+        )));
+    }
+
+    let build_id: Symbol<PluginBuildId> = unsafe { lib.get(BUILD_ID_FUNCTION_NAME.as_bytes())? };
+    let build = unsafe { CStr::from_ptr(build_id()) };
+    if build != plugin::build_id() {
+        return Err(AppError::Strategy(format!(
+            "Strategy plugin was built for {} but the server runs {}, {}",
+            build.to_string_lossy(),
+            plugin::build_id().to_string_lossy(),
+            rebuild
+        )));
     }
+    Ok(())
 }
 
-impl DerefMut for StrategyHandle {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.strategy
+/// Timeframes and lookback are read once, when the plugin is loaded.
+impl Strategy for StrategyHandle {
+    fn timeframes(&self) -> Vec<Timeframe> {
+        self.timeframes.clone()
+    }
+
+    fn lookback(&self) -> Option<usize> {
+        self.lookback
+    }
+
+    fn on_start(&mut self, context: &StrategyContext) -> AppResult<()> {
+        let (strategy, watch) = self.split();
+        watch.call("on_start", || strategy.on_start(context))
+    }
+
+    fn tick(&mut self, context: &mut StrategyContext) -> AppResult<()> {
+        let (strategy, watch) = self.split();
+        watch.call("tick", || strategy.tick(context))
+    }
+
+    fn on_order_filled(
+        &mut self,
+        context: &mut StrategyContext,
+        order: &Order,
+        trade: &Trade,
+    ) -> AppResult<()> {
+        let (strategy, watch) = self.split();
+        watch.call("on_order_filled", || {
+            strategy.on_order_filled(context, order, trade)
+        })
+    }
+
+    fn on_order_cancelled(
+        &mut self,
+        context: &mut StrategyContext,
+        order: &Order,
+    ) -> AppResult<()> {
+        let (strategy, watch) = self.split();
+        watch.call("on_order_cancelled", || {
+            strategy.on_order_cancelled(context, order)
+        })
+    }
+
+    fn on_end(&mut self, context: &StrategyContext) -> AppResult<()> {
+        let (strategy, watch) = self.split();
+        watch.call("on_end", || strategy.on_end(context))
+    }
+}
+
+/// Times the strategy's calls so that a watchdog on another thread can tell
+/// how long the call in progress and all calls so far have taken, and stops
+/// further calls once the watchdog gives up on the strategy.
+#[derive(Debug, Default)]
+pub struct StrategyWatch {
+    state: Mutex<WatchState>,
+    stopped: AtomicBool,
+    /// Runner process the strategy runs in, if any.
+    process: Mutex<Option<Child>>,
+    clock: Clock,
+}
+
+/// Where a [`StrategyWatch`] reads the time: the system clock, or in tests a
+/// manual one that only moves when it is advanced.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Clock(Option<Arc<Mutex<Instant>>>);
+
+impl Clock {
+    fn now(&self) -> Instant {
+        match &self.0 {
+            Some(now) => *now.lock().unwrap(),
+            None => Instant::now(),
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn manual() -> Self {
+        Self(Some(Arc::new(Mutex::new(Instant::now()))))
+    }
+
+    #[cfg(test)]
+    pub(crate) fn advance(&self, by: Duration) {
+        if let Some(now) = &self.0 {
+            *now.lock().unwrap() += by;
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct WatchState {
+    current: Option<(&'static str, Instant)>,
+    spent: Duration,
+}
+
+impl StrategyWatch {
+    #[cfg(test)]
+    pub(crate) fn with_clock(clock: Clock) -> Self {
+        let mut watch = Self::default();
+        watch.clock = clock;
+        watch
+    }
+
+    /// Runs `call`, timing it.
+    pub(crate) fn call<T>(
+        &self,
+        name: &'static str,
+        call: impl FnOnce() -> AppResult<T>,
+    ) -> AppResult<T> {
+        if self.stopped.load(Ordering::Relaxed) {
+            return Err(AppError::Strategy(
+                "Strategy was stopped after exceeding its time budget".into(),
+            ));
+        }
+
+        let started = self.clock.now();
+        self.state.lock().unwrap().current = Some((name, started));
+        let result = call();
+        let mut state = self.state.lock().unwrap();
+        state.current = None;
+        state.spent += self.clock.now() - started;
+        result
+    }
+
+    /// Name of the call in progress and how long it has been running.
+    pub fn current(&self) -> Option<(&'static str, Duration)> {
+        let state = self.state.lock().unwrap();
+        state
+            .current
+            .map(|(name, started)| (name, self.clock.now() - started))
+    }
+
+    /// Time spent in the strategy so far, including the call in progress.
+    pub fn spent(&self) -> Duration {
+        let state = self.state.lock().unwrap();
+        state.spent
+            + state
+                .current
+                .map_or(Duration::ZERO, |(_, started)| self.clock.now() - started)
+    }
+
+    /// Fails every later call, so an abandoned simulation ends as soon as
+    /// the strategy returns. A strategy in a runner process is killed, which
+    /// ends the call in progress at once.
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+        if let Some(process) = self.process.lock().unwrap().as_mut() {
+            let _ = process.kill();
+        }
+    }
+
+    #[cfg(unix)]
+    fn attach(&self, process: Child) {
+        *self.process.lock().unwrap() = Some(process);
+    }
+}
+
+impl Drop for StrategyWatch {
+    fn drop(&mut self) {
+        if let Some(mut process) = self.process.get_mut().unwrap().take() {
+            let _ = process.kill();
+            let _ = process.wait();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{MarketPrecision, TradingFees};
+    use crate::strategy::{FillModel, MarginConfig, Market, SlippageModel};
+    use bigdecimal::BigDecimal;
+    use std::process::Command;
+
+    /// Builds the panicking strategy of `test-plugins` with the profile of
+    /// this build and returns its library.
+    fn panicking_plugin() -> PathBuf {
+        let workspace = Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap();
+        let profile = match env!("FETTLE_PROFILE") {
+            "release" => "release",
+            _ => "dev",
+        };
+        let mut cargo = Command::new(env!("CARGO"));
+        // Cargo sets these for the test run; build scripts reading them would
+        // rerun and rebuild the plugin's dependencies every time.
+        for (key, _) in std::env::vars() {
+            if ["CARGO_PKG_", "CARGO_MANIFEST_", "CARGO_CRATE_", "OUT_DIR"]
+                .iter()
+                .any(|prefix| key.starts_with(prefix))
+            {
+                cargo.env_remove(key);
+            }
+        }
+        let status = cargo
+            .args(["build", "--profile", profile, "--package", "panicking-strategy"])
+            .current_dir(workspace)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        // Tests run from `deps` in the profile's directory.
+        let exe = std::env::current_exe().unwrap();
+        let profile_dir = exe.parent().and_then(Path::parent).unwrap();
+        profile_dir.join(libloading::library_filename("panicking_strategy"))
+    }
+
+    #[test]
+    fn plugin_panics_and_errors_come_back_as_errors() {
+        let mut handle = StrategyHandle::try_from_path(&panicking_plugin(), None).unwrap();
+        let mut context = StrategyContext::new(
+            BigDecimal::from(1000),
+            vec![Market::new(
+                "BTC/USDT".into(),
+                TradingFees {
+                    maker: BigDecimal::from(0),
+                    taker: BigDecimal::from(0),
+                },
+                MarketPrecision {
+                    price_precision: BigDecimal::from(1),
+                    amount_precision: BigDecimal::from(1),
+                },
+            )],
+            Timeframe::M1,
+            MarginConfig::default(),
+            FillModel::default(),
+            SlippageModel::default(),
+            None,
+        )
+        .unwrap();
+
+        let error = handle.tick(&mut context).unwrap_err().to_string();
+        assert!(error.contains("Strategy panicked in tick: no candle at 0"));
+        let error = handle.on_end(&context).unwrap_err();
+        assert!(matches!(error, AppError::Strategy(message) if message == "ended without trading"));
+    }
+
+    #[test]
+    fn stopped_watches_refuse_calls() {
+        let watch = StrategyWatch::default();
+        assert_eq!(watch.call("tick", || Ok(1)).unwrap(), 1);
+        assert!(watch.current().is_none());
+
+        watch.stop();
+        assert!(watch.call("tick", || Ok(1)).is_err());
     }
 }
@@ -1,7 +1,14 @@
-use crate::errors::AppResult;
+use crate::errors::{AppError, AppResult};
 use crate::strategy::handle::StrategyHandle;
 use cargo_metadata::MetadataCommand;
-use std::{fs, path::PathBuf, process::Stdio};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::{Duration, SystemTime},
+};
 use toml_edit::{DocumentMut, array, table, value};
 
 const WORKSPACE_CARGO_TOML: &str = include_str!(concat!(
@@ -16,11 +23,32 @@ const MEMBER_LIB_RS: &str = include_str!(concat!(
     env!("CARGO_MANIFEST_DIR"),
     "/templates/strategy/member/src/lib.rs.template"
 ));
+/// Lock file of the server's workspace, copied by the build script.
+const SERVER_CARGO_LOCK: &str = include_str!(concat!(env!("OUT_DIR"), "/Cargo.lock"));
 pub const STRATEGY_WORKDIR_NAME: &str = "strategies";
+/// Directory under the cargo target directory that built libraries are copied
+/// to, named by their hash.
+const LIBRARY_ARCHIVE_DIR_NAME: &str = "archive";
+/// Archived libraries kept; the least recently used ones beyond it are removed.
+const MAX_ARCHIVED_LIBRARIES: usize = 50;
+
+/// A built strategy and the hashes that identify the build.
+#[derive(Debug, Clone)]
+pub struct StrategyBuild {
+    /// Archived copy of the library, which later builds do not overwrite.
+    pub lib_path: PathBuf,
+    pub source_hash: String,
+    pub library_hash: String,
+}
 
 #[derive(Debug, Clone)]
 pub struct StrategyManager {
     workspace_dir: PathBuf,
+    /// Runner binary strategies run in, when they are not loaded into the
+    /// server.
+    runner: Option<PathBuf>,
+    /// Longest a runner may take to build its strategy.
+    load_timeout: Option<Duration>,
 }
 
 impl StrategyManager {
@@ -64,7 +92,11 @@ impl StrategyManager {
             fs::write(workspace_toml, WORKSPACE_CARGO_TOML)?;
         }
 
-        let manager = Self { workspace_dir };
+        let manager = Self {
+            workspace_dir,
+            runner: None,
+            load_timeout: None,
+        };
 
         if initial {
             manager.add_strategy("my-strategy")?;
@@ -133,7 +165,228 @@ impl StrategyManager {
         Ok(())
     }
 
-    pub async fn load_strategy(&self, strategy_name: &str) -> AppResult<StrategyHandle> {
+    /// Runs strategies in processes of the runner at `runner` instead of
+    /// loading them into the server, if given.
+    pub fn with_runner(mut self, runner: Option<PathBuf>) -> Self {
+        self.runner = runner;
+        self
+    }
+
+    /// Fails runners that take longer than `timeout` to build their strategy.
+    /// A strategy loaded into the server cannot be interrupted.
+    pub fn with_load_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.load_timeout = timeout;
+        self
+    }
+
+    pub async fn load_strategy(
+        &self,
+        strategy_name: &str,
+        parameters: Option<&serde_json::Value>,
+    ) -> AppResult<StrategyHandle> {
+        let lib_path = self.build_strategy(strategy_name).await?;
+        self.open_strategy(&lib_path, parameters).await
+    }
+
+    /// Builds the strategy of the library at `lib_path`, in a runner process
+    /// if the manager has a runner. Runs on a blocking thread, as it runs the
+    /// plugin's code or waits for the runner to start.
+    pub async fn open_strategy(
+        &self,
+        lib_path: &Path,
+        parameters: Option<&serde_json::Value>,
+    ) -> AppResult<StrategyHandle> {
+        let manager = self.clone();
+        let (lib_path, parameters) = (lib_path.to_path_buf(), parameters.cloned());
+        tokio::task::spawn_blocking(move || manager.open_blocking(&lib_path, parameters.as_ref()))
+            .await
+            .map_err(|e| AppError::Strategy(format!("Loading the strategy panicked: {}", e)))?
+    }
+
+    fn open_blocking(
+        &self,
+        lib_path: &PathBuf,
+        parameters: Option<&serde_json::Value>,
+    ) -> AppResult<StrategyHandle> {
+        match &self.runner {
+            #[cfg(unix)]
+            Some(runner) => StrategyHandle::spawn(runner, lib_path, parameters, self.load_timeout),
+            #[cfg(not(unix))]
+            Some(_) => Err("Strategy runners are only supported on Unix".into()),
+            None => StrategyHandle::try_from_path(lib_path, parameters),
+        }
+    }
+
+    /// Builds the strategy and archives its library, so the exact build can be
+    /// loaded again after the source changes.
+    pub async fn build_archived_strategy(&self, strategy_name: &str) -> AppResult<StrategyBuild> {
+        // Pinning may rewrite the lock file, which the source hash covers.
+        self.pin_dependencies()?;
+        let source_hash = self.source_hash(strategy_name)?;
+        let lib_path = self.build_strategy(strategy_name).await?;
+        // The hash only describes the library if no file changed during the build.
+        if self.source_hash(strategy_name)? != source_hash {
+            return Err(format!(
+                "Strategy {} changed while it was being built",
+                strategy_name
+            )
+            .into());
+        }
+
+        let library = fs::read(&lib_path)?;
+        let library_hash = format!("{:x}", Sha256::digest(&library));
+        let archived_path = self.archived_library_path(&lib_path, &library_hash)?;
+        let archive_dir = archived_path.parent().unwrap();
+        if archived_path.exists() {
+            Self::touch(&archived_path)?;
+        } else {
+            fs::create_dir_all(archive_dir)?;
+            fs::write(&archived_path, library)?;
+        }
+        Self::prune_archive(archive_dir, &archived_path, MAX_ARCHIVED_LIBRARIES)?;
+
+        Ok(StrategyBuild {
+            lib_path: archived_path,
+            source_hash,
+            library_hash,
+        })
+    }
+
+    /// Path of the library archived under `library_hash`.
+    pub fn archived_library(&self, library_hash: &str) -> AppResult<PathBuf> {
+        if !library_hash.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return Err(format!("Invalid library hash '{}'", library_hash).into());
+        }
+
+        let metadata = MetadataCommand::new()
+            .current_dir(&self.workspace_dir)
+            .no_deps()
+            .exec()?;
+        let archive_dir = metadata
+            .target_directory
+            .as_std_path()
+            .join(LIBRARY_ARCHIVE_DIR_NAME);
+        for entry in fs::read_dir(&archive_dir)? {
+            let path = entry?.path();
+            if path.file_stem().and_then(|stem| stem.to_str()) == Some(library_hash) {
+                Self::touch(&path)?;
+                return Ok(path);
+            }
+        }
+
+        Err(format!("Library {} is no longer archived", library_hash).into())
+    }
+
+    /// Marks an archived library as used now, so pruning keeps it longer.
+    fn touch(path: &Path) -> AppResult<()> {
+        fs::File::options()
+            .write(true)
+            .open(path)?
+            .set_modified(SystemTime::now())?;
+        Ok(())
+    }
+
+    /// Removes all but the `keep` most recently used libraries from
+    /// `archive_dir`, never `current`. Tasks built with a removed library can
+    /// no longer be replayed.
+    fn prune_archive(archive_dir: &Path, current: &Path, keep: usize) -> AppResult<()> {
+        let mut libraries = Vec::new();
+        for entry in fs::read_dir(archive_dir)? {
+            let entry = entry?;
+            // Another build may be pruning the same archive.
+            if let Ok(modified) = entry.metadata().and_then(|m| m.modified()) {
+                libraries.push((modified, entry.path()));
+            }
+        }
+        libraries.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+
+        for (_, path) in libraries.into_iter().skip(keep) {
+            if path != current {
+                let _ = fs::remove_file(path);
+            }
+        }
+        Ok(())
+    }
+
+    fn archived_library_path(&self, lib_path: &Path, library_hash: &str) -> AppResult<PathBuf> {
+        let profile_dir = lib_path.parent().ok_or("Library has no parent directory")?;
+        let target_dir = profile_dir
+            .parent()
+            .ok_or("Library is outside a target directory")?;
+        let mut name = PathBuf::from(library_hash);
+        if let Some(extension) = lib_path.extension() {
+            name.set_extension(extension);
+        }
+        Ok(target_dir.join(LIBRARY_ARCHIVE_DIR_NAME).join(name))
+    }
+
+    /// Hash of every file of the strategy package and of the workspace lock
+    /// file, which pins its dependencies.
+    pub fn source_hash(&self, strategy_name: &str) -> AppResult<String> {
+        Self::validate_strategy_name(strategy_name)?;
+
+        let strategy_dir = self.workspace_dir.join(strategy_name);
+        let mut files = Vec::new();
+        Self::collect_files(&strategy_dir, &mut files)?;
+        files.sort();
+
+        let mut hasher = Sha256::new();
+        let lock_path = self.workspace_dir.join("Cargo.lock");
+        for path in files.iter().chain(lock_path.exists().then_some(&lock_path)) {
+            let relative = path.strip_prefix(&self.workspace_dir).unwrap_or(path);
+            let content = fs::read(path)?;
+            hasher.update(relative.to_string_lossy().as_bytes());
+            hasher.update([0]);
+            hasher.update((content.len() as u64).to_le_bytes());
+            hasher.update(&content);
+        }
+        Ok(format!("{:x}", hasher.finalize()))
+    }
+
+    fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> AppResult<()> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                Self::collect_files(&path, files)?;
+            } else {
+                files.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    /// Pins the workspace to the dependency versions the server was built
+    /// with, which the build id covers. Strategies may add dependencies of
+    /// their own, but not other versions of the server's.
+    fn pin_dependencies(&self) -> AppResult<()> {
+        let server = locked_packages(SERVER_CARGO_LOCK)?;
+        let lock_path = self.workspace_dir.join("Cargo.lock");
+        if let Ok(lock) = fs::read_to_string(&lock_path)
+            && unpinned(&server, &lock)?.is_empty()
+        {
+            return Ok(());
+        }
+
+        // Cargo keeps the locked versions it can when it resolves the rest.
+        fs::write(&lock_path, SERVER_CARGO_LOCK)?;
+        MetadataCommand::new()
+            .current_dir(&self.workspace_dir)
+            .exec()?;
+        let unpinned = unpinned(&server, &fs::read_to_string(&lock_path)?)?;
+        if !unpinned.is_empty() {
+            return Err(format!(
+                "Strategies require other versions of the server's dependencies: {}",
+                unpinned.join(", ")
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Builds the strategy with the profile the server was built with, as its
+    /// build id requires, and returns the path of its library.
+    pub async fn build_strategy(&self, strategy_name: &str) -> AppResult<PathBuf> {
+        self.pin_dependencies()?;
         let metadata = MetadataCommand::new()
             .current_dir(&self.workspace_dir)
             .exec()?;
@@ -144,8 +397,13 @@ impl StrategyManager {
             .find(|p| p.name == strategy_name)
             .ok_or(format!("Package '{}' not found", strategy_name))?;
 
+        // Cargo builds the `dev` profile into `debug`.
+        let (profile, profile_dir) = match env!("FETTLE_PROFILE") {
+            "release" => ("release", "release"),
+            _ => ("dev", "debug"),
+        };
         let output = tokio::process::Command::new("cargo")
-            .args(["build", "--release", "--package", strategy_name])
+            .args(["build", "--locked", "--profile", profile, "--package", strategy_name])
             .current_dir(&self.workspace_dir)
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
@@ -168,19 +426,74 @@ impl StrategyManager {
         #[cfg(target_os = "windows")]
         let lib_name = format!("{}.dll", strategy_name.replace("-", "_"));
 
-        let lib_path = target_dir.join("release").join(&lib_name);
+        let lib_path = target_dir.join(profile_dir).join(&lib_name);
 
         if !lib_path.exists() {
             return Err(format!("Library not found: {:?}", lib_path).into());
         }
 
-        StrategyHandle::try_from_path(&lib_path)
+        Ok(lib_path)
+    }
+}
+
+/// Versions of each registry package in a lock file.
+fn locked_packages(lock: &str) -> AppResult<HashMap<String, HashSet<String>>> {
+    let lock: DocumentMut = lock.parse()?;
+    let mut packages: HashMap<String, HashSet<String>> = HashMap::new();
+    let entries = lock.get("package").and_then(|p| p.as_array_of_tables());
+    for package in entries.into_iter().flatten() {
+        // Path packages, such as fettle itself, have no source.
+        if package.get("source").is_none() {
+            continue;
+        }
+        let field = |key| package.get(key).and_then(|v| v.as_str());
+        if let (Some(name), Some(version)) = (field("name"), field("version")) {
+            packages
+                .entry(name.to_string())
+                .or_default()
+                .insert(version.to_string());
+        }
+    }
+    Ok(packages)
+}
+
+/// Packages of `lock` at other versions than the server's, but compatible
+/// with them, so that Cargo would have used them for fettle too.
+/// Incompatible versions are separate crates to Cargo.
+fn unpinned(server: &HashMap<String, HashSet<String>>, lock: &str) -> AppResult<Vec<String>> {
+    let mut unpinned = Vec::new();
+    for (name, versions) in locked_packages(lock)? {
+        let Some(server_versions) = server.get(&name) else {
+            continue;
+        };
+        for version in versions.difference(server_versions) {
+            if server_versions
+                .iter()
+                .any(|server| compatibility(server) == compatibility(version))
+            {
+                unpinned.push(format!("{} {}", name, version));
+            }
+        }
     }
+    unpinned.sort();
+    Ok(unpinned)
+}
+
+/// The leftmost non-zero part of a version and those before it, which
+/// compatible versions share.
+fn compatibility(version: &str) -> Vec<&str> {
+    let release = version.split(['-', '+']).next().unwrap_or(version);
+    let parts: Vec<&str> = release.split('.').collect();
+    let significant = parts
+        .iter()
+        .position(|part| *part != "0")
+        .unwrap_or(parts.len() - 1);
+    parts[..=significant].to_vec()
 }
 
 #[cfg(test)]
 mod tests {
-    use super::StrategyManager;
+    use super::{StrategyManager, locked_packages, unpinned};
 
     #[test]
     fn accepts_valid_strategy_name() {
@@ -214,6 +527,8 @@ mod tests {
 
         let manager = StrategyManager {
             workspace_dir: workspace_dir.clone(),
+            runner: None,
+            load_timeout: None,
         };
 
         assert!(manager.add_strategy("existing").is_err());
@@ -223,4 +538,109 @@ mod tests {
 
         std::fs::remove_dir_all(workspace_dir).unwrap();
     }
+
+    #[test]
+    fn source_hash_follows_strategy_files() {
+        let unique = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let workspace_dir =
+            std::env::temp_dir().join(format!("fettle-source-hash-test-{}", unique));
+
+        let src_dir = workspace_dir.join("hashed").join("src");
+        std::fs::create_dir_all(&src_dir).unwrap();
+        std::fs::write(src_dir.join("lib.rs"), "// v1").unwrap();
+
+        let manager = StrategyManager {
+            workspace_dir: workspace_dir.clone(),
+            runner: None,
+            load_timeout: None,
+        };
+
+        let first = manager.source_hash("hashed").unwrap();
+        assert_eq!(manager.source_hash("hashed").unwrap(), first);
+
+        std::fs::write(src_dir.join("lib.rs"), "// v2").unwrap();
+        assert_ne!(manager.source_hash("hashed").unwrap(), first);
+
+        std::fs::remove_dir_all(workspace_dir).unwrap();
+    }
+
+    #[test]
+    fn pruning_keeps_the_most_recently_used_libraries() {
+        let unique = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let archive_dir = std::env::temp_dir().join(format!("fettle-archive-test-{}", unique));
+        std::fs::create_dir_all(&archive_dir).unwrap();
+
+        let epoch = std::time::UNIX_EPOCH;
+        let libraries: Vec<_> = (0..4)
+            .map(|i| {
+                let path = archive_dir.join(format!("{}.so", i));
+                std::fs::write(&path, "").unwrap();
+                let modified = epoch + std::time::Duration::from_secs(1000 + i);
+                std::fs::File::options()
+                    .write(true)
+                    .open(&path)
+                    .unwrap()
+                    .set_modified(modified)
+                    .unwrap();
+                path
+            })
+            .collect();
+
+        // The oldest library was just used, so it is kept along with the newest.
+        StrategyManager::prune_archive(&archive_dir, &libraries[0], 1).unwrap();
+        let exists: Vec<bool> = libraries.iter().map(|path| path.exists()).collect();
+        assert_eq!(exists, vec![true, false, false, true]);
+
+        std::fs::remove_dir_all(archive_dir).unwrap();
+    }
+
+    #[test]
+    fn only_other_versions_of_server_dependencies_are_unpinned() {
+        let package = |name: &str, version: &str, source: bool| {
+            let source = if source {
+                "source = \"registry+https://github.com/rust-lang/crates.io-index\"\n"
+            } else {
+                ""
+            };
+            format!(
+                "[[package]]\nname = \"{}\"\nversion = \"{}\"\n{}\n",
+                name, version, source
+            )
+        };
+        let server = locked_packages(
+            &[
+                package("fettle", "0.1.0", false),
+                package("bigdecimal", "0.4.8", true),
+                package("syn", "1.0.109", true),
+                package("syn", "2.0.100", true),
+            ]
+            .concat(),
+        )
+        .unwrap();
+
+        let pinned = [
+            package("fettle", "0.1.0", false),
+            package("my-strategy", "0.1.0", false),
+            package("bigdecimal", "0.4.8", true),
+            package("syn", "2.0.100", true),
+            package("schemars", "1.0.4", true),
+        ]
+        .concat();
+        assert!(unpinned(&server, &pinned).unwrap().is_empty());
+
+        let drifted = [
+            package("bigdecimal", "0.4.9", true),
+            package("bigdecimal", "0.5.0", true),
+            package("syn", "2.0.100", true),
+            package("syn", "3.0.1", true),
+        ]
+        .concat();
+        assert_eq!(unpinned(&server, &drifted).unwrap(), ["bigdecimal 0.4.9"]);
+    }
 }
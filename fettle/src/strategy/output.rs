@@ -0,0 +1,153 @@
+use chrono::{DateTime, Utc, serde::ts_milliseconds};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+/// Log entries kept per backtest; later ones are only counted.
+const MAX_LOG_ENTRIES: usize = 10000;
+/// Points kept per plotted series once the backtest ends.
+const MAX_PLOT_POINTS: usize = 5000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct LogEntry {
+    /// Open time of the candle the strategy was handling.
+    #[serde(with = "ts_milliseconds")]
+    #[ts(type = "number")]
+    pub timestamp: DateTime<Utc>,
+    pub level: LogLevel,
+    pub symbol: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct PlotPoint {
+    #[serde(with = "ts_milliseconds")]
+    #[ts(type = "number")]
+    pub timestamp: DateTime<Utc>,
+    pub value: f64,
+}
+
+/// Values a strategy plotted under one name for one symbol.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct PlotSeries {
+    pub name: String,
+    pub symbol: String,
+    pub points: Vec<PlotPoint>,
+}
+
+/// What a strategy logged and plotted during a backtest.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct StrategyOutput {
+    /// The first `MAX_LOG_ENTRIES` entries, in the order they were logged.
+    pub logs: Vec<LogEntry>,
+    /// Entries logged after `logs` was full.
+    pub dropped_logs: usize,
+    /// Series in the order they were first plotted, each downsampled to at
+    /// most `MAX_PLOT_POINTS` points.
+    pub plots: Vec<PlotSeries>,
+}
+
+impl StrategyOutput {
+    pub(crate) fn log(&mut self, entry: LogEntry) {
+        if self.logs.len() < MAX_LOG_ENTRIES {
+            self.logs.push(entry);
+        } else {
+            self.dropped_logs += 1;
+        }
+    }
+
+    /// Adds a point to the series, replacing a value already plotted for the
+    /// same candle. NaN and infinite values are skipped, as JSON cannot hold
+    /// them.
+    pub(crate) fn plot(&mut self, name: &str, symbol: &str, point: PlotPoint) {
+        if !point.value.is_finite() {
+            return;
+        }
+        let index = match self
+            .plots
+            .iter()
+            .position(|s| s.name == name && s.symbol == symbol)
+        {
+            Some(index) => index,
+            None => {
+                self.plots.push(PlotSeries {
+                    name: name.to_string(),
+                    symbol: symbol.to_string(),
+                    points: Vec::new(),
+                });
+                self.plots.len() - 1
+            }
+        };
+
+        let points = &mut self.plots[index].points;
+        match points.last_mut() {
+            Some(last) if last.timestamp == point.timestamp => *last = point,
+            _ => points.push(point),
+        }
+    }
+
+    /// Downsamples long series by keeping evenly spaced points and the last one.
+    pub(crate) fn finish(mut self) -> Self {
+        for series in &mut self.plots {
+            let len = series.points.len();
+            if len <= MAX_PLOT_POINTS {
+                continue;
+            }
+            let stride = len.div_ceil(MAX_PLOT_POINTS - 1);
+            let last = series.points[len - 1].clone();
+            series.points = series.points.iter().step_by(stride).cloned().collect();
+            if series.points.last().map(|p| p.timestamp) != Some(last.timestamp) {
+                series.points.push(last);
+            }
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(minute: i64, value: f64) -> PlotPoint {
+        PlotPoint {
+            timestamp: DateTime::from_timestamp(minute * 60, 0).unwrap(),
+            value,
+        }
+    }
+
+    #[test]
+    fn plots_keep_one_value_per_candle_and_are_downsampled() {
+        let mut output = StrategyOutput::default();
+        output.plot("rsi", "BTC/USDT", point(0, 40.0));
+        output.plot("rsi", "BTC/USDT", point(0, 45.0));
+        output.plot("rsi", "ETH/USDT", point(0, 60.0));
+        assert_eq!(output.plots.len(), 2);
+        assert_eq!(output.plots[0].points.len(), 1);
+        assert_eq!(output.plots[0].points[0].value, 45.0);
+
+        for minute in 1..(3 * MAX_PLOT_POINTS as i64) {
+            output.plot("rsi", "BTC/USDT", point(minute, minute as f64));
+        }
+        let output = output.finish();
+        let points = &output.plots[0].points;
+        assert!(points.len() <= MAX_PLOT_POINTS);
+        assert_eq!(points[0].value, 45.0);
+        assert_eq!(
+            points.last().unwrap().value,
+            (3 * MAX_PLOT_POINTS - 1) as f64
+        );
+    }
+}
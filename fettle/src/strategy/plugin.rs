@@ -0,0 +1,385 @@
+use crate::errors::{AppError, AppResult};
+use crate::strategy::{Order, Strategy, StrategyContext, Trade};
+use serde::Serialize;
+use std::any::Any;
+use std::ffi::{CStr, CString, c_char};
+use std::panic::{self, AssertUnwindSafe};
+
+/// Version of the symbols a plugin exports and their signatures. Bump it
+/// whenever either changes.
+///
+/// The server calls the strategy only through these `extern "C"` symbols, so
+/// a panic is caught inside the plugin, which built it. The context, orders
+/// and trades are still passed as pointers to fettle's Rust types, whose
+/// layout is shared only by builds of the same fettle sources with the same
+/// compiler, target and profile. The server therefore also compares the
+/// plugin's build id with its own before calling anything else.
+pub const ABI_VERSION: u32 = 2;
+
+pub(crate) const ABI_VERSION_FUNCTION_NAME: &str = "_plugin_abi_version";
+pub(crate) const BUILD_ID_FUNCTION_NAME: &str = "_plugin_build_id";
+pub(crate) const PARAMETERS_FUNCTION_NAME: &str = "_plugin_parameters";
+pub(crate) const CREATE_FUNCTION_NAME: &str = "_plugin_create";
+pub(crate) const DESTROY_FUNCTION_NAME: &str = "_plugin_destroy";
+pub(crate) const FREE_STRING_FUNCTION_NAME: &str = "_plugin_free_string";
+pub(crate) const TIMEFRAMES_FUNCTION_NAME: &str = "_plugin_timeframes";
+pub(crate) const LOOKBACK_FUNCTION_NAME: &str = "_plugin_lookback";
+pub(crate) const ON_START_FUNCTION_NAME: &str = "_plugin_on_start";
+pub(crate) const TICK_FUNCTION_NAME: &str = "_plugin_tick";
+pub(crate) const ON_ORDER_FILLED_FUNCTION_NAME: &str = "_plugin_on_order_filled";
+pub(crate) const ON_ORDER_CANCELLED_FUNCTION_NAME: &str = "_plugin_on_order_cancelled";
+pub(crate) const ON_END_FUNCTION_NAME: &str = "_plugin_on_end";
+
+/// Returns [`ABI_VERSION`] as the plugin was built.
+pub(crate) type PluginAbiVersion = unsafe extern "C" fn() -> u32;
+/// Returns the build id of the fettle the plugin was built with.
+pub(crate) type PluginBuildId = unsafe extern "C" fn() -> *const c_char;
+/// Writes the JSON schema of the parameters, or null when the strategy takes
+/// none, to its argument. Returns an error message or null.
+pub(crate) type PluginParameters = unsafe extern "C" fn(*mut *mut c_char) -> *mut c_char;
+/// Builds the strategy from a JSON parameter object, empty for the defaults,
+/// and writes it to its second argument. Returns an error message or null.
+pub(crate) type PluginCreate =
+    unsafe extern "C" fn(*const c_char, *mut *mut PluginStrategy) -> *mut c_char;
+/// Drops a strategy built by the plugin.
+pub(crate) type PluginDestroy = unsafe extern "C" fn(*mut PluginStrategy);
+/// Frees a string returned by the plugin.
+pub(crate) type PluginFreeString = unsafe extern "C" fn(*mut c_char);
+/// Writes the strategy's timeframes or lookback as JSON to its second
+/// argument. Returns an error message or null.
+pub(crate) type PluginQuery =
+    unsafe extern "C" fn(*const PluginStrategy, *mut *mut c_char) -> *mut c_char;
+/// Calls `on_start`. Returns an error message or null.
+pub(crate) type PluginOnStart =
+    unsafe extern "C" fn(*mut PluginStrategy, *const StrategyContext) -> *mut c_char;
+/// Calls `on_end`. Returns an error message or null.
+pub(crate) type PluginOnEnd =
+    unsafe extern "C" fn(*mut PluginStrategy, *const StrategyContext) -> *mut c_char;
+/// Calls `tick`. Returns an error message or null.
+pub(crate) type PluginTick =
+    unsafe extern "C" fn(*mut PluginStrategy, *mut StrategyContext) -> *mut c_char;
+/// Calls `on_order_filled`. Returns an error message or null.
+pub(crate) type PluginOnOrderFilled = unsafe extern "C" fn(
+    *mut PluginStrategy,
+    *mut StrategyContext,
+    *const Order,
+    *const Trade,
+) -> *mut c_char;
+/// Calls `on_order_cancelled`. Returns an error message or null.
+pub(crate) type PluginOnOrderCancelled =
+    unsafe extern "C" fn(*mut PluginStrategy, *mut StrategyContext, *const Order) -> *mut c_char;
+
+const BUILD_ID: &str = concat!(
+    "fettle ",
+    env!("CARGO_PKG_VERSION"),
+    " (sources ",
+    env!("FETTLE_SOURCE_HASH"),
+    "), ",
+    env!("FETTLE_RUSTC_VERSION"),
+    ", ",
+    env!("FETTLE_TARGET"),
+    ", ",
+    env!("FETTLE_PROFILE"),
+    "\0"
+);
+
+/// A strategy owned by the plugin that built it. The functions below are the
+/// plugin side of the interface, called by the code `#[strategy]` generates.
+pub struct PluginStrategy(pub(crate) Box<dyn Strategy>);
+
+/// The version, strategy-facing sources, compiler, target and profile this
+/// copy of fettle was built with.
+pub fn build_id() -> &'static CStr {
+    CStr::from_bytes_with_nul(BUILD_ID.as_bytes()).unwrap()
+}
+
+/// # Safety
+///
+/// `schema` must be valid for writes.
+pub unsafe fn parameters(
+    schema: *mut *mut c_char,
+    parameters_schema: impl FnOnce() -> Option<String>,
+) -> *mut c_char {
+    match catch("parameters", || Ok(parameters_schema())) {
+        Ok(value) => {
+            let value = value.map_or(std::ptr::null_mut(), into_c_string);
+            unsafe { *schema = value };
+            std::ptr::null_mut()
+        }
+        Err(message) => into_c_string(message),
+    }
+}
+
+/// # Safety
+///
+/// `parameters` must be a valid C string and `strategy` valid for writes.
+pub unsafe fn create<S: Strategy + 'static>(
+    parameters: *const c_char,
+    strategy: *mut *mut PluginStrategy,
+    build: impl FnOnce(&str) -> Result<S, String>,
+) -> *mut c_char {
+    let parameters = unsafe { CStr::from_ptr(parameters) }.to_string_lossy();
+    let build = || {
+        let value = build(&parameters)?;
+        value
+            .validate()
+            .map_err(|e| format!("Invalid strategy parameters: {}", e))?;
+        Ok(value)
+    };
+    match catch("constructor", build) {
+        Ok(value) => {
+            let value = Box::new(PluginStrategy(Box::new(value)));
+            unsafe { *strategy = Box::into_raw(value) };
+            std::ptr::null_mut()
+        }
+        Err(message) => into_c_string(message),
+    }
+}
+
+/// # Safety
+///
+/// `strategy` must come from [`create`] and not be used afterwards.
+pub unsafe fn destroy(strategy: *mut PluginStrategy) {
+    let strategy = unsafe { Box::from_raw(strategy) };
+    // Nobody is left to report a panic in the strategy's `Drop` to.
+    let _ = catch("drop", || {
+        drop(strategy);
+        Ok(())
+    });
+}
+
+/// # Safety
+///
+/// `strategy` must come from [`create`] and `json` be valid for writes.
+pub unsafe fn timeframes(strategy: *const PluginStrategy, json: *mut *mut c_char) -> *mut c_char {
+    let strategy = unsafe { &*strategy };
+    unsafe { query("timeframes", json, || strategy.0.timeframes()) }
+}
+
+/// # Safety
+///
+/// `strategy` must come from [`create`] and `json` be valid for writes.
+pub unsafe fn lookback(strategy: *const PluginStrategy, json: *mut *mut c_char) -> *mut c_char {
+    let strategy = unsafe { &*strategy };
+    unsafe { query("lookback", json, || strategy.0.lookback()) }
+}
+
+/// # Safety
+///
+/// `strategy` must come from [`create`] and `context` be valid.
+pub unsafe fn on_start(
+    strategy: *mut PluginStrategy,
+    context: *const StrategyContext,
+) -> *mut c_char {
+    let (strategy, context) = unsafe { (&mut *strategy, &*context) };
+    hook("on_start", || strategy.0.on_start(context))
+}
+
+/// # Safety
+///
+/// `strategy` must come from [`create`] and `context` be valid.
+pub unsafe fn tick(strategy: *mut PluginStrategy, context: *mut StrategyContext) -> *mut c_char {
+    let (strategy, context) = unsafe { (&mut *strategy, &mut *context) };
+    hook("tick", || strategy.0.tick(context))
+}
+
+/// # Safety
+///
+/// `strategy` must come from [`create`] and the other pointers be valid.
+pub unsafe fn on_order_filled(
+    strategy: *mut PluginStrategy,
+    context: *mut StrategyContext,
+    order: *const Order,
+    trade: *const Trade,
+) -> *mut c_char {
+    let (strategy, context) = unsafe { (&mut *strategy, &mut *context) };
+    let (order, trade) = unsafe { (&*order, &*trade) };
+    hook("on_order_filled", || {
+        strategy.0.on_order_filled(context, order, trade)
+    })
+}
+
+/// # Safety
+///
+/// `strategy` must come from [`create`] and the other pointers be valid.
+pub unsafe fn on_order_cancelled(
+    strategy: *mut PluginStrategy,
+    context: *mut StrategyContext,
+    order: *const Order,
+) -> *mut c_char {
+    let (strategy, context, order) = unsafe { (&mut *strategy, &mut *context, &*order) };
+    hook("on_order_cancelled", || {
+        strategy.0.on_order_cancelled(context, order)
+    })
+}
+
+/// # Safety
+///
+/// `strategy` must come from [`create`] and `context` be valid.
+pub unsafe fn on_end(strategy: *mut PluginStrategy, context: *const StrategyContext) -> *mut c_char {
+    let (strategy, context) = unsafe { (&mut *strategy, &*context) };
+    hook("on_end", || strategy.0.on_end(context))
+}
+
+/// # Safety
+///
+/// `string` must be null or returned by this plugin, and not used afterwards.
+pub unsafe fn free_string(string: *mut c_char) {
+    if !string.is_null() {
+        drop(unsafe { CString::from_raw(string) });
+    }
+}
+
+/// Runs `call`, turning a panic into an error message, since a panic must
+/// not unwind out of an `extern "C"` function.
+fn catch<T>(name: &str, call: impl FnOnce() -> Result<T, String>) -> Result<T, String> {
+    panic::catch_unwind(AssertUnwindSafe(call)).unwrap_or_else(|payload| {
+        Err(format!(
+            "Strategy panicked in {}: {}",
+            name,
+            panic_message(payload.as_ref())
+        ))
+    })
+}
+
+unsafe fn query<T: Serialize>(
+    name: &str,
+    json: *mut *mut c_char,
+    read: impl FnOnce() -> T,
+) -> *mut c_char {
+    let read = || serde_json::to_string(&read()).map_err(|e| e.to_string());
+    match catch(name, read) {
+        Ok(value) => {
+            unsafe { *json = into_c_string(value) };
+            std::ptr::null_mut()
+        }
+        Err(message) => into_c_string(message),
+    }
+}
+
+fn hook(name: &str, call: impl FnOnce() -> AppResult<()>) -> *mut c_char {
+    match catch(name, || call().map_err(strategy_message)) {
+        Ok(()) => std::ptr::null_mut(),
+        Err(message) => into_c_string(message),
+    }
+}
+
+/// The message of a strategy error, or the whole error otherwise.
+pub(crate) fn strategy_message(error: AppError) -> String {
+    match error {
+        AppError::Strategy(message) => message,
+        error => error.to_string(),
+    }
+}
+
+fn into_c_string(string: String) -> *mut c_char {
+    CString::new(string.replace('\0', "")).unwrap().into_raw()
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strategy::DEFAULT_LOOKBACK;
+
+    #[derive(Default)]
+    struct Idle;
+
+    impl Strategy for Idle {
+        fn tick(&mut self, _context: &mut StrategyContext) -> AppResult<()> {
+            Ok(())
+        }
+    }
+
+    struct Period(usize);
+
+    impl Strategy for Period {
+        fn validate(&self) -> AppResult<()> {
+            if self.0 == 0 {
+                return Err(AppError::Strategy("period must be positive".into()));
+            }
+            Ok(())
+        }
+
+        fn tick(&mut self, _context: &mut StrategyContext) -> AppResult<()> {
+            Ok(())
+        }
+    }
+
+    fn take_error(error: *mut c_char) -> Option<String> {
+        if error.is_null() {
+            return None;
+        }
+        let message = unsafe { CStr::from_ptr(error) }
+            .to_string_lossy()
+            .into_owned();
+        unsafe { free_string(error) };
+        Some(message)
+    }
+
+    #[test]
+    fn create_reports_errors_and_panics_as_messages() {
+        let parameters = CString::new("{}").unwrap();
+        let mut strategy = std::ptr::null_mut();
+
+        let error = unsafe {
+            create(parameters.as_ptr(), &mut strategy, |_| {
+                Err::<Idle, _>("Invalid strategy parameters: missing field".into())
+            })
+        };
+        assert_eq!(
+            take_error(error).unwrap(),
+            "Invalid strategy parameters: missing field"
+        );
+        assert!(strategy.is_null());
+
+        let error = unsafe {
+            create(parameters.as_ptr(), &mut strategy, |_| -> Result<Idle, _> {
+                panic!("bad default")
+            })
+        };
+        assert_eq!(
+            take_error(error).unwrap(),
+            "Strategy panicked in constructor: bad default"
+        );
+
+        let error = unsafe {
+            create(parameters.as_ptr(), &mut strategy, |json| {
+                assert_eq!(json, "{}");
+                Ok(Idle)
+            })
+        };
+        assert!(take_error(error).is_none());
+        assert_eq!(
+            unsafe { (*strategy).0.lookback() },
+            Some(DEFAULT_LOOKBACK)
+        );
+        unsafe { destroy(strategy) };
+
+        assert!(build_id().to_str().unwrap().starts_with("fettle "));
+    }
+
+    #[test]
+    fn create_rejects_strategies_that_fail_validation() {
+        let parameters = CString::new("").unwrap();
+        let mut strategy = std::ptr::null_mut();
+
+        let error = unsafe { create(parameters.as_ptr(), &mut strategy, |_| Ok(Period(0))) };
+        assert_eq!(
+            take_error(error).unwrap(),
+            "Invalid strategy parameters: Strategy Error: period must be positive"
+        );
+        assert!(strategy.is_null());
+
+        let error = unsafe { create(parameters.as_ptr(), &mut strategy, |_| Ok(Period(3))) };
+        assert!(take_error(error).is_none());
+        unsafe { destroy(strategy) };
+    }
+}
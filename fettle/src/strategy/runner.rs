@@ -0,0 +1,536 @@
+mod protocol;
+
+use crate::errors::{AppError, AppResult};
+use crate::models::{Candle, Timeframe};
+use crate::strategy::plugin::strategy_message;
+use crate::strategy::{
+    CandleWindow, Market, Order, Strategy, StrategyContext, StrategyHandle, Trade,
+};
+use chrono::{DateTime, Utc};
+use protocol::{Account, Delta, MarketDelta, MarketSetup, MarketUpdate, Request, Response, Update};
+use std::io::BufReader;
+use std::mem;
+use std::os::fd::OwnedFd;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// Name of the runner binary, installed next to the server's.
+pub const RUNNER_BINARY_NAME: &str = "strategy-runner";
+
+/// A strategy hosted by a runner process, which keeps a copy of the context.
+/// Every call sends the runner what changed in the context since the previous
+/// call, and applies what the strategy changed in return.
+pub(crate) struct Runner {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+    parameters_schema: Option<serde_json::Value>,
+    timeframes: Vec<Timeframe>,
+    lookback: Option<usize>,
+    /// Trades of the context the runner has seen.
+    synced_trades: usize,
+    /// Open time of the latest candle the runner has seen, per market and
+    /// series.
+    synced_candles: Vec<SyncedCandles>,
+}
+
+#[derive(Default)]
+struct SyncedCandles {
+    candles: Option<DateTime<Utc>>,
+    higher_candles: Vec<(Timeframe, Option<DateTime<Utc>>)>,
+}
+
+impl Runner {
+    /// Starts `runner` with the library at `lib_path` and builds its strategy,
+    /// waiting at most `load_timeout` for it. The server and the runner talk
+    /// over a socket handed to the runner as its standard input, leaving
+    /// standard output to the strategy.
+    pub(crate) fn spawn(
+        runner: &Path,
+        lib_path: &Path,
+        parameters: Option<&serde_json::Value>,
+        load_timeout: Option<Duration>,
+    ) -> AppResult<(Self, Child)> {
+        let (socket, runner_socket) = UnixStream::pair()?;
+        let mut process = Command::new(runner)
+            .arg(lib_path)
+            .stdin(Stdio::from(OwnedFd::from(runner_socket)))
+            .spawn()
+            .map_err(|e| {
+                AppError::Strategy(format!(
+                    "Failed to start strategy runner {}: {}",
+                    runner.display(),
+                    e
+                ))
+            })?;
+
+        match Self::connect(socket, parameters, load_timeout) {
+            Ok(runner) => Ok((runner, process)),
+            Err(e) => {
+                let _ = process.kill();
+                let _ = process.wait();
+                Err(e)
+            }
+        }
+    }
+
+    /// Builds the strategy of the runner at the other end of `socket`.
+    pub(crate) fn connect(
+        socket: UnixStream,
+        parameters: Option<&serde_json::Value>,
+        load_timeout: Option<Duration>,
+    ) -> AppResult<Self> {
+        socket.set_read_timeout(load_timeout)?;
+        let mut runner = Self {
+            reader: BufReader::new(socket.try_clone()?),
+            writer: socket,
+            parameters_schema: None,
+            timeframes: Vec::new(),
+            lookback: None,
+            synced_trades: 0,
+            synced_candles: Vec::new(),
+        };
+
+        let load = Request::Load {
+            parameters: parameters.cloned(),
+        };
+        let started = Instant::now();
+        let response = runner.call(&load).map_err(|e| match load_timeout {
+            Some(limit) if started.elapsed() >= limit => AppError::Strategy(format!(
+                "Strategy runner did not load the strategy within {:?}",
+                limit
+            )),
+            _ => e,
+        })?;
+        match response {
+            Response::Loaded {
+                parameters_schema,
+                timeframes,
+                lookback,
+            } => {
+                // Calls are timed by the watchdog instead.
+                runner.writer.set_read_timeout(None)?;
+                runner.parameters_schema = parameters_schema;
+                runner.timeframes = timeframes;
+                runner.lookback = lookback;
+                Ok(runner)
+            }
+            response => Err(failure(response)),
+        }
+    }
+
+    pub(crate) fn parameters_schema(&self) -> Option<&serde_json::Value> {
+        self.parameters_schema.as_ref()
+    }
+
+    fn call(&mut self, request: &Request) -> AppResult<Response> {
+        let exited = || AppError::Strategy("Strategy runner exited unexpectedly".into());
+        protocol::send(&mut self.writer, request).map_err(|_| exited())?;
+        protocol::receive(&mut self.reader)?.ok_or_else(exited)
+    }
+
+    /// Sends `request` and applies the strategy's changes to `context`, for
+    /// the calls that may change it.
+    fn run(&mut self, request: Request, context: Option<&mut StrategyContext>) -> AppResult<()> {
+        let update = match self.call(&request)? {
+            Response::Done { update } => update,
+            response => return Err(failure(response)),
+        };
+        let Some(context) = context else {
+            return Ok(());
+        };
+
+        context.balance = update.balance;
+        context.trades.extend(update.fills);
+        self.synced_trades = context.trades.len();
+        for (market, update) in context.markets.iter_mut().zip(update.markets) {
+            market.position = update.position;
+            market.orders = update.orders;
+            market.volume_left = update.volume_left;
+        }
+
+        let output = update.output;
+        for entry in output.logs {
+            context.output.log(entry);
+        }
+        context.output.dropped_logs += output.dropped_logs;
+        for series in output.plots {
+            for point in series.points {
+                context.output.plot(&series.name, &series.symbol, point);
+            }
+        }
+        Ok(())
+    }
+
+    /// What changed in `context` since the runner last saw it.
+    fn delta(&mut self, context: &StrategyContext) -> Delta {
+        let fills = context.trades[self.synced_trades..].to_vec();
+        self.synced_trades = context.trades.len();
+
+        self.synced_candles
+            .resize_with(context.markets.len(), SyncedCandles::default);
+        let markets = context
+            .markets
+            .iter()
+            .zip(&mut self.synced_candles)
+            .map(|(market, synced)| {
+                let higher_candles = market
+                    .higher_candles
+                    .iter()
+                    .map(|(timeframe, candles)| {
+                        let index = match synced
+                            .higher_candles
+                            .iter()
+                            .position(|(tf, _)| tf == timeframe)
+                        {
+                            Some(index) => index,
+                            None => {
+                                synced.higher_candles.push((*timeframe, None));
+                                synced.higher_candles.len() - 1
+                            }
+                        };
+                        let latest = &mut synced.higher_candles[index].1;
+                        (*timeframe, new_candles(candles, latest))
+                    })
+                    .collect();
+
+                MarketDelta {
+                    candles: new_candles(&market.candles, &mut synced.candles),
+                    higher_candles,
+                    position: market.position.clone(),
+                    orders: market.orders.clone(),
+                    volume_left: market.volume_left.clone(),
+                }
+            })
+            .collect();
+
+        Delta {
+            balance: context.balance.clone(),
+            current: context.current,
+            fills,
+            markets,
+        }
+    }
+}
+
+impl Strategy for Runner {
+    fn timeframes(&self) -> Vec<Timeframe> {
+        self.timeframes.clone()
+    }
+
+    fn lookback(&self) -> Option<usize> {
+        self.lookback
+    }
+
+    fn on_start(&mut self, context: &StrategyContext) -> AppResult<()> {
+        let account = Account {
+            markets: context
+                .markets
+                .iter()
+                .map(|market| MarketSetup {
+                    symbol: market.symbol.clone(),
+                    fees: market.fees.clone(),
+                    precision: market.precision.clone(),
+                })
+                .collect(),
+            timeframe: context.timeframe,
+            margin: context.margin.clone(),
+            fill_model: context.fill_model,
+            slippage_model: context.slippage_model.clone(),
+            liquidity: context.liquidity.clone(),
+        };
+        let delta = self.delta(context);
+        let account = Box::new(account);
+        self.run(Request::Start { account, delta }, None)
+    }
+
+    fn tick(&mut self, context: &mut StrategyContext) -> AppResult<()> {
+        let delta = self.delta(context);
+        self.run(Request::Tick { delta }, Some(context))
+    }
+
+    fn on_order_filled(
+        &mut self,
+        context: &mut StrategyContext,
+        order: &Order,
+        trade: &Trade,
+    ) -> AppResult<()> {
+        let delta = self.delta(context);
+        let (order, trade) = (Box::new(order.clone()), Box::new(trade.clone()));
+        let request = Request::OrderFilled {
+            delta,
+            order,
+            trade,
+        };
+        self.run(request, Some(context))
+    }
+
+    fn on_order_cancelled(
+        &mut self,
+        context: &mut StrategyContext,
+        order: &Order,
+    ) -> AppResult<()> {
+        let delta = self.delta(context);
+        let order = Box::new(order.clone());
+        self.run(Request::OrderCancelled { delta, order }, Some(context))
+    }
+
+    fn on_end(&mut self, context: &StrategyContext) -> AppResult<()> {
+        let delta = self.delta(context);
+        self.run(Request::End { delta }, None)
+    }
+}
+
+/// Candles of `window` opened after `latest`, which then becomes the latest.
+fn new_candles(window: &[Candle], latest: &mut Option<DateTime<Utc>>) -> Vec<Candle> {
+    let start = match latest {
+        Some(latest) => window.partition_point(|c| c.timestamp <= *latest),
+        None => 0,
+    };
+    if let Some(last) = window.last() {
+        *latest = Some(last.timestamp);
+    }
+    window[start..].to_vec()
+}
+
+fn failure(response: Response) -> AppError {
+    match response {
+        Response::Failed { message } => AppError::Strategy(message),
+        response => AppError::Strategy(format!("Unexpected runner response: {:?}", response)),
+    }
+}
+
+/// Hosts the strategy of the library at `lib_path` for the server connected
+/// through `socket`, until the server hangs up. The strategy acts on a copy
+/// of the server's context, brought up to date before every call.
+pub fn serve(lib_path: &Path, socket: UnixStream) -> AppResult<()> {
+    serve_with(socket, |parameters| {
+        let strategy = StrategyHandle::try_from_path(&PathBuf::from(lib_path), parameters)?;
+        let parameters_schema = strategy.parameters_schema().cloned();
+        Ok((Box::new(strategy), parameters_schema))
+    })
+}
+
+/// A strategy built for the server and the schema of its parameters.
+type Loaded = (Box<dyn Strategy>, Option<serde_json::Value>);
+
+/// Like [`serve`], with the strategy built from the server's parameters by
+/// `load`.
+pub(crate) fn serve_with(
+    socket: UnixStream,
+    load: impl FnOnce(Option<&serde_json::Value>) -> AppResult<Loaded>,
+) -> AppResult<()> {
+    let mut reader = BufReader::new(socket.try_clone()?);
+    let mut writer = socket;
+
+    let Some(Request::Load { parameters }) = protocol::receive(&mut reader)? else {
+        return Err(AppError::Strategy("Expected a load request".into()));
+    };
+    let (mut strategy, parameters_schema) = match load(parameters.as_ref()) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            let message = strategy_message(e);
+            return protocol::send(&mut writer, &Response::Failed { message });
+        }
+    };
+    let loaded = Response::Loaded {
+        parameters_schema,
+        timeframes: strategy.timeframes(),
+        lookback: strategy.lookback(),
+    };
+    protocol::send(&mut writer, &loaded)?;
+
+    let mut mirror = Mirror {
+        context: None,
+        lookback: strategy.lookback().map(|lookback| lookback.max(1)),
+    };
+    while let Some(request) = protocol::receive(&mut reader)? {
+        let response = match mirror.handle(strategy.as_mut(), request) {
+            Ok(update) => Response::Done {
+                update: Box::new(update),
+            },
+            Err(e) => Response::Failed {
+                message: strategy_message(e),
+            },
+        };
+        protocol::send(&mut writer, &response)?;
+    }
+    Ok(())
+}
+
+/// The runner's copy of the server's context.
+struct Mirror {
+    context: Option<StrategyContext<'static>>,
+    lookback: Option<usize>,
+}
+
+impl Mirror {
+    fn handle(&mut self, strategy: &mut dyn Strategy, request: Request) -> AppResult<Update> {
+        let (delta, call) = match request {
+            Request::Load { .. } => {
+                return Err(AppError::Strategy("Strategy is already loaded".into()));
+            }
+            Request::Start { account, delta } => {
+                self.open(*account, &delta)?;
+                (delta, Call::Start)
+            }
+            Request::Tick { delta } => (delta, Call::Tick),
+            Request::OrderFilled {
+                delta,
+                order,
+                trade,
+            } => (delta, Call::OrderFilled(order, trade)),
+            Request::OrderCancelled { delta, order } => (delta, Call::OrderCancelled(order)),
+            Request::End { delta } => (delta, Call::End),
+        };
+
+        let context = self.apply(delta)?;
+        let synced_trades = context.trades.len();
+        match call {
+            Call::Start => strategy.on_start(context)?,
+            Call::Tick => strategy.tick(context)?,
+            Call::OrderFilled(order, trade) => strategy.on_order_filled(context, &order, &trade)?,
+            Call::OrderCancelled(order) => strategy.on_order_cancelled(context, &order)?,
+            Call::End => strategy.on_end(context)?,
+        }
+        Ok(Self::update(context, synced_trades))
+    }
+
+    fn open(&mut self, account: Account, delta: &Delta) -> AppResult<()> {
+        let markets = account
+            .markets
+            .into_iter()
+            .map(|market| Market::new(market.symbol, market.fees, market.precision))
+            .collect();
+        self.context = Some(StrategyContext::new(
+            delta.balance.clone(),
+            markets,
+            account.timeframe,
+            account.margin,
+            account.fill_model,
+            account.slippage_model,
+            account.liquidity,
+        )?);
+        Ok(())
+    }
+
+    /// Brings the copy up to date with the server's context.
+    fn apply(&mut self, delta: Delta) -> AppResult<&mut StrategyContext<'static>> {
+        let Some(context) = &mut self.context else {
+            return Err(AppError::Strategy("Strategy has not started".into()));
+        };
+
+        context.balance = delta.balance;
+        context.current = delta.current;
+        context.trades.extend(delta.fills);
+        // Events are only reported by the server.
+        context.events.clear();
+        for (market, delta) in context.markets.iter_mut().zip(delta.markets) {
+            for candle in delta.candles {
+                market.candles.push(candle, self.lookback);
+            }
+            for (timeframe, candles) in delta.higher_candles {
+                let index = match market
+                    .higher_candles
+                    .iter()
+                    .position(|(tf, _)| *tf == timeframe)
+                {
+                    Some(index) => index,
+                    None => {
+                        market
+                            .higher_candles
+                            .push((timeframe, CandleWindow::default()));
+                        market.higher_candles.len() - 1
+                    }
+                };
+                for candle in candles {
+                    market.higher_candles[index].1.push(candle, self.lookback);
+                }
+            }
+            market.position = delta.position;
+            market.orders = delta.orders;
+            market.volume_left = delta.volume_left;
+        }
+        Ok(context)
+    }
+
+    /// What the strategy changed, given the trades the context had before.
+    fn update(context: &mut StrategyContext, synced_trades: usize) -> Update {
+        Update {
+            balance: context.balance.clone(),
+            fills: context.trades[synced_trades..].to_vec(),
+            markets: context
+                .markets
+                .iter()
+                .map(|market| MarketUpdate {
+                    position: market.position.clone(),
+                    orders: market.orders.clone(),
+                    volume_left: market.volume_left.clone(),
+                })
+                .collect(),
+            output: mem::take(&mut context.output),
+        }
+    }
+}
+
+enum Call {
+    Start,
+    Tick,
+    OrderFilled(Box<Order>, Box<Trade>),
+    OrderCancelled(Box<Order>),
+    End,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bigdecimal::BigDecimal;
+
+    fn candle(minute: i64) -> Candle {
+        Candle {
+            timestamp: DateTime::from_timestamp(minute * 60, 0).unwrap(),
+            exchange: "binance".into(),
+            symbol: "BTC/USDT".into(),
+            timeframe: Timeframe::M1,
+            open: BigDecimal::from(100),
+            high: BigDecimal::from(101),
+            low: BigDecimal::from(99),
+            close: BigDecimal::from(100),
+            volume: BigDecimal::from(10),
+        }
+    }
+
+    #[test]
+    fn deltas_carry_only_unseen_candles_and_messages_round_trip() {
+        let mut window: CandleWindow = CandleWindow::default();
+        let mut latest = None;
+        for minute in 0..3 {
+            window.push(candle(minute), Some(2));
+        }
+        assert_eq!(new_candles(&window, &mut latest).len(), 2);
+        assert!(new_candles(&window, &mut latest).is_empty());
+        window.push(candle(3), Some(2));
+        let delta = new_candles(&window, &mut latest);
+        assert_eq!(delta.len(), 1);
+        assert_eq!(delta[0].timestamp, candle(3).timestamp);
+
+        let (mut server, runner) = UnixStream::pair().unwrap();
+        let mut reader = BufReader::new(runner);
+        let tick = Request::Tick {
+            delta: Delta {
+                balance: BigDecimal::from(1000),
+                current: 0,
+                fills: Vec::new(),
+                markets: Vec::new(),
+            },
+        };
+        protocol::send(&mut server, &tick).unwrap();
+        drop(server);
+        let Some(Request::Tick { delta }) = protocol::receive(&mut reader).unwrap() else {
+            panic!("expected a tick");
+        };
+        assert_eq!(delta.balance, BigDecimal::from(1000));
+        assert!(protocol::receive::<Request>(&mut reader).unwrap().is_none());
+    }
+}
@@ -0,0 +1,133 @@
+use crate::errors::{AppError, AppResult};
+use crate::models::{Candle, MarketPrecision, Timeframe, TradingFees};
+use crate::strategy::{
+    FillModel, LiquidityConfig, MarginConfig, Order, SlippageModel, StrategyOutput, Trade,
+};
+use bigdecimal::BigDecimal;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, Write};
+
+/// Messages the server sends to a runner. Every message but `Load` is
+/// answered by [`Response::Done`] or [`Response::Failed`].
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum Request {
+    /// Builds the strategy, answered by [`Response::Loaded`].
+    Load {
+        parameters: Option<serde_json::Value>,
+    },
+    /// Opens the account the strategy trades and calls `on_start`.
+    Start {
+        account: Box<Account>,
+        delta: Delta,
+    },
+    Tick {
+        delta: Delta,
+    },
+    OrderFilled {
+        delta: Delta,
+        order: Box<Order>,
+        trade: Box<Trade>,
+    },
+    OrderCancelled {
+        delta: Delta,
+        order: Box<Order>,
+    },
+    End {
+        delta: Delta,
+    },
+}
+
+/// Messages a runner sends back to the server.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum Response {
+    Loaded {
+        parameters_schema: Option<serde_json::Value>,
+        timeframes: Vec<Timeframe>,
+        lookback: Option<usize>,
+    },
+    /// The call returned, with what the strategy changed.
+    Done { update: Box<Update> },
+    /// The call returned a strategy error or panicked.
+    Failed { message: String },
+}
+
+/// Settings of the backtest's account, which do not change while it runs.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Account {
+    pub(crate) markets: Vec<MarketSetup>,
+    pub(crate) timeframe: Timeframe,
+    pub(crate) margin: MarginConfig,
+    pub(crate) fill_model: FillModel,
+    pub(crate) slippage_model: SlippageModel,
+    pub(crate) liquidity: Option<LiquidityConfig>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct MarketSetup {
+    pub(crate) symbol: String,
+    pub(crate) fees: TradingFees,
+    pub(crate) precision: MarketPrecision,
+}
+
+/// What changed in the server's context since the previous message: the
+/// candles and trades added since, and the balance, positions and orders as
+/// they are now.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Delta {
+    pub(crate) balance: BigDecimal,
+    /// Index of the market the call acts on.
+    pub(crate) current: usize,
+    /// Fills of resting orders and liquidations.
+    pub(crate) fills: Vec<Trade>,
+    pub(crate) markets: Vec<MarketDelta>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct MarketDelta {
+    pub(crate) candles: Vec<Candle>,
+    pub(crate) higher_candles: Vec<(Timeframe, Vec<Candle>)>,
+    pub(crate) position: BigDecimal,
+    pub(crate) orders: Vec<Order>,
+    pub(crate) volume_left: Option<BigDecimal>,
+}
+
+/// What the strategy changed during a call: the orders it placed and
+/// cancelled, the market orders it filled and what it logged and plotted.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Update {
+    pub(crate) balance: BigDecimal,
+    pub(crate) fills: Vec<Trade>,
+    pub(crate) markets: Vec<MarketUpdate>,
+    pub(crate) output: StrategyOutput,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct MarketUpdate {
+    pub(crate) position: BigDecimal,
+    pub(crate) orders: Vec<Order>,
+    pub(crate) volume_left: Option<BigDecimal>,
+}
+
+/// Writes `message` as one line of JSON.
+pub(crate) fn send(writer: &mut impl Write, message: &impl Serialize) -> AppResult<()> {
+    let mut line = serde_json::to_vec(message)
+        .map_err(|e| AppError::Strategy(format!("Invalid runner message: {}", e)))?;
+    line.push(b'\n');
+    writer.write_all(&line)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Reads the next message, or `None` once the other side has hung up.
+pub(crate) fn receive<T: DeserializeOwned>(reader: &mut impl BufRead) -> AppResult<Option<T>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    serde_json::from_str(&line)
+        .map(Some)
+        .map_err(|e| AppError::Strategy(format!("Invalid runner message: {}", e)))
+}
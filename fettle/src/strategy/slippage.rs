@@ -0,0 +1,84 @@
+use crate::errors::{AppError, AppResult};
+use crate::models::Candle;
+use bigdecimal::{BigDecimal, Zero};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+/// How far a market execution moves away from the quoted price.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum SlippageModel {
+    /// Fills at the quoted price.
+    #[default]
+    None,
+    /// A fixed fraction of the price, in basis points.
+    FixedBps {
+        #[ts(type = "string")]
+        bps: BigDecimal,
+    },
+    /// A percentage of the candle's high-low range.
+    RangePercent {
+        #[ts(type = "string")]
+        percent: BigDecimal,
+    },
+    /// `price * impact * sqrt(amount / volume)`, growing with the share of the
+    /// candle's volume the order takes.
+    VolumeParticipation {
+        #[ts(type = "string")]
+        impact: BigDecimal,
+    },
+}
+
+impl SlippageModel {
+    pub fn validate(&self) -> AppResult<()> {
+        let value = match self {
+            SlippageModel::None => return Ok(()),
+            SlippageModel::FixedBps { bps } => bps,
+            SlippageModel::RangePercent { percent } => percent,
+            SlippageModel::VolumeParticipation { impact } => impact,
+        };
+
+        if *value < BigDecimal::zero() {
+            return Err(AppError::BadRequest(format!(
+                "Slippage must not be negative, got {}",
+                value
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Whether a market execution can fill within `candle`. Volume-based
+    /// slippage has no liquidity to fill against in a candle nothing traded in.
+    pub(crate) fn has_liquidity(&self, candle: &Candle) -> bool {
+        !matches!(self, SlippageModel::VolumeParticipation { .. })
+            || candle.volume > BigDecimal::zero()
+    }
+
+    /// Adverse price move per unit for executing `amount` at `price` within
+    /// `candle`. Only meaningful if the candle [has liquidity](Self::has_liquidity).
+    pub(crate) fn slippage(
+        &self,
+        candle: &Candle,
+        price: &BigDecimal,
+        amount: &BigDecimal,
+    ) -> BigDecimal {
+        match self {
+            SlippageModel::None => BigDecimal::zero(),
+            SlippageModel::FixedBps { bps } => price * bps / BigDecimal::from(10000),
+            SlippageModel::RangePercent { percent } => {
+                (&candle.high - &candle.low) * percent / BigDecimal::from(100)
+            }
+            SlippageModel::VolumeParticipation { impact } => {
+                let participation = if candle.volume > BigDecimal::zero() {
+                    amount / &candle.volume
+                } else {
+                    BigDecimal::zero()
+                };
+                let participation = participation.sqrt().unwrap_or_else(BigDecimal::zero);
+                price * impact * participation
+            }
+        }
+    }
+}
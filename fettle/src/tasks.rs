@@ -1,5 +1,18 @@
 pub mod backtest;
+pub mod control;
 pub mod fetch_candles;
+pub mod optimize;
+pub mod walk_forward;
+pub mod workers;
 
-pub use backtest::{BacktestStatistic, BacktestStatus, BacktestTask};
+pub use backtest::{
+    BacktestConfig, BacktestInputs, BacktestStatistic, BacktestStatus, BacktestTask, EquityPoint,
+    Reproduction, RoundTrip,
+};
+pub use control::TaskControl;
 pub use fetch_candles::{FetchCandlesResult, FetchCandlesStatus, FetchCandlesTask};
+pub use optimize::{
+    OptimizationMetric, OptimizationResult, OptimizationTask, ParameterRange, SearchMethod,
+};
+pub use walk_forward::{WalkForwardTask, WalkForwardWindow};
+pub use workers::{TimeBudget, WorkerPool};
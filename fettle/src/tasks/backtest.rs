@@ -1,19 +1,36 @@
-use crate::errors::AppResult;
+mod feed;
+
+use crate::errors::{AppError, AppResult};
 use crate::exchange::ccxt::CCXT;
-use crate::models::{Candle, MarketPrecision, Timeframe};
-use crate::services::candles::get_candles;
+use crate::models::{Candle, MarketPrecision, Timeframe, TradingFees};
+use crate::services::candles::{get_candles, resample_candles};
 use crate::services::tasks::save_backtest_task;
-use crate::strategy::{StrategyContext, StrategyHandle, StrategyManager, Trade, TradeType};
+use crate::strategy::{
+    FillModel, LiquidityConfig, MarginConfig, Market, SlippageModel, Strategy, StrategyBuild,
+    StrategyContext, StrategyHandle, StrategyManager, StrategyOutput, Trade,
+};
+use crate::tasks::{TaskControl, WorkerPool};
 use bigdecimal::{BigDecimal, RoundingMode, ToPrimitive, Zero};
-use chrono::{DateTime, Utc, serde::ts_milliseconds, serde::ts_milliseconds_option};
+use chrono::{DateTime, TimeDelta, Utc, serde::ts_milliseconds, serde::ts_milliseconds_option};
+use feed::{CandleFeed, HigherSource, MemoryFeed, StreamFeed, SubCandleSource, higher_source};
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
+use std::collections::HashMap;
+use std::mem;
 use std::sync::Arc;
-use tokio::sync::{RwLock, broadcast};
+use tokio::sync::{RwLock, broadcast, watch};
 use ts_rs::TS;
 use uuid::Uuid;
 
 const BACKTEST_BROADCAST_INTERVAL: usize = 100;
+const MAX_EQUITY_POINTS: usize = 2000;
+/// Shortest span the growth rate is annualized over. Compounding a few days
+/// of returns over a year says nothing and overflows easily.
+const MIN_CAGR_SPAN_DAYS: i64 = 30;
+
+pub(crate) fn default_initial_capital() -> BigDecimal {
+    BigDecimal::from(10000)
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize, TS)]
 #[ts(export)]
@@ -36,7 +53,51 @@ pub struct BacktestStatistic {
     #[ts(type = "string")]
     pub gross_loss: BigDecimal,
     pub profit_factor: f32,
+    /// Annualized from per-candle equity returns in excess of the risk-free rate.
     pub sharpe_ratio: f32,
+    /// Like `sharpe_ratio`, but only returns below the risk-free rate count as risk.
+    #[serde(default)]
+    pub sortino_ratio: f32,
+    /// `cagr_percent` divided by `max_drawdown_percent`.
+    #[serde(default)]
+    pub calmar_ratio: f32,
+    /// Compound annual growth rate of the closing equity; zero for backtests
+    /// spanning less than 30 days.
+    #[serde(default)]
+    pub cagr_percent: f32,
+    /// Share of candles that closed with a position open.
+    #[serde(default)]
+    pub exposure_percent: f32,
+    /// Mean time from opening a position to closing or reversing it, in
+    /// milliseconds.
+    #[serde(default)]
+    #[ts(type = "number")]
+    pub avg_holding_time: i64,
+    /// Longest time the closing equity stayed below an earlier high, in
+    /// milliseconds.
+    #[serde(default)]
+    #[ts(type = "number")]
+    pub max_drawdown_duration: i64,
+    /// Return of buying every symbol with an equal share of the capital at its
+    /// first open and selling it at the last close, after taker fees.
+    #[serde(default)]
+    pub benchmark_return_percent: f32,
+    /// `return_percent` less `benchmark_return_percent`.
+    #[serde(default)]
+    pub excess_return_percent: f32,
+    /// Sensitivity of the per-candle equity returns to the benchmark's.
+    #[serde(default)]
+    pub beta: f32,
+    /// Annualized return not explained by `beta`, over the risk-free rate.
+    #[serde(default)]
+    pub alpha_percent: f32,
+    /// Correlation of the per-candle equity returns with the benchmark's.
+    #[serde(default)]
+    pub correlation: f32,
+    /// Annualized mean return over the benchmark divided by its standard
+    /// deviation.
+    #[serde(default)]
+    pub information_ratio: f32,
     pub total_trades: usize,
     pub buy_trades: usize,
     pub sell_trades: usize,
@@ -51,6 +112,70 @@ pub struct BacktestStatistic {
     pub largest_win: BigDecimal,
     #[ts(type = "string")]
     pub largest_loss: BigDecimal,
+    /// Positions from entry to exit, in the order they were closed.
+    #[serde(default)]
+    pub round_trips: Vec<RoundTrip>,
+    /// Account state at candle closes, downsampled to at most
+    /// `MAX_EQUITY_POINTS` points that keep the deepest drawdowns.
+    #[serde(default)]
+    pub equity_curve: Vec<EquityPoint>,
+}
+
+/// A position from the trade that opened it to the one that closed or
+/// reversed it. Excursions are measured on the fill prices and on the highs
+/// and lows of the candles strictly between entry and exit.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct RoundTrip {
+    pub symbol: String,
+    pub is_long: bool,
+    #[serde(with = "ts_milliseconds")]
+    #[ts(type = "number")]
+    pub entry_time: DateTime<Utc>,
+    #[serde(with = "ts_milliseconds")]
+    #[ts(type = "number")]
+    pub exit_time: DateTime<Utc>,
+    /// Average price of the fills that opened or added to the position.
+    #[ts(type = "string")]
+    pub entry_price: BigDecimal,
+    /// Average price of the fills that reduced the position.
+    #[ts(type = "string")]
+    pub exit_price: BigDecimal,
+    /// Total amount opened.
+    #[ts(type = "string")]
+    pub amount: BigDecimal,
+    #[ts(type = "string")]
+    pub fees: BigDecimal,
+    /// Realized profit after fees.
+    #[ts(type = "string")]
+    pub profit: BigDecimal,
+    /// `profit` as a share of the entry value.
+    pub return_percent: f32,
+    /// Largest move in the position's favour from `entry_price`.
+    pub mfe_percent: f32,
+    /// Largest move against the position from `entry_price`; zero or negative.
+    pub mae_percent: f32,
+    /// Candles of the symbol from the entry candle to the exit candle.
+    pub bars_held: usize,
+}
+
+/// Account state at the close of a candle.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct EquityPoint {
+    #[serde(with = "ts_milliseconds")]
+    #[ts(type = "number")]
+    pub timestamp: DateTime<Utc>,
+    #[ts(type = "string")]
+    pub equity: BigDecimal,
+    #[ts(type = "string")]
+    pub cash: BigDecimal,
+    /// Signed value of all positions at their closes.
+    #[ts(type = "string")]
+    pub position_value: BigDecimal,
+    /// Distance below the highest equity of earlier points.
+    #[ts(type = "string")]
+    pub drawdown: BigDecimal,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, TS)]
@@ -60,8 +185,104 @@ pub enum BacktestStatus {
     Pending,
     Compiling,
     Running,
+    /// Waiting to be resumed; only running backtests can be paused.
+    Paused,
     Completed,
     Failed,
+    Cancelled,
+}
+
+/// Market and simulation settings of a backtest.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct BacktestConfig {
+    pub exchange: String,
+    pub symbol: String,
+    /// Additional symbols traded alongside `symbol` from one shared balance.
+    #[serde(default)]
+    pub symbols: Vec<String>,
+    pub timeframe: Timeframe,
+    /// Object deserialized into the strategy; its defaults are used when unset.
+    #[serde(default)]
+    #[ts(optional)]
+    pub parameters: Option<serde_json::Value>,
+    #[serde(default, with = "ts_milliseconds_option")]
+    #[ts(optional, type = "number")]
+    pub start: Option<DateTime<Utc>>,
+    #[serde(default, with = "ts_milliseconds_option")]
+    #[ts(optional, type = "number")]
+    pub end: Option<DateTime<Utc>>,
+    #[serde(default = "default_initial_capital")]
+    #[ts(type = "string")]
+    pub initial_capital: BigDecimal,
+    /// Annual rate the Sharpe and Sortino ratios measure excess returns over.
+    #[serde(default)]
+    #[ts(type = "string")]
+    pub risk_free_rate: BigDecimal,
+    /// Fees charged on every symbol; the exchange's fees when unset.
+    #[serde(default)]
+    #[ts(optional)]
+    pub fees: Option<TradingFees>,
+    /// Precision of the primary symbol.
+    pub precision: MarketPrecision,
+    #[serde(default)]
+    pub margin: MarginConfig,
+    #[serde(default)]
+    pub fill_model: FillModel,
+    #[serde(default)]
+    pub slippage_model: SlippageModel,
+    #[serde(default)]
+    #[ts(optional)]
+    pub liquidity: Option<LiquidityConfig>,
+}
+
+impl BacktestConfig {
+    /// The primary symbol followed by the additional portfolio symbols.
+    pub fn all_symbols(&self) -> Vec<String> {
+        let mut symbols = vec![self.symbol.clone()];
+        symbols.extend(self.symbols.iter().cloned());
+        symbols
+    }
+}
+
+/// Everything a backtest ran on, recorded so it can be replayed.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct BacktestInputs {
+    /// SHA-256 of the strategy package's files and the workspace lock file.
+    pub source_hash: String,
+    /// SHA-256 of the compiled library, which is archived under this hash.
+    pub library_hash: String,
+    /// Earliest candle of the backtest's timeframe, over all symbols.
+    #[serde(with = "ts_milliseconds")]
+    #[ts(type = "number")]
+    pub candles_start: DateTime<Utc>,
+    /// Latest candle of the backtest's timeframe, over all symbols.
+    #[serde(with = "ts_milliseconds")]
+    #[ts(type = "number")]
+    pub candles_end: DateTime<Utc>,
+    pub candle_count: usize,
+    /// SHA-256 of every candle loaded.
+    pub candle_checksum: String,
+    /// Fees of each symbol, in the order of `BacktestConfig::all_symbols`.
+    pub fees: Vec<TradingFees>,
+    /// Precision of each symbol, in the order of `BacktestConfig::all_symbols`.
+    pub precisions: Vec<MarketPrecision>,
+    #[ts(optional)]
+    pub parameters: Option<serde_json::Value>,
+}
+
+/// How a rerun compares with the task it replayed.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct Reproduction {
+    /// Whether the candles loaded again have the recorded checksum.
+    pub candles_match: bool,
+    /// Whether the statistic equals the original one.
+    pub statistic_matches: bool,
+    /// Whether the strategy source still has the recorded hash. The archived
+    /// library is replayed either way.
+    pub source_unchanged: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, TS)]
@@ -71,12 +292,24 @@ pub struct BacktestTask {
     pub status: BacktestStatus,
     pub progress: f32,
     pub name: String,
-    pub exchange: String,
-    pub symbol: String,
-    pub timeframe: Timeframe,
-    pub precision: MarketPrecision,
+    #[serde(flatten)]
+    pub config: BacktestConfig,
+    #[serde(default)]
+    #[ts(optional)]
+    pub inputs: Option<BacktestInputs>,
+    /// Task this one replays.
+    #[serde(default)]
+    #[ts(optional)]
+    pub rerun_of: Option<Uuid>,
+    #[serde(default)]
+    #[ts(optional)]
+    pub reproduction: Option<Reproduction>,
     #[ts(optional)]
     pub statistic: Option<BacktestStatistic>,
+    /// What the strategy logged and plotted, kept when the run fails too.
+    #[serde(default)]
+    #[ts(optional)]
+    pub output: Option<StrategyOutput>,
     #[ts(optional)]
     pub error_message: Option<String>,
     #[serde(with = "ts_milliseconds")]
@@ -94,6 +327,9 @@ pub struct BacktestTask {
     #[serde(skip)]
     #[ts(skip)]
     pub event_tx: Option<broadcast::Sender<BacktestTask>>,
+    #[serde(skip)]
+    #[ts(skip)]
+    pub control: TaskControl,
 }
 
 impl BacktestTask {
@@ -112,11 +348,14 @@ impl BacktestTask {
         }
     }
 
+    /// Runs the task, or replays `original` with its recorded inputs.
     pub async fn run(
         task: Arc<RwLock<Self>>,
         strategy_manager: StrategyManager,
         strategy_name: String,
         db_pool: PgPool,
+        workers: WorkerPool,
+        original: Option<BacktestTask>,
     ) {
         let now = Utc::now();
         Self::update(&task, |task| {
@@ -126,8 +365,33 @@ impl BacktestTask {
         })
         .await;
 
-        let mut strategy_handle = match strategy_manager.load_strategy(&strategy_name).await {
-            Ok(handle) => handle,
+        let original_inputs = original.as_ref().and_then(|o| o.inputs.clone());
+        let parameters = task.read().await.config.parameters.clone();
+        let build = match &original_inputs {
+            Some(inputs) => {
+                strategy_manager
+                    .archived_library(&inputs.library_hash)
+                    .map(|lib_path| StrategyBuild {
+                        lib_path,
+                        source_hash: inputs.source_hash.clone(),
+                        library_hash: inputs.library_hash.clone(),
+                    })
+            }
+            None => {
+                strategy_manager
+                    .build_archived_strategy(&strategy_name)
+                    .await
+            }
+        };
+        let strategy_handle = match build {
+            Ok(build) => strategy_manager
+                .open_strategy(&build.lib_path, parameters.as_ref())
+                .await
+                .map(|handle| (handle, build)),
+            Err(e) => Err(e),
+        };
+        let (strategy_handle, build) = match strategy_handle {
+            Ok(loaded) => loaded,
             Err(e) => {
                 let now = Utc::now();
                 Self::update(&task, |task| {
@@ -155,23 +419,45 @@ impl BacktestTask {
         .await;
 
         let task_snapshot = task.read().await.clone();
+        let control = task_snapshot.control.clone();
         let result = Self::execute_backtest(
             &task,
             &db_pool,
-            &mut strategy_handle,
-            &task_snapshot.exchange,
-            &task_snapshot.symbol,
-            task_snapshot.timeframe,
+            &workers,
+            strategy_handle,
+            &task_snapshot.config,
+            &build,
+            original_inputs.as_ref(),
         )
         .await;
 
         let now = Utc::now();
         match result {
-            Ok(statistic) => {
+            Ok((statistic, inputs)) => {
+                let reproduction = original.map(|original| Reproduction {
+                    candles_match: original
+                        .inputs
+                        .is_some_and(|o| o.candle_checksum == inputs.candle_checksum),
+                    statistic_matches: original
+                        .statistic
+                        .is_some_and(|o| same_statistic(o, statistic.clone())),
+                    source_unchanged: strategy_manager
+                        .source_hash(&strategy_name)
+                        .is_ok_and(|hash| hash == build.source_hash),
+                });
                 Self::update(&task, |task| {
                     task.status = BacktestStatus::Completed;
                     task.progress = 100.0;
                     task.statistic = Some(statistic);
+                    task.reproduction = reproduction;
+                    task.completed_at = Some(now);
+                    task.updated_at = now;
+                })
+                .await;
+            }
+            Err(_) if control.is_cancelled() => {
+                Self::update(&task, |task| {
+                    task.status = BacktestStatus::Cancelled;
                     task.completed_at = Some(now);
                     task.updated_at = now;
                 })
@@ -194,256 +480,816 @@ impl BacktestTask {
             .expect("Failed to save backtest task");
     }
 
+    /// Runs the backtest and records its inputs. Replays use the fees and
+    /// precisions of `original_inputs` instead of the exchange's current ones.
     async fn execute_backtest(
         task: &Arc<RwLock<Self>>,
         db_pool: &PgPool,
-        strategy_handle: &mut StrategyHandle,
-        exchange: &str,
-        symbol: &str,
-        timeframe: Timeframe,
-    ) -> AppResult<BacktestStatistic> {
+        workers: &WorkerPool,
+        strategy_handle: StrategyHandle,
+        config: &BacktestConfig,
+        build: &StrategyBuild,
+        original_inputs: Option<&BacktestInputs>,
+    ) -> AppResult<(BacktestStatistic, BacktestInputs)> {
         tracing::info!(
             "Running backtest on {}/{} with timeframe {}",
-            exchange,
-            symbol,
-            timeframe
+            config.exchange,
+            config.all_symbols().join(","),
+            config.timeframe
         );
 
-        let all_candles = get_candles(db_pool, &exchange, &symbol, timeframe, None, None).await?;
-        let total_candles = all_candles.len();
-        if total_candles == 0 {
-            return Err("No candles available for backtest".into());
-        }
-
-        let initial_capital = BigDecimal::from(10000);
-        let ccxt = CCXT::with_exchange(&exchange)?;
-        let fees = ccxt.fees(&symbol)?;
-        let precision = ccxt.precision(&symbol)?;
-        let mut context = StrategyContext::new(initial_capital.clone(), fees, precision)?;
-
-        for i in 0..all_candles.len() {
-            context.candles = &all_candles[0..=i];
-
-            context.before()?;
-            strategy_handle.tick(&mut context)?;
-            context.after()?;
+        let higher_timeframes = higher_timeframes(config.timeframe, strategy_handle.timeframes())?;
+        let (fees, precisions) = match original_inputs {
+            Some(original) => (original.fees.clone(), original.precisions.clone()),
+            None => market_settings(config)?,
+        };
+        let lookback = lookback(&strategy_handle);
+        let mut feed = StreamFeed::open(db_pool, config, &higher_timeframes, lookback).await?;
 
-            if i % BACKTEST_BROADCAST_INTERVAL == 0 {
-                let progress = 100.0 * ((i + 1) as f32) / (total_candles as f32);
-                let now = Utc::now();
-                Self::update(task, |task| {
-                    task.progress = progress;
-                    task.updated_at = now;
-                })
-                .await;
+        // The simulation blocks, so it runs on the worker pool, reading the
+        // candles as it goes, and sends its progress back here to be broadcast.
+        let (progress_tx, mut progress_rx) = watch::channel(0.0);
+        let control = task.read().await.control.clone();
+        let settings = (config.clone(), fees.clone(), precisions.clone());
+        let simulation =
+            workers.run_strategy(strategy_handle, control, move |strategy, control| {
+                let (config, fees, precisions) = settings;
+                let (statistic, output) = simulate_feed(
+                    &config,
+                    &mut feed,
+                    &fees,
+                    &precisions,
+                    strategy,
+                    control,
+                    |progress| {
+                        let _ = progress_tx.send(progress);
+                    },
+                );
+                let result = statistic.and_then(|statistic| Ok((statistic, feed.finish()?)));
+                (result, output)
+            });
+        let mut simulation = std::pin::pin!(simulation);
+        let (result, output) = loop {
+            tokio::select! {
+                result = &mut simulation => break result?,
+                Ok(()) = progress_rx.changed() => {
+                    let progress = *progress_rx.borrow_and_update();
+                    let now = Utc::now();
+                    Self::update(task, |task| {
+                        task.progress = progress;
+                        task.updated_at = now;
+                    })
+                    .await;
+                }
             }
-        }
+        };
+        Self::update(task, |task| task.output = Some(output)).await;
+        let (statistic, digest) = result?;
 
-        context.end()?;
+        let inputs = BacktestInputs {
+            source_hash: build.source_hash.clone(),
+            library_hash: build.library_hash.clone(),
+            candles_start: digest.start,
+            candles_end: digest.end,
+            candle_count: digest.count,
+            candle_checksum: digest.checksum,
+            fees,
+            precisions,
+            parameters: config.parameters.clone(),
+        };
+
+        let task_inputs = inputs.clone();
         let now = Utc::now();
         Self::update(task, |task| {
+            task.inputs = Some(task_inputs);
             task.progress = 100.0;
             task.updated_at = now;
         })
         .await;
 
-        let backtest_stat = Self::calculate_backtest_statistic(
-            initial_capital,
-            context.candles(),
-            context.trades(),
-        );
+        Ok((statistic, inputs))
+    }
 
-        Ok(backtest_stat)
-    }
-
-    fn calculate_backtest_statistic(
-        initial_capital: BigDecimal,
-        candles: &[Candle],
-        trades: &[Trade],
-    ) -> BacktestStatistic {
-        let mut balance = initial_capital.clone();
-        let mut position = BigDecimal::zero();
-        let mut total_cost = BigDecimal::zero();
-        let mut max_equity = initial_capital.clone();
-        let mut max_drawdown = BigDecimal::zero();
-        let mut max_drawdown_percent = 0.0f32;
-
-        let mut buy_trades = 0usize;
-        let mut sell_trades = 0usize;
-        let mut winning_trades = 0usize;
-        let mut losing_trades = 0usize;
-        let mut gross_profit = BigDecimal::zero();
-        let mut gross_loss = BigDecimal::zero();
-        let mut largest_win = BigDecimal::zero();
-        let mut largest_loss = BigDecimal::zero();
-
-        let mut trades_iter = trades.iter().peekable();
-        let mut trades_with_profit = Vec::with_capacity(trades.len());
-
-        for candle in candles.iter() {
-            while let Some(trade) = trades_iter.peek() {
-                if trade.timestamp > candle.timestamp {
-                    break;
-                }
+    /// Annualized mean excess return over its standard deviation.
+    fn calculate_sharpe_ratio(returns: &[f64], risk_free_rate: f64, timeframe: Timeframe) -> f32 {
+        if returns.len() < 2 {
+            return 0.0;
+        }
 
-                let trade = trades_iter.next().unwrap();
-                let is_buy = matches!(trade.trade_type, TradeType::MarketBuy | TradeType::LimitBuy);
-
-                if is_buy {
-                    buy_trades += 1;
-                    let cost = &trade.price * &trade.amount + &trade.fee;
-                    total_cost += &cost;
-                    balance -= &cost;
-                    position += &trade.amount;
-                    trades_with_profit.push(trade.clone());
-                } else {
-                    sell_trades += 1;
-                    let proceeds = &trade.price * &trade.amount;
-                    let revenue = &proceeds - &trade.fee;
-                    let average_cost = if position.is_zero() {
-                        BigDecimal::zero()
-                    } else {
-                        &total_cost / &position
-                    };
-                    let profit = &revenue - (&average_cost * &trade.amount);
+        let periods = timeframe.periods_per_year();
+        let excess = excess_returns(returns, risk_free_rate, periods);
+        let mean = excess.iter().sum::<f64>() / excess.len() as f64;
+        let variance =
+            excess.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (excess.len() - 1) as f64;
 
-                    position -= &trade.amount;
-                    balance += &revenue;
+        let std_dev = variance.sqrt();
+        if std_dev == 0.0 {
+            return 0.0;
+        }
 
-                    if position.is_zero() {
-                        total_cost = BigDecimal::zero();
-                    } else {
-                        total_cost -= &average_cost * &trade.amount;
-                    }
+        (mean / std_dev * periods.sqrt()) as f32
+    }
 
-                    if profit > BigDecimal::zero() {
-                        winning_trades += 1;
-                        gross_profit += &profit;
-                        if profit > largest_win {
-                            largest_win = profit.clone();
-                        }
-                    } else if profit < BigDecimal::zero() {
-                        losing_trades += 1;
-                        gross_loss += &profit;
-                        if profit < largest_loss {
-                            largest_loss = profit.clone();
-                        }
-                    }
+    /// Annualized mean excess return over its downside deviation.
+    fn calculate_sortino_ratio(returns: &[f64], risk_free_rate: f64, timeframe: Timeframe) -> f32 {
+        if returns.len() < 2 {
+            return 0.0;
+        }
 
-                    trades_with_profit.push(Trade {
-                        timestamp: trade.timestamp,
-                        trade_type: trade.trade_type.clone(),
-                        price: trade.price.clone(),
-                        amount: trade.amount.clone(),
-                        fee: trade.fee.clone(),
-                        profit: Some(profit.clone()),
-                    });
-                }
+        let periods = timeframe.periods_per_year();
+        let excess = excess_returns(returns, risk_free_rate, periods);
+        let mean = excess.iter().sum::<f64>() / excess.len() as f64;
+        let downside = excess.iter().map(|r| r.min(0.0).powi(2)).sum::<f64>() / excess.len() as f64;
+
+        let downside_deviation = downside.sqrt();
+        if downside_deviation == 0.0 {
+            return 0.0;
+        }
+
+        (mean / downside_deviation * periods.sqrt()) as f32
+    }
+
+    /// Regression of the strategy's per-candle returns on the benchmark's.
+    fn compare_to_benchmark(
+        returns: &[f64],
+        benchmark_returns: &[f64],
+        risk_free_rate: f64,
+        timeframe: Timeframe,
+    ) -> BenchmarkComparison {
+        let len = returns.len().min(benchmark_returns.len());
+        if len < 2 {
+            return BenchmarkComparison::default();
+        }
+
+        let periods = timeframe.periods_per_year();
+        let returns = excess_returns(&returns[..len], risk_free_rate, periods);
+        let benchmark_returns = excess_returns(&benchmark_returns[..len], risk_free_rate, periods);
+        let mean = |values: &[f64]| values.iter().sum::<f64>() / values.len() as f64;
+        let mean_return = mean(&returns);
+        let mean_benchmark = mean(&benchmark_returns);
+
+        let mut covariance = 0.0;
+        let mut variance = 0.0;
+        let mut benchmark_variance = 0.0;
+        for (r, b) in returns.iter().zip(&benchmark_returns) {
+            covariance += (r - mean_return) * (b - mean_benchmark);
+            variance += (r - mean_return).powi(2);
+            benchmark_variance += (b - mean_benchmark).powi(2);
+        }
+
+        let beta = if benchmark_variance > 0.0 {
+            covariance / benchmark_variance
+        } else {
+            0.0
+        };
+        let correlation = if variance > 0.0 && benchmark_variance > 0.0 {
+            covariance / (variance * benchmark_variance).sqrt()
+        } else {
+            0.0
+        };
+
+        let active: Vec<f64> = returns
+            .iter()
+            .zip(&benchmark_returns)
+            .map(|(r, b)| r - b)
+            .collect();
+        let mean_active = mean(&active);
+        let tracking_error = (active
+            .iter()
+            .map(|a| (a - mean_active).powi(2))
+            .sum::<f64>()
+            / (len - 1) as f64)
+            .sqrt();
+        let information_ratio = if tracking_error > 0.0 {
+            mean_active / tracking_error * periods.sqrt()
+        } else {
+            0.0
+        };
+
+        BenchmarkComparison {
+            beta: beta as f32,
+            alpha_percent: ((mean_return - beta * mean_benchmark) * periods * 100.0) as f32,
+            correlation: correlation as f32,
+            information_ratio: information_ratio as f32,
+        }
+    }
+
+    /// Compound annual growth from `initial_capital` to the last closing
+    /// `equity`, over the time the candles `span`.
+    fn calculate_cagr_percent(
+        initial_capital: &BigDecimal,
+        equity: &BigDecimal,
+        span: TimeDelta,
+    ) -> f32 {
+        let initial = initial_capital.to_f64().unwrap_or(0.0);
+        if initial <= 0.0 {
+            return 0.0;
+        }
+
+        let growth = equity.to_f64().unwrap_or(0.0) / initial;
+        if growth <= 0.0 {
+            return -100.0;
+        }
+
+        if span < TimeDelta::days(MIN_CAGR_SPAN_DAYS) {
+            return 0.0;
+        }
+        let years = span.num_milliseconds() as f64 / TimeDelta::days(365).num_milliseconds() as f64;
+        let cagr = ((growth.powf(1.0 / years) - 1.0) * 100.0) as f32;
+        if cagr.is_finite() { cagr } else { 0.0 }
+    }
+
+    fn calculate_calmar_ratio(cagr_percent: f32, max_drawdown_percent: f32) -> f32 {
+        if max_drawdown_percent <= 0.0 {
+            return 0.0;
+        }
+        let calmar = cagr_percent / max_drawdown_percent;
+        if calmar.is_finite() { calmar } else { 0.0 }
+    }
+}
+
+/// Validates the extra timeframes a strategy declares and drops duplicates and
+/// the backtest timeframe itself.
+pub(crate) fn higher_timeframes(
+    timeframe: Timeframe,
+    declared: impl IntoIterator<Item = Timeframe>,
+) -> AppResult<Vec<Timeframe>> {
+    let mut higher_timeframes = Vec::new();
+    for higher in declared {
+        if higher.to_ms() < timeframe.to_ms() {
+            return Err(AppError::Strategy(format!(
+                "Timeframe {} is shorter than the backtest timeframe {}",
+                higher, timeframe
+            )));
+        }
+        if higher != timeframe && !higher_timeframes.contains(&higher) {
+            higher_timeframes.push(higher);
+        }
+    }
+
+    Ok(higher_timeframes)
+}
+
+/// Fees and precision of every symbol: the overrides of `config`, or else the
+/// exchange's.
+fn market_settings(config: &BacktestConfig) -> AppResult<(Vec<TradingFees>, Vec<MarketPrecision>)> {
+    let ccxt = CCXT::with_exchange(&config.exchange)?;
+    let mut fees = Vec::new();
+    let mut precisions = Vec::new();
+    for symbol in &config.all_symbols() {
+        fees.push(match &config.fees {
+            Some(fees) => fees.clone(),
+            None => ccxt.fees(symbol)?,
+        });
+        precisions.push(if *symbol == config.symbol {
+            config.precision.clone()
+        } else {
+            ccxt.precision(symbol)?
+        });
+    }
+    Ok((fees, precisions))
+}
+
+/// Candles and market settings of every symbol, loaded once and shared by
+/// any number of simulations.
+pub(crate) struct BacktestData {
+    higher_timeframes: Vec<Timeframe>,
+    candles: Vec<Vec<Candle>>,
+    sub_candles: Option<SubCandleSource>,
+    higher_candles: Vec<Vec<(Timeframe, Vec<Candle>)>>,
+    /// First timestamp the strategy trades on; earlier candles are only history.
+    trade_from: Option<DateTime<Utc>>,
+    /// Whether positions still open after the last candle are closed at market.
+    close_at_end: bool,
+    fees: Vec<TradingFees>,
+    precisions: Vec<MarketPrecision>,
+}
+
+impl BacktestData {
+    pub(crate) async fn load(
+        db_pool: &PgPool,
+        config: &BacktestConfig,
+        higher_timeframes: Vec<Timeframe>,
+    ) -> AppResult<Self> {
+        let exchange = config.exchange.as_str();
+        let symbols = config.all_symbols();
+        let (timeframe, start, end) = (config.timeframe, config.start, config.end);
+
+        let (fees, precisions) = market_settings(config)?;
+        let mut data = Self {
+            higher_timeframes,
+            candles: Vec::with_capacity(symbols.len()),
+            sub_candles: SubCandleSource::new(db_pool, config),
+            higher_candles: Vec::with_capacity(symbols.len()),
+            trade_from: None,
+            close_at_end: false,
+            fees,
+            precisions,
+        };
+        for symbol in &symbols {
+            let candles = get_candles(db_pool, exchange, symbol, timeframe, start, end).await?;
+            if candles.is_empty() {
+                return Err(format!("No candles available for {}", symbol).into());
             }
 
-            let high_value = &position * &candle.high + &balance;
-            if high_value > max_equity {
-                max_equity = high_value;
+            let span = (candles[0].timestamp, candles[candles.len() - 1].timestamp);
+            let mut higher_candles = Vec::with_capacity(data.higher_timeframes.len());
+            for higher in &data.higher_timeframes {
+                let source =
+                    higher_source(db_pool, exchange, symbol, timeframe, span, *higher).await?;
+                let series = match source {
+                    HigherSource::Stored { start } => {
+                        get_candles(db_pool, exchange, symbol, *higher, Some(start), end).await?
+                    }
+                    HigherSource::Resampled { history } => {
+                        resample_candles(history.iter().chain(&candles), *higher)
+                    }
+                };
+                higher_candles.push((*higher, series));
             }
+            data.higher_candles.push(higher_candles);
+            data.candles.push(candles);
+        }
 
-            let low_value = &position * &candle.low + &balance;
-            let drawdown = &max_equity - &low_value;
-            if drawdown > max_drawdown {
-                max_drawdown = drawdown.clone();
-                if !max_equity.is_zero() {
-                    max_drawdown_percent =
-                        (&drawdown / &max_equity).to_f32().unwrap_or(0.0) * 100.0;
-                }
+        Ok(data)
+    }
+
+    /// Timestamps of the primary symbol's candles.
+    pub(crate) fn timestamps(&self) -> Vec<DateTime<Utc>> {
+        self.candles[0].iter().map(|c| c.timestamp).collect()
+    }
+
+    /// The candles in `[from, to)`, as if only they had been loaded.
+    pub(crate) fn slice(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Self {
+        self.slice_after(from, from, to)
+    }
+
+    /// The candles of an out-of-sample run over `[from, to)`. Those in
+    /// `[warmup, from)` are kept as history the strategy can read but does
+    /// not tick or trade on, and positions still open after the last candle
+    /// are closed.
+    pub(crate) fn out_of_sample(
+        &self,
+        warmup: DateTime<Utc>,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Self {
+        Self {
+            close_at_end: true,
+            ..self.slice_after(warmup, from, to)
+        }
+    }
+
+    /// The candles in `[warmup, to)`, trading from `from`.
+    fn slice_after(&self, warmup: DateTime<Utc>, from: DateTime<Utc>, to: DateTime<Utc>) -> Self {
+        let within = |candles: &[Candle]| -> Vec<Candle> {
+            candles
+                .iter()
+                .filter(|c| c.timestamp >= warmup && c.timestamp < to)
+                .cloned()
+                .collect()
+        };
+
+        Self {
+            higher_timeframes: self.higher_timeframes.clone(),
+            candles: self.candles.iter().map(|c| within(c)).collect(),
+            sub_candles: self.sub_candles.as_ref().map(|s| s.slice(from, to)),
+            higher_candles: self
+                .higher_candles
+                .iter()
+                .map(|series| {
+                    series
+                        .iter()
+                        .map(|(higher, candles)| (*higher, within(candles)))
+                        .collect()
+                })
+                .collect(),
+            trade_from: Some(from),
+            close_at_end: false,
+            fees: self.fees.clone(),
+            precisions: self.precisions.clone(),
+        }
+    }
+}
+
+/// Replays `data` through `strategy` and computes the resulting statistic,
+/// waiting at every candle while `control` is paused.
+/// `on_progress` receives the completed percentage every
+/// `BACKTEST_BROADCAST_INTERVAL` steps.
+pub(crate) fn simulate(
+    config: &BacktestConfig,
+    data: &BacktestData,
+    strategy: &mut dyn Strategy,
+    control: &TaskControl,
+    on_progress: impl FnMut(f32),
+) -> AppResult<BacktestStatistic> {
+    let mut feed = MemoryFeed::new(data, config.timeframe, lookback(strategy));
+    simulate_feed(
+        config,
+        &mut feed,
+        &data.fees,
+        &data.precisions,
+        strategy,
+        control,
+        on_progress,
+    )
+    .0
+}
+
+/// Whether a rerun produced the `original` statistic. Resting orders get
+/// random ids, so trades are compared without them.
+fn same_statistic(mut original: BacktestStatistic, mut rerun: BacktestStatistic) -> bool {
+    for trade in original.trades.iter_mut().chain(&mut rerun.trades) {
+        trade.order_id = None;
+    }
+    serde_json::to_value(original).ok() == serde_json::to_value(rerun).ok()
+}
+
+/// Like [`simulate`], stepping through the candles of `feed`. Also returns
+/// what the strategy logged and plotted, even if it failed.
+fn simulate_feed<'a>(
+    config: &BacktestConfig,
+    feed: &mut impl CandleFeed<'a>,
+    fees: &[TradingFees],
+    precisions: &[MarketPrecision],
+    strategy: &mut dyn Strategy,
+    control: &TaskControl,
+    on_progress: impl FnMut(f32),
+) -> (AppResult<BacktestStatistic>, StrategyOutput) {
+    let symbols = config.all_symbols();
+    let markets = symbols
+        .iter()
+        .zip(fees.iter().zip(precisions))
+        .map(|(symbol, (fees, precision))| {
+            Market::new(symbol.clone(), fees.clone(), precision.clone())
+        })
+        .collect();
+
+    let context = StrategyContext::new(
+        config.initial_capital.clone(),
+        markets,
+        config.timeframe,
+        config.margin.clone(),
+        config.fill_model,
+        config.slippage_model.clone(),
+        config.liquidity.clone(),
+    );
+    let mut context = match context {
+        Ok(context) => context,
+        Err(e) => return (Err(e), StrategyOutput::default()),
+    };
+    let mut recorder = StatisticRecorder::new(config, fees, precisions);
+
+    let result = run_strategy(
+        feed,
+        &mut context,
+        &mut recorder,
+        strategy,
+        control,
+        on_progress,
+    );
+    let output = mem::take(&mut context.output).finish();
+    let statistic = result.map(|()| recorder.finish(context.trades()));
+    (statistic, output)
+}
+
+/// Steps `strategy` through every candle of `feed`, from its `on_start` to
+/// its `on_end`, then closes the open positions if the feed asks for it.
+fn run_strategy<'a>(
+    feed: &mut impl CandleFeed<'a>,
+    context: &mut StrategyContext<'a>,
+    recorder: &mut StatisticRecorder,
+    strategy: &mut dyn Strategy,
+    control: &TaskControl,
+    mut on_progress: impl FnMut(f32),
+) -> AppResult<()> {
+    strategy.on_start(context)?;
+
+    let mut advanced = vec![false; context.markets.len()];
+    let mut step = 0;
+    while let Some(timestamp) = feed.next_timestamp()? {
+        control.checkpoint()?;
+        for (index, advanced) in advanced.iter_mut().enumerate() {
+            *advanced = feed.advance(index, timestamp, &mut context.markets[index])?;
+            if *advanced {
+                context.select(index).before(strategy)?;
             }
         }
 
-        while let Some(trade) = trades_iter.next() {
-            let is_buy = matches!(trade.trade_type, TradeType::MarketBuy | TradeType::LimitBuy);
+        // Strategies tick once the primary symbol has data.
+        if !context.markets[0].candles.is_empty() {
+            strategy.tick(context)?;
+            context.after()?;
+        }
 
-            if is_buy {
-                buy_trades += 1;
-                let cost = &trade.price * &trade.amount + &trade.fee;
-                total_cost += &cost;
-                balance -= &cost;
-                position += &trade.amount;
-                trades_with_profit.push(trade.clone());
-            } else {
-                sell_trades += 1;
-                let proceeds = &trade.price * &trade.amount;
-                let revenue = &proceeds - &trade.fee;
-                let average_cost = if position.is_zero() {
-                    BigDecimal::zero()
-                } else {
-                    &total_cost / &position
-                };
-                let profit = &revenue - (&average_cost * &trade.amount);
+        recorder.record(timestamp, context, &advanced);
+        if step % BACKTEST_BROADCAST_INTERVAL == 0 {
+            on_progress(feed.progress());
+        }
+        step += 1;
+    }
+
+    context.end(strategy)?;
+    if feed.close_at_end() {
+        context.close_positions()?;
+        recorder.settle(context);
+    }
+    Ok(())
+}
+
+/// Candles of each series `strategy` reads at once; at least the latest one.
+fn lookback(strategy: &dyn Strategy) -> Option<usize> {
+    strategy.lookback().map(|lookback| lookback.max(1))
+}
+
+/// Sets each point's drawdown from the highest equity up to it.
+pub(crate) fn fill_drawdown(mut curve: Vec<EquityPoint>) -> Vec<EquityPoint> {
+    let mut peak: Option<BigDecimal> = None;
+    for point in &mut curve {
+        let peak = match &mut peak {
+            Some(peak) if *peak >= point.equity => peak,
+            _ => peak.insert(point.equity.clone()),
+        };
+        point.drawdown = &*peak - &point.equity;
+    }
+    curve
+}
+
+struct StatisticRecorder<'s> {
+    config: &'s BacktestConfig,
+    symbols: Vec<String>,
+    precisions: &'s [MarketPrecision],
+    ledger: PositionLedger,
+    recorded: usize,
+    trades: Vec<Trade>,
+    round_trips: RoundTripTracker,
+    buy_trades: usize,
+    sell_trades: usize,
+    closing_trades: usize,
+    winning_trades: usize,
+    losing_trades: usize,
+    gross_profit: BigDecimal,
+    gross_loss: BigDecimal,
+    largest_win: BigDecimal,
+    largest_loss: BigDecimal,
+    max_equity: BigDecimal,
+    max_drawdown: BigDecimal,
+    max_drawdown_percent: f32,
+    equity_curve: EquityCurve,
+    /// Held back from the curve until the next step, so that trades
+    /// settled at the end still count in it.
+    last_point: Option<EquityPoint>,
+    returns: Returns,
+    exposed_candles: usize,
+    // Each symbol's benchmark share is held as cash until its first open.
+    allocation: f64,
+    taker_fees: Vec<f64>,
+    benchmark_amounts: Vec<Option<f64>>,
+    last_closes: Vec<f64>,
+    benchmark_returns: Returns,
+}
+
+impl<'s> StatisticRecorder<'s> {
+    fn new(
+        config: &'s BacktestConfig,
+        fees: &[TradingFees],
+        precisions: &'s [MarketPrecision],
+    ) -> Self {
+        let symbols = config.all_symbols();
+        let initial_capital = config.initial_capital.clone();
+        let initial = initial_capital.to_f64().unwrap_or(0.0);
+        Self {
+            config,
+            allocation: initial / symbols.len() as f64,
+            taker_fees: fees
+                .iter()
+                .map(|fees| fees.taker.to_f64().unwrap_or(0.0))
+                .collect(),
+            benchmark_amounts: vec![None; symbols.len()],
+            last_closes: vec![0.0; symbols.len()],
+            benchmark_returns: Returns::new(initial),
+            symbols,
+            precisions,
+            ledger: PositionLedger::new(initial_capital.clone()),
+            recorded: 0,
+            trades: Vec::new(),
+            round_trips: RoundTripTracker::default(),
+            buy_trades: 0,
+            sell_trades: 0,
+            closing_trades: 0,
+            winning_trades: 0,
+            losing_trades: 0,
+            gross_profit: BigDecimal::zero(),
+            gross_loss: BigDecimal::zero(),
+            largest_win: BigDecimal::zero(),
+            largest_loss: BigDecimal::zero(),
+            max_equity: initial_capital,
+            max_drawdown: BigDecimal::zero(),
+            max_drawdown_percent: 0.0,
+            equity_curve: EquityCurve::new(),
+            last_point: None,
+            returns: Returns::new(initial),
+            exposed_candles: 0,
+        }
+    }
+
+    fn record_trade(&mut self, trade: &Trade) {
+        if trade.trade_type.is_buy() {
+            self.buy_trades += 1;
+        } else {
+            self.sell_trades += 1;
+        }
 
-                position -= &trade.amount;
-                balance += &revenue;
+        let profit = self.ledger.apply(trade);
 
-                if position.is_zero() {
-                    total_cost = BigDecimal::zero();
-                } else {
-                    total_cost -= &average_cost * &trade.amount;
+        if let Some(profit) = &profit {
+            self.closing_trades += 1;
+            if *profit > BigDecimal::zero() {
+                self.winning_trades += 1;
+                self.gross_profit += profit;
+                if *profit > self.largest_win {
+                    self.largest_win = profit.clone();
                 }
+            } else if *profit < BigDecimal::zero() {
+                self.losing_trades += 1;
+                self.gross_loss += profit;
+                if *profit < self.largest_loss {
+                    self.largest_loss = profit.clone();
+                }
+            }
+        }
 
-                if profit > BigDecimal::zero() {
-                    winning_trades += 1;
-                    gross_profit += &profit;
-                    if profit > largest_win {
-                        largest_win = profit.clone();
-                    }
-                } else if profit < BigDecimal::zero() {
-                    losing_trades += 1;
-                    gross_loss += &profit;
-                    if profit < largest_loss {
-                        largest_loss = profit.clone();
-                    }
+        if let Some(index) = self.symbols.iter().position(|s| *s == trade.symbol) {
+            self.round_trips.record(trade, &self.precisions[index]);
+        }
+
+        self.trades.push(Trade {
+            profit,
+            ..trade.clone()
+        });
+    }
+
+    fn benchmark_value(&self, exit_fee: bool) -> f64 {
+        self.benchmark_amounts
+            .iter()
+            .enumerate()
+            .map(|(index, amount)| match amount {
+                None => self.allocation,
+                Some(amount) => {
+                    let fee = if exit_fee {
+                        self.taker_fees[index]
+                    } else {
+                        0.0
+                    };
+                    amount * self.last_closes[index] * (1.0 - fee)
                 }
+            })
+            .sum()
+    }
 
-                trades_with_profit.push(Trade {
-                    timestamp: trade.timestamp,
-                    trade_type: trade.trade_type.clone(),
-                    price: trade.price.clone(),
-                    amount: trade.amount.clone(),
-                    fee: trade.fee.clone(),
-                    profit: Some(profit.clone()),
+    fn record(&mut self, timestamp: DateTime<Utc>, context: &StrategyContext, advanced: &[bool]) {
+        while let Some(trade) = context
+            .trades()
+            .get(self.recorded)
+            .filter(|trade| trade.timestamp <= timestamp)
+        {
+            self.record_trade(trade);
+            self.recorded += 1;
+        }
+
+        // Markets without a candle at this timestamp are held at their last close.
+        let mut peak_value = self.ledger.balance.clone();
+        let mut trough_value = self.ledger.balance.clone();
+        let mut position_value = BigDecimal::zero();
+        for (index, market) in context.markets.iter().enumerate() {
+            let Some(candle) = market.candles.last() else {
+                continue;
+            };
+            let (high, low) = if advanced[index] {
+                self.round_trips.observe(&market.symbol, candle);
+                let (allocation, taker) = (self.allocation, self.taker_fees[index]);
+                self.benchmark_amounts[index].get_or_insert_with(|| {
+                    if candle.open > BigDecimal::zero() {
+                        allocation / (candle.open.to_f64().unwrap_or(0.0) * (1.0 + taker))
+                    } else {
+                        0.0
+                    }
                 });
+                self.last_closes[index] = candle.close.to_f64().unwrap_or(0.0);
+                (&candle.high, &candle.low)
+            } else {
+                (&candle.close, &candle.close)
+            };
+            position_value += self.ledger.value(&market.symbol, &candle.close);
+
+            let high_value = self.ledger.value(&market.symbol, high);
+            let low_value = self.ledger.value(&market.symbol, low);
+            if high_value >= low_value {
+                peak_value += high_value;
+                trough_value += low_value;
+            } else {
+                peak_value += low_value;
+                trough_value += high_value;
+            }
+        }
+
+        if peak_value > self.max_equity {
+            self.max_equity = peak_value;
+        }
+
+        if self.ledger.has_positions() {
+            self.exposed_candles += 1;
+        }
+        self.benchmark_returns.push(self.benchmark_value(false));
+        let point = EquityPoint {
+            timestamp,
+            equity: &self.ledger.balance + &position_value,
+            cash: self.ledger.balance.clone(),
+            position_value,
+            drawdown: BigDecimal::zero(),
+        };
+        self.push_point(Some(point));
+
+        let drawdown = &self.max_equity - &trough_value;
+        if drawdown > self.max_drawdown {
+            self.max_drawdown = drawdown.clone();
+            if !self.max_equity.is_zero() {
+                self.max_drawdown_percent =
+                    (&drawdown / &self.max_equity).to_f32().unwrap_or(0.0) * 100.0;
             }
         }
+    }
 
-        let total_trades = buy_trades + sell_trades;
-        let win_rate = if sell_trades > 0 {
-            (winning_trades as f32 / sell_trades as f32) * 100.0
+    /// Counts positions closed after the last step as cash in its point.
+    fn settle(&mut self, context: &StrategyContext) {
+        for trade in &context.trades()[self.recorded..] {
+            self.record_trade(trade);
+        }
+        self.recorded = context.trades().len();
+
+        let Some(point) = &mut self.last_point else {
+            return;
+        };
+        let mut position_value = BigDecimal::zero();
+        for market in &context.markets {
+            if let Some(candle) = market.candles.last() {
+                position_value += self.ledger.value(&market.symbol, &candle.close);
+            }
+        }
+        point.equity = &self.ledger.balance + &position_value;
+        point.cash = self.ledger.balance.clone();
+        point.position_value = position_value;
+    }
+
+    fn push_point(&mut self, point: Option<EquityPoint>) {
+        if let Some(last) = mem::replace(&mut self.last_point, point) {
+            self.returns.push(last.equity.to_f64().unwrap_or(0.0));
+            self.equity_curve.push(last);
+        }
+    }
+
+    fn finish(mut self, trades: &[Trade]) -> BacktestStatistic {
+        for trade in &trades[self.recorded.min(trades.len())..] {
+            self.record_trade(trade);
+        }
+        let benchmark_value = self.benchmark_value(true);
+
+        let initial_capital = self.config.initial_capital.clone();
+        let total_trades = self.buy_trades + self.sell_trades;
+        let win_rate = if self.closing_trades > 0 {
+            (self.winning_trades as f32 / self.closing_trades as f32) * 100.0
         } else {
             0.0
         };
 
-        let avg_win = if winning_trades > 0 {
-            (&gross_profit / BigDecimal::from(winning_trades as i64))
+        let avg_win = if self.winning_trades > 0 {
+            (&self.gross_profit / BigDecimal::from(self.winning_trades as i64))
                 .with_scale_round(2, RoundingMode::HalfUp)
         } else {
             BigDecimal::zero()
         };
 
-        let avg_loss = if losing_trades > 0 {
-            (&gross_loss / BigDecimal::from(losing_trades as i64))
+        let avg_loss = if self.losing_trades > 0 {
+            (&self.gross_loss / BigDecimal::from(self.losing_trades as i64))
                 .with_scale_round(2, RoundingMode::HalfUp)
         } else {
             BigDecimal::zero()
         };
 
-        let profit_factor = if gross_loss.is_zero() {
-            if !gross_profit.is_zero() {
+        let profit_factor = if self.gross_loss.is_zero() {
+            if !self.gross_profit.is_zero() {
                 f32::INFINITY
             } else {
                 0.0
             }
         } else {
-            (&gross_profit / &gross_loss.abs()).to_f32().unwrap_or(0.0)
+            (&self.gross_profit / &self.gross_loss.abs())
+                .to_f32()
+                .unwrap_or(0.0)
         };
 
-        let net_profit = (&gross_profit + &gross_loss).with_scale_round(2, RoundingMode::HalfUp);
+        let net_profit =
+            (&self.gross_profit + &self.gross_loss).with_scale_round(2, RoundingMode::HalfUp);
 
         let return_percent = if !initial_capital.is_zero() {
             (&net_profit / &initial_capital).to_f32().unwrap_or(0.0) * 100.0
@@ -451,83 +1297,930 @@ impl BacktestTask {
             0.0
         };
 
-        let sharpe_ratio = Self::calculate_sharpe_ratio(&trades_with_profit, &initial_capital);
+        let timeframe = self.config.timeframe;
+        let risk_free_rate = self.config.risk_free_rate.to_f64().unwrap_or(0.0);
+        let initial = initial_capital.to_f64().unwrap_or(0.0);
+        self.push_point(None);
+        let returns = self.returns.returns;
+        let max_drawdown_percent = self.max_drawdown_percent;
+        let sharpe_ratio =
+            BacktestTask::calculate_sharpe_ratio(&returns, risk_free_rate, timeframe);
+        let sortino_ratio =
+            BacktestTask::calculate_sortino_ratio(&returns, risk_free_rate, timeframe);
+        let cagr_percent = match &self.equity_curve.last {
+            Some(last) => BacktestTask::calculate_cagr_percent(
+                &initial_capital,
+                &last.equity,
+                self.equity_curve.span(timeframe),
+            ),
+            None => 0.0,
+        };
+        let calmar_ratio = BacktestTask::calculate_calmar_ratio(cagr_percent, max_drawdown_percent);
+        let exposure_percent = if returns.is_empty() {
+            0.0
+        } else {
+            100.0 * self.exposed_candles as f32 / returns.len() as f32
+        };
+        let round_trips = self.round_trips.closed;
+        let avg_holding_time = if round_trips.is_empty() {
+            0
+        } else {
+            round_trips
+                .iter()
+                .map(|t| (t.exit_time - t.entry_time).num_milliseconds())
+                .sum::<i64>()
+                / round_trips.len() as i64
+        };
+        let benchmark_return_percent = if initial > 0.0 && !returns.is_empty() {
+            ((benchmark_value / initial - 1.0) * 100.0) as f32
+        } else {
+            0.0
+        };
+        let comparison = BacktestTask::compare_to_benchmark(
+            &returns,
+            &self.benchmark_returns.returns,
+            risk_free_rate,
+            timeframe,
+        );
+
+        let max_drawdown_duration = self.equity_curve.longest_drawdown.num_milliseconds();
 
         BacktestStatistic {
-            trades: trades_with_profit,
+            trades: self.trades,
             initial_capital,
-            total_cost,
+            total_cost: self.ledger.total_cost(),
             net_profit,
             return_percent,
-            max_equity,
-            max_drawdown,
+            max_equity: self.max_equity,
+            max_drawdown: self.max_drawdown,
             max_drawdown_percent,
-            gross_profit,
-            gross_loss,
+            gross_profit: self.gross_profit,
+            gross_loss: self.gross_loss,
             profit_factor,
             sharpe_ratio,
+            sortino_ratio,
+            calmar_ratio,
+            cagr_percent,
+            exposure_percent,
+            avg_holding_time,
+            max_drawdown_duration,
+            benchmark_return_percent,
+            excess_return_percent: return_percent - benchmark_return_percent,
+            beta: comparison.beta,
+            alpha_percent: comparison.alpha_percent,
+            correlation: comparison.correlation,
+            information_ratio: comparison.information_ratio,
             total_trades,
-            buy_trades,
-            sell_trades,
-            winning_trades,
-            losing_trades,
+            buy_trades: self.buy_trades,
+            sell_trades: self.sell_trades,
+            winning_trades: self.winning_trades,
+            losing_trades: self.losing_trades,
             win_rate,
             avg_win,
             avg_loss,
-            largest_win,
-            largest_loss,
+            largest_win: self.largest_win,
+            largest_loss: self.largest_loss,
+            round_trips,
+            equity_curve: self.equity_curve.finish(),
         }
     }
+}
 
-    fn calculate_sharpe_ratio(trades: &[Trade], initial_capital: &BigDecimal) -> f32 {
-        if trades.is_empty() {
-            return 0.0;
+#[derive(Default)]
+struct BenchmarkComparison {
+    beta: f32,
+    alpha_percent: f32,
+    correlation: f32,
+    information_ratio: f32,
+}
+
+struct Returns {
+    previous: f64,
+    returns: Vec<f64>,
+}
+
+impl Returns {
+    fn new(initial: f64) -> Self {
+        Self {
+            previous: initial,
+            returns: Vec::new(),
         }
+    }
 
-        let sell_trades: Vec<&Trade> = trades
-            .iter()
-            .filter(|t| {
-                if let Some(profit) = &t.profit {
-                    !profit.is_zero()
-                } else {
-                    false
+    fn push(&mut self, value: f64) {
+        let r = if self.previous > 0.0 {
+            value / self.previous - 1.0
+        } else {
+            0.0
+        };
+        self.previous = value;
+        self.returns.push(r);
+    }
+}
+
+fn excess_returns(returns: &[f64], risk_free_rate: f64, periods_per_year: f64) -> Vec<f64> {
+    let risk_free = (1.0 + risk_free_rate).powf(1.0 / periods_per_year) - 1.0;
+    returns.iter().map(|r| r - risk_free).collect()
+}
+
+/// Keeps the deepest-drawdown point of each bucket of candles. Buckets double
+/// in size whenever `MAX_EQUITY_POINTS` of them fill up, so the curve never
+/// holds more points than that.
+struct EquityCurve {
+    sampled: Vec<EquityPoint>,
+    bucket: usize,
+    filled: usize,
+    first_timestamp: Option<DateTime<Utc>>,
+    last: Option<EquityPoint>,
+    peak: Option<BigDecimal>,
+    peak_at: Option<DateTime<Utc>>,
+    below_peak: bool,
+    longest_drawdown: TimeDelta,
+}
+
+impl EquityCurve {
+    fn new() -> Self {
+        Self {
+            sampled: Vec::new(),
+            bucket: 1,
+            filled: 0,
+            first_timestamp: None,
+            last: None,
+            peak: None,
+            peak_at: None,
+            below_peak: false,
+            longest_drawdown: TimeDelta::zero(),
+        }
+    }
+
+    fn push(&mut self, mut point: EquityPoint) {
+        let peak = match &mut self.peak {
+            Some(peak) if *peak >= point.equity => peak,
+            _ => self.peak.insert(point.equity.clone()),
+        };
+        point.drawdown = &*peak - &point.equity;
+
+        if let Some(peak_at) = self.peak_at
+            && (self.below_peak || !point.drawdown.is_zero())
+        {
+            self.longest_drawdown = self.longest_drawdown.max(point.timestamp - peak_at);
+        }
+        self.below_peak = !point.drawdown.is_zero();
+        if !self.below_peak {
+            self.peak_at = Some(point.timestamp);
+        }
+        self.first_timestamp.get_or_insert(point.timestamp);
+
+        match self.sampled.last_mut() {
+            Some(deepest) if self.filled > 0 => {
+                if point.drawdown > deepest.drawdown {
+                    *deepest = point.clone();
                 }
-            })
-            .collect();
+            }
+            _ => self.sampled.push(point.clone()),
+        }
+        self.filled += 1;
+        if self.filled == self.bucket {
+            self.filled = 0;
+            if self.sampled.len() >= MAX_EQUITY_POINTS {
+                self.merge_buckets();
+            }
+        }
+        self.last = Some(point);
+    }
 
-        if sell_trades.is_empty() {
-            return 0.0;
+    fn merge_buckets(&mut self) {
+        let mut points = mem::take(&mut self.sampled).into_iter();
+        while let Some(first) = points.next() {
+            self.sampled.push(match points.next() {
+                Some(second) if second.drawdown > first.drawdown => second,
+                _ => first,
+            });
         }
+        self.bucket *= 2;
+    }
 
-        if sell_trades.len() == 1 {
-            return f32::INFINITY;
+    fn span(&self, timeframe: Timeframe) -> TimeDelta {
+        match (self.first_timestamp, &self.last) {
+            (Some(first), Some(last)) => last.timestamp + timeframe.to_delta() - first,
+            _ => TimeDelta::zero(),
         }
+    }
 
-        let initial_capital_f64 = initial_capital.to_f64().unwrap_or(1.0);
+    fn finish(self) -> Vec<EquityPoint> {
+        let mut sampled = self.sampled;
+        if let Some(last) = self.last
+            && sampled.last().map(|p| p.timestamp) != Some(last.timestamp)
+        {
+            sampled.push(last);
+        }
+        sampled
+    }
+}
 
-        let returns: Vec<f64> = sell_trades
-            .iter()
-            .filter_map(|t| t.profit.as_ref())
-            .map(|profit| profit.to_f64().unwrap_or(0.0) / initial_capital_f64)
-            .collect();
+#[derive(Default)]
+struct RoundTripTracker {
+    open: HashMap<String, OpenTrip>,
+    closed: Vec<RoundTrip>,
+}
 
-        let mean_return = returns.iter().sum::<f64>() / returns.len() as f64;
+impl RoundTripTracker {
+    fn record(&mut self, trade: &Trade, precision: &MarketPrecision) {
+        let is_buy = trade.trade_type.is_buy();
+        let mut remaining = trade.amount.clone();
 
-        let variance = returns
-            .iter()
-            .map(|r| {
-                let diff = r - mean_return;
-                diff * diff
-            })
-            .sum::<f64>()
-            / returns.len() as f64;
+        if let Some(trip) = self.open.get_mut(&trade.symbol) {
+            if trip.is_long == is_buy {
+                trip.fill(trade, &remaining, true);
+                remaining = BigDecimal::zero();
+            } else {
+                let reduced = (&trip.entry_amount - &trip.exit_amount).min(remaining.clone());
+                trip.fill(trade, &reduced, false);
+                remaining -= &reduced;
+                if trip.entry_amount == trip.exit_amount {
+                    let trip = self.open.remove(&trade.symbol).unwrap();
+                    self.closed.push(trip.close(trade, precision));
+                }
+            }
+        }
 
-        let std_dev = variance.sqrt();
+        if !remaining.is_zero() {
+            let mut trip = OpenTrip::new(trade, is_buy);
+            trip.fill(trade, &remaining, true);
+            self.open.insert(trade.symbol.clone(), trip);
+        }
+    }
 
-        if std_dev == 0.0 {
-            return f32::INFINITY;
+    fn observe(&mut self, symbol: &str, candle: &Candle) {
+        if let Some(trip) = self.open.get_mut(symbol) {
+            trip.observe(candle);
+        }
+    }
+}
+
+struct OpenTrip {
+    is_long: bool,
+    entry_time: DateTime<Utc>,
+    entry_amount: BigDecimal,
+    entry_value: BigDecimal,
+    exit_amount: BigDecimal,
+    exit_value: BigDecimal,
+    fees: BigDecimal,
+    high: BigDecimal,
+    low: BigDecimal,
+    bars: usize,
+}
+
+impl OpenTrip {
+    fn new(trade: &Trade, is_long: bool) -> Self {
+        Self {
+            is_long,
+            entry_time: trade.timestamp,
+            entry_amount: BigDecimal::zero(),
+            entry_value: BigDecimal::zero(),
+            exit_amount: BigDecimal::zero(),
+            exit_value: BigDecimal::zero(),
+            fees: BigDecimal::zero(),
+            high: trade.price.clone(),
+            low: trade.price.clone(),
+            bars: 0,
+        }
+    }
+
+    fn fill(&mut self, trade: &Trade, amount: &BigDecimal, entry: bool) {
+        let value = &trade.price * amount;
+        if entry {
+            self.entry_amount += amount;
+            self.entry_value += value;
+        } else {
+            self.exit_amount += amount;
+            self.exit_value += value;
+        }
+        self.fees += &trade.fee * amount / &trade.amount;
+        if trade.price > self.high {
+            self.high = trade.price.clone();
+        }
+        if trade.price < self.low {
+            self.low = trade.price.clone();
+        }
+    }
+
+    // Prices from before the entry don't count towards its excursions.
+    fn observe(&mut self, candle: &Candle) {
+        self.bars += 1;
+        if candle.timestamp > self.entry_time {
+            if candle.high > self.high {
+                self.high = candle.high.clone();
+            }
+            if candle.low < self.low {
+                self.low = candle.low.clone();
+            }
+        }
+    }
+
+    fn close(self, exit: &Trade, precision: &MarketPrecision) -> RoundTrip {
+        let entry_price = &self.entry_value / &self.entry_amount;
+        let exit_price = &self.exit_value / &self.exit_amount;
+        let profit = if self.is_long {
+            &self.exit_value - &self.entry_value - &self.fees
+        } else {
+            &self.entry_value - &self.exit_value - &self.fees
+        };
+        let percent = |value: &BigDecimal| -> f32 {
+            (value / &self.entry_value).to_f32().unwrap_or(0.0) * 100.0
+        };
+        let (favorable, adverse) = if self.is_long {
+            (&self.high - &entry_price, &self.low - &entry_price)
+        } else {
+            (&entry_price - &self.low, &entry_price - &self.high)
+        };
+
+        RoundTrip {
+            symbol: exit.symbol.clone(),
+            is_long: self.is_long,
+            entry_time: self.entry_time,
+            exit_time: exit.timestamp,
+            return_percent: percent(&profit),
+            mfe_percent: percent(&(favorable * &self.entry_amount)),
+            mae_percent: percent(&(adverse * &self.entry_amount)),
+            entry_price: precision.round_price(&entry_price, RoundingMode::HalfUp),
+            exit_price: precision.round_price(&exit_price, RoundingMode::HalfUp),
+            amount: self.entry_amount,
+            fees: self.fees.with_scale_round(8, RoundingMode::HalfUp),
+            profit: profit.with_scale_round(8, RoundingMode::HalfUp),
+            bars_held: self.bars,
+        }
+    }
+}
+
+/// Replays trades against a shared balance and signed per-symbol positions
+/// using average-cost accounting. Entry fees are folded into the cost basis,
+/// exit fees into realized profit.
+struct PositionLedger {
+    balance: BigDecimal,
+    holdings: HashMap<String, Holding>,
+}
+
+#[derive(Default)]
+struct Holding {
+    position: BigDecimal,
+    total_cost: BigDecimal,
+}
+
+impl PositionLedger {
+    fn new(balance: BigDecimal) -> Self {
+        Self {
+            balance,
+            holdings: HashMap::new(),
+        }
+    }
+
+    fn has_positions(&self) -> bool {
+        self.holdings.values().any(|h| !h.position.is_zero())
+    }
+
+    /// Value of the position in `symbol` at `price`.
+    fn value(&self, symbol: &str, price: &BigDecimal) -> BigDecimal {
+        self.holdings
+            .get(symbol)
+            .map(|h| &h.position * price)
+            .unwrap_or_else(BigDecimal::zero)
+    }
+
+    fn total_cost(&self) -> BigDecimal {
+        self.holdings
+            .values()
+            .fold(BigDecimal::zero(), |total, h| total + &h.total_cost)
+    }
+
+    /// Applies a trade and returns the realized profit if it reduced the position.
+    fn apply(&mut self, trade: &Trade) -> Option<BigDecimal> {
+        let is_buy = trade.trade_type.is_buy();
+        let notional = &trade.price * &trade.amount;
+        if is_buy {
+            self.balance -= &notional + &trade.fee;
+        } else {
+            self.balance += &notional - &trade.fee;
+        }
+
+        let holding = self.holdings.entry(trade.symbol.clone()).or_default();
+        let is_long = holding.position > BigDecimal::zero();
+        let reduces = !holding.position.is_zero() && is_buy != is_long;
+        let reduced = if reduces {
+            holding.position.abs().min(trade.amount.clone())
+        } else {
+            BigDecimal::zero()
+        };
+        let opened = &trade.amount - &reduced;
+
+        let mut profit = None;
+        if !reduced.is_zero() {
+            let fee = &trade.fee * &reduced / &trade.amount;
+            let average_cost = &holding.total_cost / &holding.position;
+            let exit_value = &trade.price * &reduced;
+            let entry_value = &average_cost * &reduced;
+
+            profit = Some(if is_long {
+                exit_value - entry_value - &fee
+            } else {
+                entry_value - exit_value - &fee
+            });
+
+            if is_long {
+                holding.position -= &reduced;
+                holding.total_cost -= &average_cost * &reduced;
+            } else {
+                holding.position += &reduced;
+                holding.total_cost += &average_cost * &reduced;
+            }
+
+            if holding.position.is_zero() {
+                holding.total_cost = BigDecimal::zero();
+            }
+        }
+
+        if !opened.is_zero() {
+            let fee = &trade.fee * &opened / &trade.amount;
+            let value = &trade.price * &opened;
+            if is_buy {
+                holding.position += &opened;
+                holding.total_cost += value + fee;
+            } else {
+                holding.position -= &opened;
+                holding.total_cost -= value - fee;
+            }
+        }
+
+        profit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strategy::{LogLevel, Order, TradeType};
+    use chrono::TimeZone;
+
+    fn point(minute: i64, equity: i64) -> EquityPoint {
+        EquityPoint {
+            timestamp: Utc.timestamp_opt(minute * 60, 0).unwrap(),
+            equity: BigDecimal::from(equity),
+            cash: BigDecimal::from(equity),
+            position_value: BigDecimal::zero(),
+            drawdown: BigDecimal::zero(),
+        }
+    }
+
+    #[test]
+    fn drawdown_duration_and_cagr_follow_the_calendar() {
+        let day = |day: i64, equity: i64| EquityPoint {
+            timestamp: Utc.timestamp_opt(day * 86400, 0).unwrap(),
+            ..point(0, equity)
+        };
+        let mut curve = EquityCurve::new();
+        for (d, equity) in [1000, 900, 950, 1000, 800].into_iter().enumerate() {
+            curve.push(day(d as i64, equity));
+        }
+        assert_eq!(curve.longest_drawdown, TimeDelta::days(3));
+
+        let mut curve = EquityCurve::new();
+        let mut returns = Returns::new(1000.0);
+        for d in 0..365 {
+            curve.push(day(d, 1000 + d));
+            returns.push((1000 + d) as f64);
+        }
+        assert_eq!(curve.span(Timeframe::D1), TimeDelta::days(365));
+        let cagr = BacktestTask::calculate_cagr_percent(
+            &BigDecimal::from(682),
+            &curve.last.unwrap().equity,
+            TimeDelta::days(365),
+        );
+        assert!((cagr - 100.0).abs() < 0.01);
+
+        let returns = returns.returns;
+        assert!(BacktestTask::calculate_sharpe_ratio(&returns, 0.0, Timeframe::D1) > 0.0);
+        assert_eq!(
+            BacktestTask::calculate_sortino_ratio(&returns, 0.0, Timeframe::D1),
+            0.0
+        );
+    }
+
+    #[test]
+    fn one_day_backtests_report_no_cagr_or_calmar_ratio() {
+        let mut curve = EquityCurve::new();
+        for minute in 0..1440 {
+            curve.push(point(minute, 1000 + minute));
+        }
+        let span = curve.span(Timeframe::M1);
+        assert_eq!(span, TimeDelta::days(1));
+        let cagr = BacktestTask::calculate_cagr_percent(
+            &BigDecimal::from(1000),
+            &curve.last.unwrap().equity,
+            span,
+        );
+        assert_eq!(cagr, 0.0);
+        assert_eq!(BacktestTask::calculate_calmar_ratio(cagr, 5.0), 0.0);
+
+        assert_eq!(BacktestTask::calculate_calmar_ratio(f32::MAX, 0.5), 0.0);
+        assert_eq!(BacktestTask::calculate_calmar_ratio(20.0, 10.0), 2.0);
+    }
+
+    #[test]
+    fn leveraged_benchmark_returns_have_a_beta_of_two() {
+        let benchmark = [0.01, -0.02, 0.03, 0.005, -0.01];
+        let returns: Vec<f64> = benchmark.iter().map(|b| 2.0 * b).collect();
+
+        let comparison =
+            BacktestTask::compare_to_benchmark(&returns, &benchmark, 0.0, Timeframe::D1);
+        assert!((comparison.beta - 2.0).abs() < 1e-4);
+        assert!((comparison.correlation - 1.0).abs() < 1e-4);
+        assert!(comparison.alpha_percent.abs() < 1e-3);
+        assert!(comparison.information_ratio > 0.0);
+    }
+
+    fn candle(minute: i64, high: i64, low: i64) -> Candle {
+        Candle {
+            timestamp: Utc.timestamp_opt(minute * 60, 0).unwrap(),
+            exchange: "test".into(),
+            symbol: "BTC/USDT".into(),
+            timeframe: Timeframe::M1,
+            open: BigDecimal::from(low),
+            high: BigDecimal::from(high),
+            low: BigDecimal::from(low),
+            close: BigDecimal::from(high),
+            volume: BigDecimal::from(1000),
+        }
+    }
+
+    fn trade(minute: i64, trade_type: TradeType, price: i64, amount: &str) -> Trade {
+        Trade {
+            timestamp: Utc.timestamp_opt(minute * 60, 0).unwrap(),
+            symbol: "BTC/USDT".into(),
+            trade_type,
+            price: BigDecimal::from(price),
+            amount: amount.parse().unwrap(),
+            fee: BigDecimal::zero(),
+            slippage: BigDecimal::zero(),
+            slippage_model: SlippageModel::default(),
+            order_id: None,
+            profit: None,
+        }
+    }
+
+    #[test]
+    fn round_trips_end_when_a_position_is_closed_or_reversed() {
+        let candles = vec![
+            candle(0, 150, 50),
+            candle(1, 120, 95),
+            candle(2, 110, 90),
+            candle(3, 108, 100),
+            candle(4, 200, 10),
+        ];
+        let trades = [
+            trade(0, TradeType::MarketBuy, 100, "1"),
+            trade(3, TradeType::MarketSell, 105, "0.5"),
+            trade(4, TradeType::MarketSell, 110, "1.5"),
+        ];
+        let precision = MarketPrecision {
+            price_precision: "0.01".parse().unwrap(),
+            amount_precision: "0.001".parse().unwrap(),
+        };
+
+        let mut tracker = RoundTripTracker::default();
+        let mut trades = trades.iter().peekable();
+        for candle in &candles {
+            while let Some(trade) = trades.next_if(|t| t.timestamp <= candle.timestamp) {
+                tracker.record(trade, &precision);
+            }
+            tracker.observe(&candle.symbol, candle);
+        }
+
+        assert_eq!(tracker.closed.len(), 1);
+        let trip = &tracker.closed[0];
+        assert!(trip.is_long);
+        assert_eq!(trip.exit_price, "107.5".parse::<BigDecimal>().unwrap());
+        assert_eq!(trip.profit, "7.5".parse::<BigDecimal>().unwrap());
+        assert!((trip.return_percent - 7.5).abs() < 1e-4);
+        // Only the candles between entry and exit count towards the excursions.
+        assert!((trip.mfe_percent - 20.0).abs() < 1e-4);
+        assert!((trip.mae_percent + 10.0).abs() < 1e-4);
+        assert_eq!(trip.bars_held, 4);
+    }
+
+    /// Buys on its first tick and holds, noting how much history it saw then.
+    #[derive(Default)]
+    struct BuyAndHold {
+        history: Option<usize>,
+        ticks: usize,
+    }
+
+    impl Strategy for BuyAndHold {
+        fn tick(&mut self, context: &mut StrategyContext) -> AppResult<()> {
+            self.ticks += 1;
+            if self.history.is_none() {
+                self.history = Some(context.candles().len());
+                context.market_buy(&BigDecimal::from(1))?;
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn out_of_sample_runs_warm_up_on_history_and_close_at_the_end() {
+        let config: BacktestConfig = serde_json::from_value(serde_json::json!({
+            "exchange": "test",
+            "symbol": "BTC/USDT",
+            "timeframe": "1m",
+            "initial_capital": "1000",
+            "fees": { "maker": "0", "taker": "0" },
+            "precision": { "price_precision": "0.01", "amount_precision": "0.001" },
+        }))
+        .unwrap();
+        let data = BacktestData {
+            higher_timeframes: Vec::new(),
+            candles: vec![
+                (0..10)
+                    .map(|minute| candle(minute, 100 + minute, 90))
+                    .collect(),
+            ],
+            sub_candles: None,
+            higher_candles: vec![Vec::new()],
+            trade_from: None,
+            close_at_end: false,
+            fees: vec![config.fees.clone().unwrap()],
+            precisions: vec![config.precision.clone()],
+        };
+        let minute = |minute: i64| Utc.timestamp_opt(minute * 60, 0).unwrap();
+
+        let mut strategy = BuyAndHold::default();
+        let data = data.out_of_sample(minute(3), minute(5), minute(10));
+        let statistic = simulate(
+            &config,
+            &data,
+            &mut strategy,
+            &TaskControl::default(),
+            |_| {},
+        )
+        .unwrap();
+
+        assert_eq!(strategy.history, Some(3));
+        assert_eq!(strategy.ticks, 5);
+        assert_eq!(statistic.equity_curve.len(), 5);
+
+        // Bought at the close of minute 5 and sold at the close of minute 9.
+        assert_eq!(statistic.trades.len(), 2);
+        assert!(matches!(
+            statistic.trades[1].trade_type,
+            TradeType::MarketSell
+        ));
+        assert_eq!(statistic.net_profit, BigDecimal::from(4));
+        let last = statistic.equity_curve.last().unwrap();
+        assert_eq!(last.equity, BigDecimal::from(1004));
+        assert_eq!(last.cash, last.equity);
+    }
+
+    #[test]
+    fn downsampling_keeps_the_deepest_drawdowns_and_the_final_point() {
+        let len = MAX_EQUITY_POINTS as i64 * 3 + 1;
+        let mut curve = EquityCurve::new();
+        for minute in 0..len {
+            curve.push(point(
+                minute,
+                if minute == 1000 { 500 } else { 1000 + minute },
+            ));
+            assert!(curve.sampled.len() <= MAX_EQUITY_POINTS);
+        }
+
+        let sampled = curve.finish();
+        assert!(sampled.len() <= MAX_EQUITY_POINTS + 1);
+        let deepest = sampled.iter().find(|p| p.equity == BigDecimal::from(500));
+        assert_eq!(deepest.unwrap().drawdown, BigDecimal::from(1499));
+        assert_eq!(
+            sampled.last().unwrap().equity,
+            BigDecimal::from(1000 + len - 1)
+        );
+    }
+
+    struct PlotsRatio;
+
+    impl Strategy for PlotsRatio {
+        fn tick(&mut self, context: &mut StrategyContext) -> AppResult<()> {
+            let seen = context.candles().len() as f64;
+            let change = seen - 2.0;
+            context.plot("ratio", change / change.abs());
+            context.plot("inverse", 1.0 / (seen - 1.0));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn non_finite_plots_are_skipped_so_the_task_survives_persistence() {
+        let task = serde_json::json!({
+            "id": Uuid::nil(),
+            "status": "completed",
+            "progress": 1.0,
+            "name": "plots",
+            "exchange": "test",
+            "symbol": "BTC/USDT",
+            "timeframe": "1m",
+            "initial_capital": "1000",
+            "fees": { "maker": "0", "taker": "0" },
+            "precision": { "price_precision": "0.01", "amount_precision": "0.001" },
+            "created_at": 0,
+            "started_at": 0,
+            "completed_at": 0,
+            "updated_at": 0,
+        });
+        let mut task: BacktestTask = serde_json::from_value(task).unwrap();
+        let data = BacktestData {
+            higher_timeframes: Vec::new(),
+            candles: vec![(0..4).map(|minute| candle(minute, 100, 90)).collect()],
+            sub_candles: None,
+            higher_candles: vec![Vec::new()],
+            trade_from: None,
+            close_at_end: false,
+            fees: vec![task.config.fees.clone().unwrap()],
+            precisions: vec![task.config.precision.clone()],
+        };
+        let mut feed = MemoryFeed::new(&data, task.config.timeframe, None);
+        let (statistic, output) = simulate_feed(
+            &task.config,
+            &mut feed,
+            &data.fees,
+            &data.precisions,
+            &mut PlotsRatio,
+            &TaskControl::default(),
+            |_| {},
+        );
+        statistic.unwrap();
+        task.output = Some(output);
+
+        // Persisted and loaded the way `save_backtest_task` and
+        // `load_backtest_tasks` do.
+        let saved = serde_json::to_value(&task).unwrap();
+        let loaded: BacktestTask = serde_json::from_value(saved).unwrap();
+        let plots = loaded.output.unwrap().plots;
+        let values = |i: usize| plots[i].points.iter().map(|p| p.value).collect::<Vec<_>>();
+        assert_eq!(values(0), [-1.0, 1.0, 1.0]);
+        assert_eq!(values(1), [1.0, 0.5, 1.0 / 3.0]);
+    }
+
+    /// Buys on the first candle with a resting exit and a resting entry that
+    /// never fills, and fails or crashes on the given tick.
+    #[derive(Default)]
+    struct Trader {
+        ticks: usize,
+        fail_at: Option<usize>,
+        crash_at: Option<usize>,
+    }
+
+    impl Strategy for Trader {
+        fn tick(&mut self, context: &mut StrategyContext) -> AppResult<()> {
+            self.ticks += 1;
+            if Some(self.ticks) == self.fail_at {
+                return Err(AppError::Strategy("Gave up".into()));
+            }
+            if Some(self.ticks) == self.crash_at {
+                panic!("crashed");
+            }
+            context.plot("ticks", self.ticks as f64);
+            if self.ticks == 1 {
+                context.market_buy(&BigDecimal::from(2))?;
+                context.limit_sell(&BigDecimal::from(105), &BigDecimal::from(1))?;
+                context.limit_buy(&BigDecimal::from(50), &BigDecimal::from(1))?;
+            }
+            Ok(())
         }
 
-        (mean_return / std_dev) as f32
+        fn on_order_filled(
+            &mut self,
+            context: &mut StrategyContext,
+            _order: &Order,
+            trade: &Trade,
+        ) -> AppResult<()> {
+            let message = format!("Sold {} at {}", trade.amount, trade.price);
+            context.log(LogLevel::Info, message);
+            Ok(())
+        }
+
+        fn on_order_cancelled(
+            &mut self,
+            context: &mut StrategyContext,
+            order: &Order,
+        ) -> AppResult<()> {
+            context.log(LogLevel::Info, format!("Cancelled at {}", order.price));
+            Ok(())
+        }
+    }
+
+    /// Ten rising one-minute candles the [`Trader`] exits on.
+    fn trader_backtest() -> (BacktestConfig, BacktestData) {
+        let config: BacktestConfig = serde_json::from_value(serde_json::json!({
+            "exchange": "test",
+            "symbol": "BTC/USDT",
+            "timeframe": "1m",
+            "initial_capital": "1000",
+            "fees": { "maker": "0.001", "taker": "0.002" },
+            "precision": { "price_precision": "0.01", "amount_precision": "0.001" },
+        }))
+        .unwrap();
+        let data = BacktestData {
+            higher_timeframes: Vec::new(),
+            candles: vec![
+                (0..10)
+                    .map(|minute| candle(minute, 100 + minute, 90))
+                    .collect(),
+            ],
+            sub_candles: None,
+            higher_candles: vec![Vec::new()],
+            trade_from: None,
+            close_at_end: false,
+            fees: vec![config.fees.clone().unwrap()],
+            precisions: vec![config.precision.clone()],
+        };
+        (config, data)
+    }
+
+    #[test]
+    fn reruns_match_despite_new_order_ids() {
+        let (config, data) = trader_backtest();
+        let control = TaskControl::default();
+        let run = || simulate(&config, &data, &mut Trader::default(), &control, |_| {});
+        let (original, rerun) = (run().unwrap(), run().unwrap());
+        assert_ne!(original.trades[1].order_id, rerun.trades[1].order_id);
+        assert!(same_statistic(original.clone(), rerun));
+
+        let mut changed = original.clone();
+        changed.trades[1].price += BigDecimal::from(1);
+        assert!(!same_statistic(original, changed));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn runners_trade_like_strategies_loaded_into_the_server() {
+        use crate::strategy::runner::{Runner, serve_with};
+        use std::os::unix::net::UnixStream;
+
+        let (config, data) = trader_backtest();
+        let run = |strategy: &mut dyn Strategy| {
+            let mut feed = MemoryFeed::new(&data, config.timeframe, lookback(strategy));
+            let control = TaskControl::default();
+            let (fees, precisions) = (&data.fees, &data.precisions);
+            simulate_feed(
+                &config,
+                &mut feed,
+                fees,
+                precisions,
+                strategy,
+                &control,
+                |_| {},
+            )
+        };
+        let in_runner = |trader: Trader| {
+            let (server, runner) = UnixStream::pair().unwrap();
+            let serving =
+                std::thread::spawn(move || serve_with(runner, |_| Ok((Box::new(trader), None))));
+            let mut runner = Runner::connect(server, None, None).unwrap();
+            let result = run(&mut runner);
+            drop(runner);
+            // Hanging up ends a runner that did not crash.
+            if let Ok(served) = serving.join() {
+                served.unwrap();
+            }
+            result
+        };
+        let comparable = |statistic: AppResult<BacktestStatistic>| {
+            let mut statistic = statistic.unwrap();
+            // Resting orders get their ids from the context they are placed in.
+            for trade in &mut statistic.trades {
+                trade.order_id = None;
+            }
+            serde_json::to_value(statistic).unwrap()
+        };
+
+        let (statistic, output) = run(&mut Trader::default());
+        let (runner_statistic, runner_output) = in_runner(Trader::default());
+        assert_eq!(statistic.as_ref().unwrap().trades.len(), 2);
+        assert_eq!(output.logs.len(), 2);
+        assert_eq!(comparable(runner_statistic), comparable(statistic));
+        assert_eq!(
+            serde_json::to_value(runner_output).unwrap(),
+            serde_json::to_value(output).unwrap()
+        );
+
+        let stopping = || Trader {
+            fail_at: Some(3),
+            ..Trader::default()
+        };
+        let failed = run(&mut stopping()).0.unwrap_err().to_string();
+        let runner_failed = in_runner(stopping()).0.unwrap_err().to_string();
+        assert_eq!(runner_failed, failed);
+
+        let crashing = Trader {
+            crash_at: Some(3),
+            ..Trader::default()
+        };
+        let crashed = in_runner(crashing).0.unwrap_err().to_string();
+        assert!(crashed.contains("Strategy runner exited unexpectedly"));
     }
 }
@@ -0,0 +1,617 @@
+use super::{BacktestConfig, BacktestData};
+use crate::errors::AppResult;
+use crate::models::{Candle, Timeframe};
+use crate::services::candles::{
+    CandleCoverage, CandleCursor, Resampler, get_candle_coverage, get_candles,
+};
+use crate::strategy::{CandleWindow, FillModel, Market};
+use chrono::{DateTime, TimeDelta, Utc};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::collections::{BTreeSet, VecDeque};
+use tokio::sync::mpsc;
+
+/// Pages read ahead of the simulation for each series.
+const PREFETCHED_PAGES: usize = 2;
+
+/// Steps a simulation through the candles of every symbol, one timestamp at a
+/// time.
+pub(super) trait CandleFeed<'a> {
+    fn next_timestamp(&mut self) -> AppResult<Option<DateTime<Utc>>>;
+
+    /// Moves `market` to its candle at `timestamp`, if it has one.
+    fn advance(
+        &mut self,
+        index: usize,
+        timestamp: DateTime<Utc>,
+        market: &mut Market<'a>,
+    ) -> AppResult<bool>;
+
+    fn close_at_end(&self) -> bool {
+        false
+    }
+
+    fn progress(&self) -> f32;
+}
+
+/// Where the candles of a higher timeframe come from.
+pub(super) enum HigherSource {
+    /// Stored candles, read from `start`.
+    Stored { start: DateTime<Utc> },
+    /// The base candles resampled, after the `history` before the first of
+    /// them that shares its bucket.
+    Resampled { history: Vec<Candle> },
+}
+
+/// Picks the source of `higher` for a series of `timeframe` candles spanning
+/// `span`. Stored candles are used only if they cover the whole span without
+/// gaps; otherwise the series is resampled. Either way the bucket holding the
+/// first candle is complete, starting before it if need be.
+pub(super) async fn higher_source(
+    db_pool: &PgPool,
+    exchange: &str,
+    symbol: &str,
+    timeframe: Timeframe,
+    span: (DateTime<Utc>, DateTime<Utc>),
+    higher: Timeframe,
+) -> AppResult<HigherSource> {
+    let (first, last) = span;
+    let delta = higher.to_delta();
+    // The stored candles that end after the first candle starts.
+    let start = first - delta + TimeDelta::milliseconds(1);
+    let stored =
+        get_candle_coverage(db_pool, exchange, symbol, higher, Some(start), Some(last)).await?;
+    if stored.is_some_and(|stored| covers(&stored, span, higher)) {
+        return Ok(HigherSource::Stored { start });
+    }
+
+    let bucket_ms = higher.to_ms() as i64;
+    let millis = first.timestamp_millis();
+    let bucket_start =
+        DateTime::from_timestamp_millis(millis - millis.rem_euclid(bucket_ms)).unwrap_or(first);
+    let history = if bucket_start < first {
+        let before = first - TimeDelta::milliseconds(1);
+        get_candles(
+            db_pool,
+            exchange,
+            symbol,
+            timeframe,
+            Some(bucket_start),
+            Some(before),
+        )
+        .await?
+    } else {
+        Vec::new()
+    };
+    Ok(HigherSource::Resampled { history })
+}
+
+/// Whether `stored` candles of `higher` span `span` without gaps. Calendar
+/// months and years vary by a few days, while a missing candle at least
+/// doubles the gap.
+fn covers(
+    stored: &CandleCoverage,
+    span: (DateTime<Utc>, DateTime<Utc>),
+    higher: Timeframe,
+) -> bool {
+    let delta = higher.to_delta();
+    stored.first <= span.0
+        && stored.last + delta > span.1
+        && stored.widest_gap_ms < delta.num_milliseconds() * 3 / 2
+}
+
+/// Lower-timeframe candles for [`FillModel::LowerTimeframe`], streamed by
+/// each simulation rather than shared, as they outnumber the candles.
+#[derive(Clone)]
+pub(super) struct SubCandleSource {
+    db_pool: PgPool,
+    exchange: String,
+    symbols: Vec<String>,
+    timeframe: Timeframe,
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+}
+
+impl SubCandleSource {
+    pub(super) fn new(db_pool: &PgPool, config: &BacktestConfig) -> Option<Self> {
+        let FillModel::LowerTimeframe(timeframe) = config.fill_model else {
+            return None;
+        };
+
+        Some(Self {
+            db_pool: db_pool.clone(),
+            exchange: config.exchange.clone(),
+            symbols: config.all_symbols(),
+            timeframe,
+            start: config.start,
+            end: config.end,
+        })
+    }
+
+    pub(super) fn slice(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Self {
+        Self {
+            start: Some(self.start.map_or(from, |start| start.max(from))),
+            end: Some(self.end.map_or(to, |end| end.min(to))),
+            ..self.clone()
+        }
+    }
+
+    fn open(&self) -> Vec<CandleStream> {
+        self.symbols
+            .iter()
+            .map(|symbol| {
+                CandleStream::spawn(CandleCursor::new(
+                    self.db_pool.clone(),
+                    &self.exchange,
+                    symbol,
+                    self.timeframe,
+                    self.start,
+                    self.end,
+                ))
+            })
+            .collect()
+    }
+}
+
+/// Steps through candles loaded up front, lending the strategy slices of them.
+pub(super) struct MemoryFeed<'a> {
+    data: &'a BacktestData,
+    timeframe: Timeframe,
+    lookback: Option<usize>,
+    timestamps: Vec<DateTime<Utc>>,
+    step: usize,
+    next_candle: Vec<usize>,
+    sub_candles: Option<Vec<CandleStream>>,
+    higher_closed: Vec<Vec<usize>>,
+}
+
+impl<'a> MemoryFeed<'a> {
+    /// Must be called within the Tokio runtime.
+    pub(super) fn new(
+        data: &'a BacktestData,
+        timeframe: Timeframe,
+        lookback: Option<usize>,
+    ) -> Self {
+        let symbols = data.candles.len();
+        let mut timestamps = merge_timestamps(&data.candles);
+        let mut next_candle = vec![0; symbols];
+        // Warmup candles are only history: the feed starts after them.
+        if let Some(from) = data.trade_from {
+            timestamps.retain(|timestamp| *timestamp >= from);
+            for (next, candles) in next_candle.iter_mut().zip(&data.candles) {
+                *next = candles.partition_point(|c| c.timestamp < from);
+            }
+        }
+        Self {
+            data,
+            timeframe,
+            lookback,
+            timestamps,
+            step: 0,
+            next_candle,
+            sub_candles: data.sub_candles.as_ref().map(SubCandleSource::open),
+            higher_closed: vec![vec![0; data.higher_timeframes.len()]; symbols],
+        }
+    }
+
+    fn window(&self, candles: &'a [Candle], end: usize) -> CandleWindow<'a> {
+        let start = self
+            .lookback
+            .map_or(0, |lookback| end.saturating_sub(lookback));
+        CandleWindow::Borrowed(&candles[start..end])
+    }
+}
+
+impl<'a> CandleFeed<'a> for MemoryFeed<'a> {
+    fn next_timestamp(&mut self) -> AppResult<Option<DateTime<Utc>>> {
+        let timestamp = self.timestamps.get(self.step).copied();
+        self.step += 1;
+        Ok(timestamp)
+    }
+
+    fn advance(
+        &mut self,
+        index: usize,
+        timestamp: DateTime<Utc>,
+        market: &mut Market<'a>,
+    ) -> AppResult<bool> {
+        let data = self.data;
+        let candles = &data.candles[index];
+        let next = self.next_candle[index];
+        if next >= candles.len() || candles[next].timestamp != timestamp {
+            return Ok(false);
+        }
+        self.next_candle[index] = next + 1;
+
+        let candle_end = timestamp + self.timeframe.to_delta();
+        if let Some(sub_candles) = &mut self.sub_candles {
+            market.sub_candles = candles_inside(&mut sub_candles[index], timestamp, candle_end)?;
+        }
+
+        // Only higher-timeframe candles that closed by the end of this
+        // candle are visible, so strategies never see ahead.
+        let higher_candles = &data.higher_candles[index];
+        for ((higher, series), closed) in higher_candles.iter().zip(&mut self.higher_closed[index])
+        {
+            while *closed < series.len()
+                && series[*closed].timestamp + higher.to_delta() <= candle_end
+            {
+                *closed += 1;
+            }
+        }
+
+        market.candles = self.window(candles, next + 1);
+        market.higher_candles = higher_candles
+            .iter()
+            .zip(&self.higher_closed[index])
+            .map(|((higher, series), closed)| (*higher, self.window(series, *closed)))
+            .collect();
+        Ok(true)
+    }
+
+    fn close_at_end(&self) -> bool {
+        self.data.close_at_end
+    }
+
+    fn progress(&self) -> f32 {
+        100.0 * self.step.min(self.timestamps.len()) as f32 / self.timestamps.len().max(1) as f32
+    }
+}
+
+/// Streams candles from the database while the simulation runs, keeping only
+/// the latest `lookback` candles of each series in memory.
+pub(super) struct StreamFeed {
+    timeframe: Timeframe,
+    lookback: Option<usize>,
+    symbols: Vec<SymbolStreams>,
+    total: usize,
+    read: usize,
+}
+
+struct SymbolStreams {
+    candles: CandleStream,
+    sub_candles: Option<CandleStream>,
+    higher_candles: Vec<(Timeframe, HigherSeries)>,
+}
+
+enum HigherSeries {
+    Stored(CandleStream),
+    Resampled(Resampler),
+}
+
+/// Count and checksum of the candles a [`StreamFeed`] read, and the range of
+/// those of the backtest's timeframe.
+pub(super) struct CandleDigest {
+    pub(super) count: usize,
+    pub(super) start: DateTime<Utc>,
+    pub(super) end: DateTime<Utc>,
+    pub(super) checksum: String,
+}
+
+impl StreamFeed {
+    pub(super) async fn open(
+        db_pool: &PgPool,
+        config: &BacktestConfig,
+        higher_timeframes: &[Timeframe],
+        lookback: Option<usize>,
+    ) -> AppResult<Self> {
+        let exchange = config.exchange.as_str();
+        let (timeframe, start, end) = (config.timeframe, config.start, config.end);
+        let stream = |symbol: &str, timeframe: Timeframe, start: Option<DateTime<Utc>>| {
+            CandleStream::spawn(CandleCursor::new(
+                db_pool.clone(),
+                exchange,
+                symbol,
+                timeframe,
+                start,
+                end,
+            ))
+        };
+
+        let mut total = 0;
+        let mut symbols = Vec::new();
+        for symbol in &config.all_symbols() {
+            let coverage =
+                get_candle_coverage(db_pool, exchange, symbol, timeframe, start, end).await?;
+            let Some(coverage) = coverage else {
+                return Err(format!("No candles available for {}", symbol).into());
+            };
+            if symbols.is_empty() {
+                total = coverage.count as usize;
+            }
+
+            let span = (coverage.first, coverage.last);
+            let mut higher_candles = Vec::with_capacity(higher_timeframes.len());
+            for higher in higher_timeframes {
+                let source =
+                    higher_source(db_pool, exchange, symbol, timeframe, span, *higher).await?;
+                let series = match source {
+                    HigherSource::Stored { start } => {
+                        HigherSeries::Stored(stream(symbol, *higher, Some(start)))
+                    }
+                    HigherSource::Resampled { history } => {
+                        let mut resampler = Resampler::new(*higher);
+                        for candle in &history {
+                            resampler.push(candle);
+                        }
+                        HigherSeries::Resampled(resampler)
+                    }
+                };
+                higher_candles.push((*higher, series));
+            }
+
+            symbols.push(SymbolStreams {
+                candles: stream(symbol, timeframe, start),
+                sub_candles: match config.fill_model {
+                    FillModel::LowerTimeframe(lower) => Some(stream(symbol, lower, start)),
+                    _ => None,
+                },
+                higher_candles,
+            });
+        }
+
+        Ok(Self {
+            timeframe,
+            lookback,
+            symbols,
+            total,
+            read: 0,
+        })
+    }
+
+    pub(super) fn finish(mut self) -> AppResult<CandleDigest> {
+        let mut hasher = Sha256::new();
+        let mut count = 0;
+        let mut range: Option<(DateTime<Utc>, DateTime<Utc>)> = None;
+        for streams in &mut self.symbols {
+            let stored = streams
+                .higher_candles
+                .iter_mut()
+                .filter_map(|(_, series)| match series {
+                    HigherSeries::Stored(stream) => Some(stream),
+                    HigherSeries::Resampled(_) => None,
+                });
+            let series = std::iter::once(&mut streams.candles)
+                .chain(streams.sub_candles.as_mut())
+                .chain(stored);
+            for (index, stream) in series.enumerate() {
+                stream.drain()?;
+                let digest = &stream.digest;
+                count += digest.count;
+                // Other series start before or end after the backtest's own.
+                if let (0, Some((first, last))) = (index, digest.range) {
+                    range = Some(match range {
+                        Some((start, end)) => (start.min(first), end.max(last)),
+                        None => (first, last),
+                    });
+                }
+                hasher.update(format!("{:x}\n", digest.hasher.clone().finalize()).as_bytes());
+            }
+        }
+
+        let (start, end) = range.unwrap_or_default();
+        Ok(CandleDigest {
+            count,
+            start,
+            end,
+            checksum: format!("{:x}", hasher.finalize()),
+        })
+    }
+}
+
+impl<'a> CandleFeed<'a> for StreamFeed {
+    fn next_timestamp(&mut self) -> AppResult<Option<DateTime<Utc>>> {
+        let mut next: Option<DateTime<Utc>> = None;
+        for streams in &mut self.symbols {
+            if let Some(candle) = streams.candles.peek()? {
+                next = Some(next.map_or(candle.timestamp, |next| next.min(candle.timestamp)));
+            }
+        }
+        Ok(next)
+    }
+
+    fn advance(
+        &mut self,
+        index: usize,
+        timestamp: DateTime<Utc>,
+        market: &mut Market<'a>,
+    ) -> AppResult<bool> {
+        let lookback = self.lookback;
+        let candle_end = timestamp + self.timeframe.to_delta();
+        let streams = &mut self.symbols[index];
+        let Some(candle) = streams.candles.next_if(|c| c.timestamp == timestamp)? else {
+            return Ok(false);
+        };
+
+        if let Some(sub_candles) = &mut streams.sub_candles {
+            market.sub_candles = candles_inside(sub_candles, timestamp, candle_end)?;
+        }
+
+        // Only higher-timeframe candles that closed by the end of this
+        // candle are visible, so strategies never see ahead.
+        if market.higher_candles.len() != streams.higher_candles.len() {
+            market.higher_candles = streams
+                .higher_candles
+                .iter()
+                .map(|(higher, _)| (*higher, CandleWindow::default()))
+                .collect();
+        }
+        for ((higher, series), (_, window)) in streams
+            .higher_candles
+            .iter_mut()
+            .zip(&mut market.higher_candles)
+        {
+            match series {
+                HigherSeries::Stored(stream) => {
+                    while let Some(closed) =
+                        stream.next_if(|c| c.timestamp + higher.to_delta() <= candle_end)?
+                    {
+                        window.push(closed, lookback);
+                    }
+                }
+                HigherSeries::Resampled(resampler) => {
+                    if let Some(closed) = resampler.push(&candle) {
+                        window.push(closed, lookback);
+                    }
+                    if let Some(closed) = resampler.close_by(candle_end) {
+                        window.push(closed, lookback);
+                    }
+                }
+            }
+        }
+
+        market.candles.push(candle, lookback);
+        if index == 0 {
+            self.read += 1;
+        }
+        Ok(true)
+    }
+
+    fn progress(&self) -> f32 {
+        100.0 * self.read.min(self.total) as f32 / self.total.max(1) as f32
+    }
+}
+
+/// One series, read page by page ahead of the simulation. The reading task
+/// ends once the stream is dropped, so a simulation abandoned in a stuck
+/// strategy call keeps it and its prefetched pages until the call returns.
+struct CandleStream {
+    pages: mpsc::Receiver<AppResult<Vec<Candle>>>,
+    page: VecDeque<Candle>,
+    digest: SeriesDigest,
+}
+
+impl CandleStream {
+    fn spawn(mut cursor: CandleCursor) -> Self {
+        let (tx, pages) = mpsc::channel(PREFETCHED_PAGES);
+        tokio::spawn(async move {
+            loop {
+                let page = cursor.next_page().await;
+                let failed = page.is_err();
+                // Sending fails once the simulation has stopped reading.
+                if matches!(&page, Ok(candles) if candles.is_empty())
+                    || tx.send(page).await.is_err()
+                    || failed
+                {
+                    break;
+                }
+            }
+        });
+
+        Self {
+            pages,
+            page: VecDeque::new(),
+            digest: SeriesDigest::default(),
+        }
+    }
+
+    /// Called from the simulation's blocking thread.
+    fn peek(&mut self) -> AppResult<Option<&Candle>> {
+        while self.page.is_empty() {
+            let Some(page) = self.pages.blocking_recv() else {
+                return Ok(None);
+            };
+            let page = page?;
+            self.digest.update(&page);
+            self.page = page.into();
+        }
+        Ok(self.page.front())
+    }
+
+    fn next_if(&mut self, accept: impl FnOnce(&Candle) -> bool) -> AppResult<Option<Candle>> {
+        if self.peek()?.is_some_and(accept) {
+            Ok(self.page.pop_front())
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn drain(&mut self) -> AppResult<()> {
+        while self.peek()?.is_some() {
+            self.page.clear();
+        }
+        Ok(())
+    }
+}
+
+fn candles_inside<'a>(
+    stream: &mut CandleStream,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> AppResult<CandleWindow<'a>> {
+    while stream.next_if(|c| c.timestamp < start)?.is_some() {}
+    let mut inside = Vec::new();
+    while let Some(candle) = stream.next_if(|c| c.timestamp < end)? {
+        inside.push(candle);
+    }
+    Ok(inside.into())
+}
+
+/// Count, range and SHA-256 of the candles read from one series.
+#[derive(Default)]
+struct SeriesDigest {
+    count: usize,
+    range: Option<(DateTime<Utc>, DateTime<Utc>)>,
+    hasher: Sha256,
+}
+
+impl SeriesDigest {
+    fn update(&mut self, page: &[Candle]) {
+        let (Some(first), Some(last)) = (page.first(), page.last()) else {
+            return;
+        };
+        self.count += page.len();
+        self.range = Some(match self.range {
+            Some((start, _)) => (start, last.timestamp),
+            None => (first.timestamp, last.timestamp),
+        });
+        for candle in page {
+            let fields = [
+                candle.timestamp.timestamp_millis().to_string(),
+                candle.symbol.clone(),
+                candle.timeframe.to_string(),
+                candle.open.normalized().to_string(),
+                candle.high.normalized().to_string(),
+                candle.low.normalized().to_string(),
+                candle.close.normalized().to_string(),
+                candle.volume.normalized().to_string(),
+            ];
+            self.hasher.update(fields.join(",").as_bytes());
+            self.hasher.update(b"\n");
+        }
+    }
+}
+
+fn merge_timestamps<C: AsRef<[Candle]>>(series: &[C]) -> Vec<DateTime<Utc>> {
+    series
+        .iter()
+        .flat_map(|candles| candles.as_ref().iter().map(|c| c.timestamp))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hour(hour: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(hour * 3600, 0).unwrap()
+    }
+
+    #[test]
+    fn stored_higher_candles_must_cover_the_span_without_gaps() {
+        let span = (hour(5), hour(30));
+        let stored = |first: i64, last: i64, gap: i64| CandleCoverage {
+            count: 0,
+            first: hour(first),
+            last: hour(last),
+            widest_gap_ms: gap * 3_600_000,
+        };
+
+        assert!(covers(&stored(4, 28, 4), span, Timeframe::H4));
+        assert!(!covers(&stored(8, 28, 4), span, Timeframe::H4));
+        assert!(!covers(&stored(4, 24, 4), span, Timeframe::H4));
+        assert!(!covers(&stored(4, 28, 8), span, Timeframe::H4));
+    }
+}
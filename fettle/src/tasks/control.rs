@@ -0,0 +1,117 @@
+use crate::errors::{AppError, AppResult};
+use crate::tasks::workers::Worker;
+use std::sync::{Arc, Condvar, Mutex};
+use tokio_util::sync::CancellationToken;
+
+/// Lets handlers cancel, pause and resume a running task. Clones share state.
+#[derive(Debug, Clone, Default)]
+pub struct TaskControl {
+    token: CancellationToken,
+    paused: Arc<(Mutex<bool>, Condvar)>,
+    /// Worker of the task's simulation, handed back to the pool while the
+    /// task is paused.
+    worker: Arc<Mutex<Option<Worker>>>,
+}
+
+impl TaskControl {
+    pub fn cancel(&self) {
+        self.token.cancel();
+        // Wake a paused task so it can observe the cancellation. Holding the
+        // lock keeps the wakeup from slipping in before the task waits.
+        let (paused, resumed) = &*self.paused;
+        let _guard = paused.lock().unwrap();
+        resumed.notify_all();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    pub fn pause(&self) {
+        *self.paused.0.lock().unwrap() = true;
+    }
+
+    pub fn resume(&self) {
+        let (paused, resumed) = &*self.paused;
+        *paused.lock().unwrap() = false;
+        resumed.notify_all();
+    }
+
+    /// Blocks while the task is paused and fails once it is cancelled. Called
+    /// from the simulation loop, which runs on a blocking thread. A paused
+    /// task frees its worker for other runs, and waits for a free one again
+    /// once it is resumed.
+    pub fn checkpoint(&self) -> AppResult<()> {
+        let (paused, resumed) = &*self.paused;
+        let guard = paused.lock().unwrap();
+        if *guard && !self.token.is_cancelled() {
+            self.with_worker(Worker::release);
+            let guard = resumed
+                .wait_while(guard, |paused| *paused && !self.token.is_cancelled())
+                .unwrap();
+            drop(guard);
+            if !self.token.is_cancelled() {
+                self.with_worker(Worker::reacquire);
+            }
+        }
+
+        if self.token.is_cancelled() {
+            return Err(AppError::Strategy("Task was cancelled".into()));
+        }
+        Ok(())
+    }
+
+    /// Lends the task `worker` until the returned guard is dropped.
+    pub(crate) fn hold(&self, worker: Worker) -> HeldWorker {
+        *self.worker.lock().unwrap() = Some(worker);
+        HeldWorker(self.clone())
+    }
+
+    fn with_worker(&self, update: impl FnOnce(&mut Worker)) {
+        if let Some(worker) = self.worker.lock().unwrap().as_mut() {
+            update(worker);
+        }
+    }
+}
+
+/// Returns the worker lent by [`TaskControl::hold`] to the pool when dropped.
+pub(crate) struct HeldWorker(TaskControl);
+
+impl Drop for HeldWorker {
+    fn drop(&mut self) {
+        self.0.worker.lock().unwrap().take();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn cancelling_wakes_a_paused_checkpoint() {
+        let control = TaskControl::default();
+        assert!(control.checkpoint().is_ok());
+
+        control.pause();
+        let waiting = control.clone();
+        let handle = std::thread::spawn(move || waiting.checkpoint());
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(!handle.is_finished());
+
+        control.cancel();
+        assert!(handle.join().unwrap().is_err());
+        assert!(control.is_cancelled());
+    }
+
+    #[test]
+    fn resuming_releases_a_paused_checkpoint() {
+        let control = TaskControl::default();
+        control.pause();
+        let waiting = control.clone();
+        let handle = std::thread::spawn(move || waiting.checkpoint());
+
+        control.resume();
+        assert!(handle.join().unwrap().is_ok());
+    }
+}
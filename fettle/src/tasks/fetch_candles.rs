@@ -137,15 +137,15 @@ impl FetchCandlesTask {
             timeframe
         );
 
-        let ccxt = CCXT::with_exchange(&exchange)?;
+        let ccxt = CCXT::with_exchange(exchange)?;
 
         let timeframe_ms = timeframe.to_ms();
         let timeframe_delta = timeframe.to_delta();
         let mut next_since =
-            match candles::get_latest_candle(db_pool, &exchange, &symbol, timeframe).await? {
+            match candles::get_latest_candle(db_pool, exchange, symbol, timeframe).await? {
                 Some(latest_candle) => latest_candle.timestamp + timeframe_delta,
                 None => {
-                    let first_candle = ccxt.first_candle(&symbol, timeframe)?;
+                    let first_candle = ccxt.first_candle(symbol, timeframe)?;
                     let Some(first_candle) = first_candle else {
                         return Err(format!(
                             "No candles data available for {} on {}",
@@ -170,7 +170,7 @@ impl FetchCandlesTask {
         };
 
         let mut count: u64 = 0;
-        let total = (time_diff_ms + timeframe_ms - 1) / timeframe_ms;
+        let total = time_diff_ms.div_ceil(timeframe_ms);
         let mut progress = 0.0;
 
         let now = Utc::now();
@@ -182,7 +182,7 @@ impl FetchCandlesTask {
 
         loop {
             let next_since_ms = next_since.timestamp_millis();
-            let epoch = ccxt.fetch_candles(&symbol, timeframe, Some(next_since_ms), None)?;
+            let epoch = ccxt.fetch_candles(symbol, timeframe, Some(next_since_ms), None)?;
             let Some(latest) = epoch.last() else {
                 break;
             };
@@ -0,0 +1,562 @@
+use crate::errors::{AppError, AppResult};
+use crate::models::Timeframe;
+use crate::services::tasks::save_optimization_task;
+use crate::strategy::{Strategy, StrategyManager};
+use crate::tasks::backtest::{BacktestData, higher_timeframes, simulate};
+use crate::tasks::{BacktestConfig, BacktestStatistic, BacktestStatus, TaskControl, WorkerPool};
+use bigdecimal::ToPrimitive;
+use chrono::{DateTime, Utc, serde::ts_milliseconds, serde::ts_milliseconds_option};
+use futures::stream::{self, Stream, StreamExt};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::{RwLock, broadcast};
+use ts_rs::TS;
+use uuid::Uuid;
+
+pub const MAX_OPTIMIZATION_RUNS: usize = 10000;
+
+/// Values a strategy parameter takes during an optimization.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[ts(export)]
+pub enum ParameterRange {
+    /// An explicit list of values.
+    Values { values: Vec<serde_json::Value> },
+    /// `start`, `start + step`, ... up to and including `end`. Integers are
+    /// produced when `start` and `step` are integral.
+    Range { start: f64, end: f64, step: f64 },
+}
+
+impl ParameterRange {
+    pub fn validate(&self, name: &str) -> AppResult<()> {
+        match self {
+            ParameterRange::Values { values } if values.is_empty() => Err(AppError::BadRequest(
+                format!("Parameter {} has no values", name),
+            )),
+            ParameterRange::Range { start, end, step }
+                if !start.is_finite() || !end.is_finite() || !step.is_finite() =>
+            {
+                Err(AppError::BadRequest(format!(
+                    "Parameter {} must have finite bounds and step",
+                    name
+                )))
+            }
+            ParameterRange::Range { start, end, step } if *step <= 0.0 || end < start => {
+                Err(AppError::BadRequest(format!(
+                    "Parameter {} needs a positive step and an end not before its start",
+                    name
+                )))
+            }
+            ParameterRange::Range { start, end, step }
+                if (end - start) / step >= MAX_OPTIMIZATION_RUNS as f64 =>
+            {
+                Err(AppError::BadRequest(format!(
+                    "Parameter {} takes more than {} values",
+                    name, MAX_OPTIMIZATION_RUNS
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn values(&self) -> Vec<serde_json::Value> {
+        match self {
+            ParameterRange::Values { values } => values.clone(),
+            ParameterRange::Range { start, end, step } => {
+                let count = ((end - start) / step + 1e-9).floor() as usize + 1;
+                let integral = start.fract() == 0.0 && step.fract() == 0.0;
+                (0..count)
+                    .map(|i| {
+                        let value = start + step * i as f64;
+                        if integral {
+                            serde_json::Value::from(value as i64)
+                        } else {
+                            serde_json::Value::from(value)
+                        }
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// How parameter combinations are chosen from the ranges.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(tag = "method", rename_all = "snake_case")]
+#[ts(export)]
+pub enum SearchMethod {
+    /// Every combination.
+    #[default]
+    Grid,
+    /// `samples` distinct combinations drawn at random. The seed is chosen at
+    /// creation when not given, so the draw can be repeated.
+    Random {
+        samples: usize,
+        #[serde(default)]
+        #[ts(optional, type = "number")]
+        seed: Option<u64>,
+    },
+}
+
+/// Statistic the runs are ranked by.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum OptimizationMetric {
+    #[default]
+    NetProfit,
+    ReturnPercent,
+    ProfitFactor,
+    SharpeRatio,
+    SortinoRatio,
+    CalmarRatio,
+    WinRate,
+    /// Ranked lowest first.
+    MaxDrawdownPercent,
+}
+
+impl OptimizationMetric {
+    pub fn value(&self, statistic: &BacktestStatistic) -> f32 {
+        match self {
+            OptimizationMetric::NetProfit => statistic.net_profit.to_f32().unwrap_or(0.0),
+            OptimizationMetric::ReturnPercent => statistic.return_percent,
+            OptimizationMetric::ProfitFactor => statistic.profit_factor,
+            OptimizationMetric::SharpeRatio => statistic.sharpe_ratio,
+            OptimizationMetric::SortinoRatio => statistic.sortino_ratio,
+            OptimizationMetric::CalmarRatio => statistic.calmar_ratio,
+            OptimizationMetric::WinRate => statistic.win_rate,
+            OptimizationMetric::MaxDrawdownPercent => statistic.max_drawdown_percent,
+        }
+    }
+
+    /// Orders `a` before `b` when it is the better value.
+    fn compare(&self, a: f32, b: f32) -> Ordering {
+        match self {
+            OptimizationMetric::MaxDrawdownPercent => a.total_cmp(&b),
+            _ => b.total_cmp(&a),
+        }
+    }
+}
+
+/// Outcome of one parameter combination. The statistic omits the trades,
+/// round trips and equity curve.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct OptimizationResult {
+    pub parameters: serde_json::Value,
+    #[ts(optional)]
+    pub value: Option<f32>,
+    #[ts(optional)]
+    pub statistic: Option<BacktestStatistic>,
+    #[ts(optional)]
+    pub error_message: Option<String>,
+}
+
+impl OptimizationResult {
+    pub(crate) fn new(
+        parameters: serde_json::Value,
+        metric: OptimizationMetric,
+        result: AppResult<BacktestStatistic>,
+    ) -> Self {
+        match result {
+            Ok(mut statistic) => {
+                statistic.trades = Vec::new();
+                statistic.equity_curve = Vec::new();
+                statistic.round_trips = Vec::new();
+                Self {
+                    parameters,
+                    value: Some(metric.value(&statistic)),
+                    statistic: Some(statistic),
+                    error_message: None,
+                }
+            }
+            Err(e) => Self {
+                parameters,
+                value: None,
+                statistic: None,
+                error_message: Some(e.to_string()),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct OptimizationTask {
+    pub id: Uuid,
+    pub status: BacktestStatus,
+    pub progress: f32,
+    pub name: String,
+    /// Settings shared by every run; `parameters` holds the fixed parameters
+    /// the ranges are merged into.
+    #[serde(flatten)]
+    pub config: BacktestConfig,
+    pub ranges: BTreeMap<String, ParameterRange>,
+    pub search: SearchMethod,
+    pub metric: OptimizationMetric,
+    pub total_runs: usize,
+    /// Completed runs, best first.
+    pub results: Vec<OptimizationResult>,
+    #[ts(optional)]
+    pub error_message: Option<String>,
+    #[serde(with = "ts_milliseconds")]
+    #[ts(type = "number")]
+    pub created_at: DateTime<Utc>,
+    #[serde(with = "ts_milliseconds_option")]
+    #[ts(optional, type = "number")]
+    pub started_at: Option<DateTime<Utc>>,
+    #[serde(with = "ts_milliseconds_option")]
+    #[ts(optional, type = "number")]
+    pub completed_at: Option<DateTime<Utc>>,
+    #[serde(with = "ts_milliseconds")]
+    #[ts(type = "number")]
+    pub updated_at: DateTime<Utc>,
+    #[serde(skip)]
+    #[ts(skip)]
+    pub event_tx: Option<broadcast::Sender<OptimizationTask>>,
+}
+
+impl OptimizationTask {
+    async fn update<F>(task: &Arc<RwLock<Self>>, update: F)
+    where
+        F: FnOnce(&mut Self),
+    {
+        let mut task = task.write().await;
+        update(&mut task);
+        task.broadcast();
+    }
+
+    pub fn broadcast(&self) {
+        if let Some(tx) = &self.event_tx {
+            let _ = tx.send(self.clone());
+        }
+    }
+
+    /// Parameter objects of the runs.
+    pub fn combinations(&self) -> AppResult<Vec<serde_json::Value>> {
+        combinations(&self.ranges, self.search, self.config.parameters.as_ref())
+    }
+
+    pub async fn run(
+        task: Arc<RwLock<Self>>,
+        strategy_manager: StrategyManager,
+        db_pool: PgPool,
+        workers: WorkerPool,
+    ) {
+        let now = Utc::now();
+        Self::update(&task, |task| {
+            task.status = BacktestStatus::Compiling;
+            task.started_at = Some(now);
+            task.updated_at = now;
+        })
+        .await;
+
+        let result = Self::execute_optimization(&task, &strategy_manager, &db_pool, &workers).await;
+
+        let now = Utc::now();
+        match result {
+            Ok(()) => {
+                Self::update(&task, |task| {
+                    task.status = BacktestStatus::Completed;
+                    task.progress = 100.0;
+                    task.completed_at = Some(now);
+                    task.updated_at = now;
+                })
+                .await;
+            }
+            Err(e) => {
+                Self::update(&task, |task| {
+                    task.status = BacktestStatus::Failed;
+                    task.error_message = Some(e.to_string());
+                    task.completed_at = Some(now);
+                    task.updated_at = now;
+                })
+                .await;
+            }
+        };
+
+        let task_snapshot = task.read().await.clone();
+        save_optimization_task(&db_pool, &task_snapshot)
+            .await
+            .expect("Failed to save optimization task");
+    }
+
+    async fn execute_optimization(
+        task: &Arc<RwLock<Self>>,
+        strategy_manager: &StrategyManager,
+        db_pool: &PgPool,
+        workers: &WorkerPool,
+    ) -> AppResult<()> {
+        let task_snapshot = task.read().await.clone();
+        let config = Arc::new(task_snapshot.config.clone());
+        let metric = task_snapshot.metric;
+        let combinations = task_snapshot.combinations()?;
+        let total_runs = combinations.len();
+
+        let lib_path = strategy_manager.build_strategy(&task_snapshot.name).await?;
+        let timeframes = strategy_timeframes(strategy_manager, &lib_path, &combinations).await?;
+
+        let now = Utc::now();
+        Self::update(task, |task| {
+            task.status = BacktestStatus::Running;
+            task.total_runs = total_runs;
+            task.updated_at = now;
+        })
+        .await;
+
+        tracing::info!(
+            "Running {} optimization backtests on {}/{} with timeframe {}",
+            total_runs,
+            config.exchange,
+            config.all_symbols().join(","),
+            config.timeframe
+        );
+
+        // Candles are loaded once and shared by every run.
+        let higher_timeframes = higher_timeframes(config.timeframe, timeframes)?;
+        let data = Arc::new(BacktestData::load(db_pool, &config, higher_timeframes).await?);
+
+        let mut runs = std::pin::pin!(run_combinations(
+            workers,
+            strategy_manager,
+            &lib_path,
+            &config,
+            &data,
+            combinations
+        ));
+        while let Some((parameters, result)) = runs.next().await {
+            let result = OptimizationResult::new(parameters, metric, result);
+
+            let now = Utc::now();
+            Self::update(task, |task| {
+                let position = task
+                    .results
+                    .partition_point(|other| rank(metric, other, &result) != Ordering::Greater);
+                task.results.insert(position, result);
+                task.progress = 100.0 * task.results.len() as f32 / total_runs as f32;
+                task.updated_at = now;
+            })
+            .await;
+        }
+
+        Ok(())
+    }
+}
+
+/// Parameter objects drawn from `ranges`, each the `fixed` parameters with one
+/// combination of the ranges merged in.
+pub(crate) fn combinations(
+    ranges: &BTreeMap<String, ParameterRange>,
+    search: SearchMethod,
+    fixed: Option<&serde_json::Value>,
+) -> AppResult<Vec<serde_json::Value>> {
+    let names: Vec<&String> = ranges.keys().collect();
+    let values: Vec<Vec<serde_json::Value>> = ranges.values().map(ParameterRange::values).collect();
+    let total = values
+        .iter()
+        .try_fold(1usize, |total, values| total.checked_mul(values.len()))
+        .unwrap_or(usize::MAX);
+
+    let indices: Vec<usize> = match search {
+        SearchMethod::Grid => {
+            if total > MAX_OPTIMIZATION_RUNS {
+                return Err(AppError::BadRequest(format!(
+                    "Grid has {} combinations, more than the limit of {}",
+                    total, MAX_OPTIMIZATION_RUNS
+                )));
+            }
+            (0..total).collect()
+        }
+        SearchMethod::Random { samples, seed } => {
+            let mut rng = StdRng::seed_from_u64(seed.unwrap_or_default());
+            let samples = samples.min(total).min(MAX_OPTIMIZATION_RUNS);
+            let mut indices = rand::seq::index::sample(&mut rng, total, samples).into_vec();
+            indices.sort_unstable();
+            indices
+        }
+    };
+
+    let base = match fixed {
+        Some(serde_json::Value::Object(base)) => base.clone(),
+        _ => serde_json::Map::new(),
+    };
+    let combinations = indices
+        .into_iter()
+        .map(|mut index| {
+            // Mixed-radix decoding; the last parameter varies fastest.
+            let mut parameters = base.clone();
+            for (name, values) in names.iter().zip(&values).rev() {
+                parameters.insert((*name).clone(), values[index % values.len()].clone());
+                index /= values.len();
+            }
+            serde_json::Value::Object(parameters)
+        })
+        .collect();
+
+    Ok(combinations)
+}
+
+/// Timeframes the strategy reads, asked of the strategy built from the first
+/// combination. They may not depend on the parameters being optimized.
+pub(crate) async fn strategy_timeframes(
+    strategy_manager: &StrategyManager,
+    lib_path: &Path,
+    combinations: &[serde_json::Value],
+) -> AppResult<Vec<Timeframe>> {
+    match combinations.first() {
+        Some(parameters) => {
+            let handle = strategy_manager
+                .open_strategy(lib_path, Some(parameters))
+                .await?;
+            Ok(handle.timeframes())
+        }
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Runs the strategy of the library at `lib_path` with each combination of
+/// parameters over `data` on `workers`, as many at once as the pool allows,
+/// yielding results as they finish. Each strategy is only built once its run
+/// is among those in flight.
+pub(crate) fn run_combinations(
+    workers: &WorkerPool,
+    strategy_manager: &StrategyManager,
+    lib_path: &Path,
+    config: &Arc<BacktestConfig>,
+    data: &Arc<BacktestData>,
+    combinations: Vec<serde_json::Value>,
+) -> impl Stream<Item = (serde_json::Value, AppResult<BacktestStatistic>)> {
+    let concurrency = workers.size();
+    let (workers, strategy_manager, lib_path) = (
+        workers.clone(),
+        strategy_manager.clone(),
+        lib_path.to_path_buf(),
+    );
+    let (config, data) = (config.clone(), data.clone());
+    stream::iter(combinations)
+        .map(move |parameters| {
+            let workers = workers.clone();
+            let strategy_manager = strategy_manager.clone();
+            let lib_path = lib_path.clone();
+            let config = config.clone();
+            let data = data.clone();
+            async move {
+                let handle = strategy_manager
+                    .open_strategy(&lib_path, Some(&parameters))
+                    .await;
+                let result = match handle {
+                    Ok(handle) => workers
+                        .run_strategy(handle, TaskControl::default(), move |handle, control| {
+                            simulate(&config, &data, handle, control, |_| {})
+                        })
+                        .await
+                        .and_then(|result| result),
+                    Err(e) => Err(e),
+                };
+                (parameters, result)
+            }
+        })
+        .buffer_unordered(concurrency)
+}
+
+/// Orders results best first, failed runs last.
+pub(crate) fn rank(
+    metric: OptimizationMetric,
+    a: &OptimizationResult,
+    b: &OptimizationResult,
+) -> Ordering {
+    match (a.value, b.value) {
+        (Some(a), Some(b)) => metric.compare(a, b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn task(search: SearchMethod) -> OptimizationTask {
+        let config: BacktestConfig = serde_json::from_value(json!({
+            "exchange": "binance",
+            "symbol": "BTC/USDT",
+            "timeframe": "1m",
+            "parameters": { "size": "0.5" },
+            "precision": { "price_precision": "0.01", "amount_precision": "0.001" },
+        }))
+        .unwrap();
+        let now = Utc::now();
+
+        OptimizationTask {
+            id: Uuid::new_v4(),
+            status: BacktestStatus::Pending,
+            progress: 0.0,
+            name: "my-strategy".into(),
+            config,
+            ranges: BTreeMap::from([
+                (
+                    "long_period".into(),
+                    ParameterRange::Range {
+                        start: 20.0,
+                        end: 40.0,
+                        step: 10.0,
+                    },
+                ),
+                (
+                    "short_period".into(),
+                    ParameterRange::Values {
+                        values: vec![json!(5), json!(10)],
+                    },
+                ),
+            ]),
+            search,
+            metric: OptimizationMetric::default(),
+            total_runs: 0,
+            results: Vec::new(),
+            error_message: None,
+            created_at: now,
+            started_at: None,
+            completed_at: None,
+            updated_at: now,
+            event_tx: None,
+        }
+    }
+
+    #[test]
+    fn grid_merges_every_combination_into_fixed_parameters() {
+        let combinations = task(SearchMethod::Grid).combinations().unwrap();
+
+        assert_eq!(combinations.len(), 6);
+        assert_eq!(
+            combinations[0],
+            json!({ "size": "0.5", "long_period": 20, "short_period": 5 })
+        );
+        assert_eq!(
+            combinations[5],
+            json!({ "size": "0.5", "long_period": 40, "short_period": 10 })
+        );
+    }
+
+    #[test]
+    fn random_search_draws_distinct_combinations_repeatably() {
+        let search = SearchMethod::Random {
+            samples: 4,
+            seed: Some(7),
+        };
+        let combinations = task(search).combinations().unwrap();
+
+        assert_eq!(combinations.len(), 4);
+        assert_eq!(combinations, task(search).combinations().unwrap());
+        for (i, combination) in combinations.iter().enumerate() {
+            assert!(!combinations[..i].contains(combination));
+        }
+    }
+}
@@ -0,0 +1,354 @@
+use crate::errors::{AppError, AppResult};
+use crate::services::tasks::save_walk_forward_task;
+use crate::strategy::{Strategy, StrategyManager};
+use crate::tasks::backtest::{BacktestData, fill_drawdown, higher_timeframes, simulate};
+use crate::tasks::optimize::{combinations, rank, run_combinations, strategy_timeframes};
+use crate::tasks::{
+    BacktestConfig, BacktestStatistic, BacktestStatus, EquityPoint, OptimizationMetric,
+    OptimizationResult, ParameterRange, SearchMethod, TaskControl, WorkerPool,
+};
+use chrono::{DateTime, Utc, serde::ts_milliseconds, serde::ts_milliseconds_option};
+use futures::stream::StreamExt;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::{RwLock, broadcast};
+use ts_rs::TS;
+use uuid::Uuid;
+
+/// One in-sample optimization and the out-of-sample run of its winner.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct WalkForwardWindow {
+    #[serde(with = "ts_milliseconds")]
+    #[ts(type = "number")]
+    pub in_sample_start: DateTime<Utc>,
+    /// Also the start of the out-of-sample period.
+    #[serde(with = "ts_milliseconds")]
+    #[ts(type = "number")]
+    pub out_of_sample_start: DateTime<Utc>,
+    #[serde(with = "ts_milliseconds")]
+    #[ts(type = "number")]
+    pub out_of_sample_end: DateTime<Utc>,
+    /// Best in-sample run; its parameters are used out of sample.
+    pub in_sample: OptimizationResult,
+    pub out_of_sample: BacktestStatistic,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct WalkForwardTask {
+    pub id: Uuid,
+    pub status: BacktestStatus,
+    pub progress: f32,
+    pub name: String,
+    /// Settings shared by every run; `parameters` holds the fixed parameters
+    /// the ranges are merged into.
+    #[serde(flatten)]
+    pub config: BacktestConfig,
+    pub ranges: BTreeMap<String, ParameterRange>,
+    pub search: SearchMethod,
+    pub metric: OptimizationMetric,
+    /// Primary-symbol candles each optimization is run on.
+    pub in_sample_candles: usize,
+    /// Primary-symbol candles each winner is run on; windows advance by this much.
+    pub out_of_sample_candles: usize,
+    pub windows: Vec<WalkForwardWindow>,
+    /// Out-of-sample equity of all windows, each starting from the previous
+    /// window's final equity.
+    pub equity_curve: Vec<EquityPoint>,
+    #[ts(optional)]
+    pub error_message: Option<String>,
+    #[serde(with = "ts_milliseconds")]
+    #[ts(type = "number")]
+    pub created_at: DateTime<Utc>,
+    #[serde(with = "ts_milliseconds_option")]
+    #[ts(optional, type = "number")]
+    pub started_at: Option<DateTime<Utc>>,
+    #[serde(with = "ts_milliseconds_option")]
+    #[ts(optional, type = "number")]
+    pub completed_at: Option<DateTime<Utc>>,
+    #[serde(with = "ts_milliseconds")]
+    #[ts(type = "number")]
+    pub updated_at: DateTime<Utc>,
+    #[serde(skip)]
+    #[ts(skip)]
+    pub event_tx: Option<broadcast::Sender<WalkForwardTask>>,
+}
+
+impl WalkForwardTask {
+    async fn update<F>(task: &Arc<RwLock<Self>>, update: F)
+    where
+        F: FnOnce(&mut Self),
+    {
+        let mut task = task.write().await;
+        update(&mut task);
+        task.broadcast();
+    }
+
+    pub fn broadcast(&self) {
+        if let Some(tx) = &self.event_tx {
+            let _ = tx.send(self.clone());
+        }
+    }
+
+    /// Parameter objects tried in every in-sample window.
+    pub fn combinations(&self) -> AppResult<Vec<serde_json::Value>> {
+        combinations(&self.ranges, self.search, self.config.parameters.as_ref())
+    }
+
+    /// Start indices of the in-sample, out-of-sample and end of each window
+    /// over `len` candles. The last out-of-sample period may be shorter.
+    fn windows(&self, len: usize) -> Vec<(usize, usize, usize)> {
+        let mut windows = Vec::new();
+        let mut start = 0;
+        while start + self.in_sample_candles < len {
+            let split = start + self.in_sample_candles;
+            windows.push((start, split, (split + self.out_of_sample_candles).min(len)));
+            start += self.out_of_sample_candles;
+        }
+        windows
+    }
+
+    pub async fn run(
+        task: Arc<RwLock<Self>>,
+        strategy_manager: StrategyManager,
+        db_pool: PgPool,
+        workers: WorkerPool,
+    ) {
+        let now = Utc::now();
+        Self::update(&task, |task| {
+            task.status = BacktestStatus::Compiling;
+            task.started_at = Some(now);
+            task.updated_at = now;
+        })
+        .await;
+
+        let result = Self::execute_walk_forward(&task, &strategy_manager, &db_pool, &workers).await;
+
+        let now = Utc::now();
+        match result {
+            Ok(()) => {
+                Self::update(&task, |task| {
+                    task.status = BacktestStatus::Completed;
+                    task.progress = 100.0;
+                    task.completed_at = Some(now);
+                    task.updated_at = now;
+                })
+                .await;
+            }
+            Err(e) => {
+                Self::update(&task, |task| {
+                    task.status = BacktestStatus::Failed;
+                    task.error_message = Some(e.to_string());
+                    task.completed_at = Some(now);
+                    task.updated_at = now;
+                })
+                .await;
+            }
+        };
+
+        let task_snapshot = task.read().await.clone();
+        save_walk_forward_task(&db_pool, &task_snapshot)
+            .await
+            .expect("Failed to save walk-forward task");
+    }
+
+    async fn execute_walk_forward(
+        task: &Arc<RwLock<Self>>,
+        strategy_manager: &StrategyManager,
+        db_pool: &PgPool,
+        workers: &WorkerPool,
+    ) -> AppResult<()> {
+        let task_snapshot = task.read().await.clone();
+        let metric = task_snapshot.metric;
+        let combinations = task_snapshot.combinations()?;
+
+        let lib_path = strategy_manager.build_strategy(&task_snapshot.name).await?;
+        let timeframes = strategy_timeframes(strategy_manager, &lib_path, &combinations).await?;
+
+        let config = task_snapshot.config.clone();
+        let higher_timeframes = higher_timeframes(config.timeframe, timeframes)?;
+        let data = BacktestData::load(db_pool, &config, higher_timeframes).await?;
+        let timestamps = data.timestamps();
+        let windows = task_snapshot.windows(timestamps.len());
+        if windows.is_empty() {
+            return Err(format!(
+                "{} candles are not enough for an in-sample window of {}",
+                timestamps.len(),
+                task_snapshot.in_sample_candles
+            )
+            .into());
+        }
+
+        let now = Utc::now();
+        Self::update(task, |task| {
+            task.status = BacktestStatus::Running;
+            task.updated_at = now;
+        })
+        .await;
+
+        tracing::info!(
+            "Running {} walk-forward windows on {}/{} with timeframe {}",
+            windows.len(),
+            config.exchange,
+            config.all_symbols().join(","),
+            config.timeframe
+        );
+
+        let mut capital = config.initial_capital.clone();
+        for (index, (start, split, end)) in windows.iter().enumerate() {
+            let in_sample_start = timestamps[*start];
+            let out_of_sample_start = timestamps[*split];
+            let out_of_sample_end = timestamps
+                .get(*end)
+                .copied()
+                .unwrap_or_else(|| timestamps[*end - 1] + config.timeframe.to_delta());
+
+            let in_sample = Self::optimize_window(
+                strategy_manager,
+                workers,
+                &lib_path,
+                &config,
+                data.slice(in_sample_start, out_of_sample_start),
+                &combinations,
+                metric,
+            )
+            .await?;
+
+            // The winner trades the next window with the equity carried over,
+            // warmed up on the tail of the in-sample candles it may look back on.
+            // Positions still open at the end of the window are closed, so the
+            // next window starts from cash.
+            let out_of_sample_config = BacktestConfig {
+                parameters: Some(in_sample.parameters.clone()),
+                initial_capital: capital.clone(),
+                ..config.clone()
+            };
+            let handle = strategy_manager
+                .open_strategy(&lib_path, Some(&in_sample.parameters))
+                .await?;
+            let warmup = handle.lookback().map_or(*start, |lookback| {
+                split.saturating_sub(lookback).max(*start)
+            });
+            let out_of_sample_data =
+                data.out_of_sample(timestamps[warmup], out_of_sample_start, out_of_sample_end);
+            let out_of_sample = workers
+                .run_strategy(handle, TaskControl::default(), move |handle, control| {
+                    simulate(
+                        &out_of_sample_config,
+                        &out_of_sample_data,
+                        handle,
+                        control,
+                        |_| {},
+                    )
+                })
+                .await??;
+
+            let curve = out_of_sample.equity_curve.clone();
+            if let Some(last) = curve.last() {
+                capital = last.equity.clone();
+            }
+
+            let window = WalkForwardWindow {
+                in_sample_start,
+                out_of_sample_start,
+                out_of_sample_end,
+                in_sample,
+                out_of_sample,
+            };
+            let progress = 100.0 * (index + 1) as f32 / windows.len() as f32;
+            let now = Utc::now();
+            Self::update(task, |task| {
+                task.windows.push(window);
+                task.equity_curve.extend(curve);
+                // Drawdowns span windows, so they are measured on the stitched curve.
+                task.equity_curve = fill_drawdown(std::mem::take(&mut task.equity_curve));
+                task.progress = progress;
+                task.updated_at = now;
+            })
+            .await;
+        }
+
+        Ok(())
+    }
+
+    /// Runs every combination over one in-sample window and returns the best.
+    async fn optimize_window(
+        strategy_manager: &StrategyManager,
+        workers: &WorkerPool,
+        lib_path: &Path,
+        config: &BacktestConfig,
+        data: BacktestData,
+        combinations: &[serde_json::Value],
+        metric: OptimizationMetric,
+    ) -> AppResult<OptimizationResult> {
+        let config = Arc::new(config.clone());
+        let data = Arc::new(data);
+        let runs = run_combinations(
+            workers,
+            strategy_manager,
+            lib_path,
+            &config,
+            &data,
+            combinations.to_vec(),
+        );
+        let results: Vec<OptimizationResult> = runs
+            .map(|(parameters, result)| OptimizationResult::new(parameters, metric, result))
+            .collect()
+            .await;
+
+        let best = results
+            .into_iter()
+            .min_by(|a, b| rank(metric, a, b))
+            .filter(|best| best.value.is_some());
+        best.ok_or_else(|| AppError::Strategy("Every in-sample run failed".into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn windows_roll_by_the_out_of_sample_length() {
+        let mut task: WalkForwardTask = serde_json::from_value(json!({
+            "id": Uuid::nil(),
+            "status": "pending",
+            "progress": 0.0,
+            "name": "my-strategy",
+            "exchange": "binance",
+            "symbol": "BTC/USDT",
+            "timeframe": "1m",
+            "precision": { "price_precision": "0.01", "amount_precision": "0.001" },
+            "ranges": {},
+            "search": { "method": "grid" },
+            "metric": "net_profit",
+            "in_sample_candles": 100,
+            "out_of_sample_candles": 30,
+            "windows": [],
+            "equity_curve": [],
+            "created_at": 0,
+            "started_at": null,
+            "completed_at": null,
+            "updated_at": 0,
+        }))
+        .unwrap();
+
+        assert_eq!(
+            task.windows(200),
+            vec![
+                (0, 100, 130),
+                (30, 130, 160),
+                (60, 160, 190),
+                (90, 190, 200)
+            ]
+        );
+
+        task.in_sample_candles = 200;
+        assert!(task.windows(200).is_empty());
+    }
+}
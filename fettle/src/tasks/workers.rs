@@ -0,0 +1,319 @@
+use crate::errors::{AppError, AppResult};
+use crate::strategy::{StrategyHandle, StrategyWatch};
+use crate::tasks::TaskControl;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::task::JoinHandle;
+
+/// How often running strategies are checked against their time budget.
+const WATCHDOG_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Longest a strategy may spend in one call, and in all calls of one
+/// simulation. `None` is unlimited.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TimeBudget {
+    pub call: Option<Duration>,
+    pub total: Option<Duration>,
+}
+
+impl TimeBudget {
+    /// Describes how the strategy timed by `watch` overran the budget, if it did.
+    fn overrun(&self, watch: &StrategyWatch) -> Option<String> {
+        if let (Some(limit), Some((call, elapsed))) = (self.call, watch.current())
+            && elapsed > limit
+        {
+            return Some(format!("Strategy {} ran for more than {:?}", call, limit));
+        }
+        match self.total {
+            Some(limit) if watch.spent() > limit => {
+                Some(format!("Strategy ran for more than {:?} in total", limit))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A worker of a [`WorkerPool`], held by the job running on it.
+#[derive(Debug)]
+pub(crate) struct Worker {
+    permits: Arc<Semaphore>,
+    permit: Option<OwnedSemaphorePermit>,
+}
+
+impl Worker {
+    /// Frees the worker for other jobs while this one waits.
+    pub(crate) fn release(&mut self) {
+        self.permit = None;
+    }
+
+    /// Blocks until a worker is free again, if this one was released.
+    pub(crate) fn reacquire(&mut self) {
+        if self.permit.is_none() {
+            self.permit = futures::executor::block_on(self.permits.clone().acquire_owned()).ok();
+        }
+    }
+}
+
+/// Runs simulations on Tokio's blocking threads, at most `size` at once across
+/// every backtest, optimization and walk-forward task. Clones share the limit.
+/// A job paused through its [`TaskControl`] does not count towards it.
+#[derive(Debug, Clone)]
+pub struct WorkerPool {
+    permits: Arc<Semaphore>,
+    size: usize,
+    budget: TimeBudget,
+}
+
+impl WorkerPool {
+    pub fn new(size: usize) -> Self {
+        let size = size.max(1);
+        Self {
+            permits: Arc::new(Semaphore::new(size)),
+            size,
+            budget: TimeBudget::default(),
+        }
+    }
+
+    /// Limits the time strategies run by [`WorkerPool::run_strategy`] may take.
+    pub fn with_budget(mut self, budget: TimeBudget) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn budget(&self) -> TimeBudget {
+        self.budget
+    }
+
+    /// Waits for a free worker and runs `job` on it, keeping the async
+    /// runtime free for requests while strategies tick.
+    pub async fn run<T, F>(&self, job: F) -> AppResult<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let _permit = self.acquire().await?;
+        tokio::task::spawn_blocking(job).await.map_err(run_panicked)
+    }
+
+    /// Like [`WorkerPool::run`], handing `strategy` and `control` to `job`
+    /// and failing once the strategy overruns the pool's time budget. The
+    /// worker is lent to `control`, so that pausing the task frees it until it
+    /// is resumed.
+    ///
+    /// A strategy stuck in a call cannot be interrupted: the run fails at
+    /// once, but its thread keeps the worker, and whatever `job` holds such as
+    /// a candle feed streaming from the database, until the call returns. A
+    /// call that never returns leaks them until the server restarts, so the
+    /// pool never runs more threads than it has workers.
+    pub async fn run_strategy<T, F>(
+        &self,
+        mut strategy: StrategyHandle,
+        control: TaskControl,
+        job: F,
+    ) -> AppResult<T>
+    where
+        F: FnOnce(&mut StrategyHandle, &TaskControl) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let watch = strategy.watch();
+        let mut job = self
+            .spawn(&control.clone(), move || job(&mut strategy, &control))
+            .await?;
+        if self.budget.call.is_none() && self.budget.total.is_none() {
+            return job.await.map_err(run_panicked);
+        }
+
+        let mut watchdog = tokio::time::interval(WATCHDOG_INTERVAL);
+        loop {
+            tokio::select! {
+                result = &mut job => return result.map_err(run_panicked),
+                _ = watchdog.tick() => {
+                    if let Some(overrun) = self.budget.overrun(&watch) {
+                        watch.stop();
+                        return Err(AppError::Strategy(overrun));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Waits for a free worker and starts `job` on it, lending the worker to
+    /// `control` until the job returns, even if the returned handle is
+    /// dropped first.
+    async fn spawn<T, F>(&self, control: &TaskControl, job: F) -> AppResult<JoinHandle<T>>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let permit = self
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(pool_closed)?;
+        let held = control.hold(Worker {
+            permits: self.permits.clone(),
+            permit: Some(permit),
+        });
+        Ok(tokio::task::spawn_blocking(move || {
+            let _held = held;
+            job()
+        }))
+    }
+
+    async fn acquire(&self) -> AppResult<tokio::sync::SemaphorePermit<'_>> {
+        self.permits.acquire().await.map_err(pool_closed)
+    }
+}
+
+fn pool_closed(e: tokio::sync::AcquireError) -> AppError {
+    AppError::Internal(format!("Worker pool closed: {}", e))
+}
+
+fn run_panicked(e: tokio::task::JoinError) -> AppError {
+    AppError::Strategy(format!("Backtest run panicked: {}", e))
+}
+
+impl Default for WorkerPool {
+    /// One worker per core.
+    fn default() -> Self {
+        Self::new(
+            std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strategy::Clock;
+    use futures::future::join_all;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn jobs_never_exceed_the_pool_size() {
+        let pool = WorkerPool::new(2);
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+
+        let jobs = (0..6).map(|i| {
+            let (running, peak) = (running.clone(), peak.clone());
+            pool.run(move || {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                std::thread::sleep(Duration::from_millis(20));
+                running.fetch_sub(1, Ordering::SeqCst);
+                i
+            })
+        });
+        let results: Vec<usize> = join_all(jobs)
+            .await
+            .into_iter()
+            .collect::<AppResult<_>>()
+            .unwrap();
+
+        assert_eq!(results, vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn a_paused_job_frees_its_worker() {
+        let pool = WorkerPool::new(1);
+        let control = TaskControl::default();
+        control.pause();
+
+        let (started_tx, started_rx) = std::sync::mpsc::channel();
+        let paused = control.clone();
+        let first = pool
+            .spawn(&control, move || {
+                started_tx.send(()).unwrap();
+                paused.checkpoint()
+            })
+            .await
+            .unwrap();
+        tokio::task::spawn_blocking(move || started_rx.recv().unwrap())
+            .await
+            .unwrap();
+
+        // The only worker is free while the first job is paused.
+        let second = tokio::time::timeout(Duration::from_secs(10), pool.run(|| 2));
+        assert_eq!(second.await.unwrap().unwrap(), 2);
+        assert!(!first.is_finished());
+
+        control.resume();
+        first.await.unwrap().unwrap();
+        assert_eq!(pool.run(|| 3).await.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn an_abandoned_job_keeps_its_worker_until_it_returns() {
+        let pool = WorkerPool::new(1);
+        let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+        let stuck = pool
+            .spawn(&TaskControl::default(), move || release_rx.recv())
+            .await
+            .unwrap();
+        drop(stuck);
+
+        let waiting = tokio::time::timeout(Duration::from_millis(50), pool.run(|| 1));
+        assert!(waiting.await.is_err());
+
+        release_tx.send(()).unwrap();
+        assert_eq!(pool.run(|| 2).await.unwrap(), 2);
+    }
+
+    #[test]
+    fn budget_catches_a_long_call_and_a_long_total() {
+        let clock = Clock::manual();
+        let watch = Arc::new(StrategyWatch::with_clock(clock.clone()));
+        let budget = TimeBudget {
+            call: Some(Duration::from_millis(50)),
+            total: Some(Duration::from_millis(120)),
+        };
+
+        let (entered_tx, entered_rx) = std::sync::mpsc::channel();
+        let (release_tx, release_rx) = std::sync::mpsc::channel();
+        let calling = watch.clone();
+        let call = std::thread::spawn(move || {
+            calling.call("tick", || {
+                entered_tx.send(()).unwrap();
+                release_rx.recv().unwrap();
+                Ok(())
+            })
+        });
+        entered_rx.recv().unwrap();
+        clock.advance(Duration::from_millis(50));
+        assert!(budget.overrun(&watch).is_none());
+        clock.advance(Duration::from_millis(10));
+        let overrun = budget.overrun(&watch).unwrap();
+        assert!(overrun.contains("tick ran for more than 50ms"));
+        release_tx.send(()).unwrap();
+        call.join().unwrap().unwrap();
+        assert!(budget.overrun(&watch).is_none());
+
+        for _ in 0..2 {
+            watch
+                .call("tick", || {
+                    clock.advance(Duration::from_millis(30));
+                    Ok(())
+                })
+                .unwrap();
+        }
+        assert!(budget.overrun(&watch).is_none());
+        watch
+            .call("tick", || {
+                clock.advance(Duration::from_millis(1));
+                Ok(())
+            })
+            .unwrap();
+        assert!(budget.overrun(&watch).unwrap().contains("in total"));
+    }
+}
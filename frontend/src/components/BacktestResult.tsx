@@ -1,6 +1,8 @@
 import { useState } from 'react'
-import { TrendingUp, TrendingDown, DollarSign, Percent, BarChart3, ArrowUpRight, ArrowDownRight, ChevronLeft, ChevronRight, type LucideIcon } from 'lucide-react'
-import { formatTimestamp } from '@/utils/time'
+import { TrendingUp, TrendingDown, DollarSign, Percent, BarChart3, Clock, ArrowUpRight, ArrowDownRight, ChevronLeft, ChevronRight, type LucideIcon } from 'lucide-react'
+import EquityChart from '@/components/EquityChart'
+import { formatDuration, formatTimestamp } from '@/utils/time'
+import { isBuyTrade, tradeKindLabel } from '@/utils/trade'
 import type { BacktestStatistic, MarketPrecision } from '@/types'
 
 interface BacktestResultProps {
@@ -12,6 +14,7 @@ const TRADES_PER_PAGE = 20
 
 export default function BacktestResult({ statistic, precision }: BacktestResultProps) {
   const [currentPage, setCurrentPage] = useState(1)
+  const [roundTripPage, setRoundTripPage] = useState(1)
   const netProfitValue = Number(statistic.net_profit)
   const returnPercentValue = statistic.return_percent
   const isProfit = netProfitValue > 0
@@ -146,6 +149,70 @@ export default function BacktestResult({ statistic, precision }: BacktestResultP
           />
         </div>
 
+        <div className="grid grid-cols-2 md:grid-cols-4 gap-4 mt-4">
+          <StatCard
+            icon={BarChart3}
+            label="Sortino Ratio"
+            value={formatNumber(statistic.sortino_ratio)}
+            valueColor={statistic.sortino_ratio > 1 ? 'text-green-600' : statistic.sortino_ratio > 0 ? 'text-gray-900' : 'text-red-600'}
+          />
+
+          <StatCard
+            icon={BarChart3}
+            label="Calmar Ratio"
+            value={formatNumber(statistic.calmar_ratio)}
+            valueColor={statistic.calmar_ratio > 1 ? 'text-green-600' : statistic.calmar_ratio > 0 ? 'text-gray-900' : 'text-red-600'}
+            subtitle={`CAGR ${formatPercent(statistic.cagr_percent)}`}
+          />
+
+          <StatCard
+            icon={Clock}
+            label="Exposure"
+            value={formatPercent(statistic.exposure_percent)}
+            valueColor="text-gray-900"
+            subtitle={`Avg hold ${formatDuration(statistic.avg_holding_time)}`}
+          />
+
+          <StatCard
+            icon={TrendingDown}
+            label="Longest Drawdown"
+            value={formatDuration(statistic.max_drawdown_duration)}
+            valueColor="text-red-600"
+          />
+        </div>
+
+        <div className="grid grid-cols-2 md:grid-cols-4 gap-4 mt-4">
+          <StatCard
+            icon={TrendingUp}
+            label="Buy & Hold"
+            value={formatPercent(statistic.benchmark_return_percent)}
+            valueColor={statistic.benchmark_return_percent >= 0 ? 'text-green-600' : 'text-red-600'}
+            subtitle={`Excess ${formatPercent(statistic.excess_return_percent)}`}
+          />
+
+          <StatCard
+            icon={Percent}
+            label="Alpha"
+            value={formatPercent(statistic.alpha_percent)}
+            valueColor={statistic.alpha_percent > 0 ? 'text-green-600' : 'text-red-600'}
+            subtitle={`Beta ${formatNumber(statistic.beta)}`}
+          />
+
+          <StatCard
+            icon={BarChart3}
+            label="Correlation"
+            value={formatNumber(statistic.correlation)}
+            valueColor="text-gray-900"
+          />
+
+          <StatCard
+            icon={BarChart3}
+            label="Information Ratio"
+            value={formatNumber(statistic.information_ratio)}
+            valueColor={statistic.information_ratio > 0 ? 'text-green-600' : 'text-red-600'}
+          />
+        </div>
+
         <div className="grid grid-cols-3 gap-4 mt-4">
           <div className="bg-gray-50 rounded-lg p-4 text-center">
             <div className="text-sm text-gray-600 mb-1">Total Orders</div>
@@ -162,6 +229,13 @@ export default function BacktestResult({ statistic, precision }: BacktestResultP
         </div>
       </div>
 
+      {statistic.equity_curve.length > 0 && (
+        <div className="bg-white rounded-xl border border-gray-200 p-6">
+          <h2 className="text-lg font-medium text-gray-900 mb-4">Equity Curve</h2>
+          <EquityChart points={statistic.equity_curve} />
+        </div>
+      )}
+
       <div className="bg-white rounded-xl border border-gray-200 p-6">
         <h2 className="text-lg font-medium text-gray-900 mb-4">Trade Statistics</h2>
 
@@ -243,6 +317,104 @@ export default function BacktestResult({ statistic, precision }: BacktestResultP
         </div>
       </div>
 
+      {statistic.round_trips.length > 0 && (
+        <div className="bg-white rounded-xl border border-gray-200 p-6">
+          <div className="flex items-center justify-between mb-4">
+            <h2 className="text-lg font-medium text-gray-900">Round Trips</h2>
+            <div className="text-sm text-gray-500">
+              {statistic.round_trips.length} closed positions
+            </div>
+          </div>
+
+          <div className="overflow-x-auto">
+            <table className="w-full text-sm">
+              <thead>
+                <tr className="border-b border-gray-200">
+                  <th className="text-left py-3 px-4 font-medium text-gray-700">Entry</th>
+                  <th className="text-left py-3 px-4 font-medium text-gray-700">Side</th>
+                  <th className="text-right py-3 px-4 font-medium text-gray-700">Entry Price</th>
+                  <th className="text-right py-3 px-4 font-medium text-gray-700">Exit Price</th>
+                  <th className="text-right py-3 px-4 font-medium text-gray-700">Amount</th>
+                  <th className="text-right py-3 px-4 font-medium text-gray-700">Bars</th>
+                  <th className="text-right py-3 px-4 font-medium text-gray-700">MFE</th>
+                  <th className="text-right py-3 px-4 font-medium text-gray-700">MAE</th>
+                  <th className="text-right py-3 px-4 font-medium text-gray-700">Profit</th>
+                </tr>
+              </thead>
+              <tbody>
+                {statistic.round_trips
+                  .slice((roundTripPage - 1) * TRADES_PER_PAGE, roundTripPage * TRADES_PER_PAGE)
+                  .map((trip, idx) => {
+                    const profitValue = Number(trip.profit)
+
+                    return (
+                      <tr key={idx} className="border-b border-gray-100 hover:bg-gray-50">
+                        <td className="py-3 px-4 text-gray-600">
+                          {formatTimestamp(trip.entry_time)}
+                        </td>
+                        <td className="py-3 px-4">
+                          <span className={`text-xs font-medium px-2 py-1 rounded ${trip.is_long ? 'bg-green-100 text-green-700' : 'bg-red-100 text-red-700'}`}>
+                            {trip.is_long ? 'LONG' : 'SHORT'}
+                          </span>
+                        </td>
+                        <td className="py-3 px-4 text-right text-gray-900 font-mono">
+                          {formatPrice(trip.entry_price)}
+                        </td>
+                        <td className="py-3 px-4 text-right text-gray-900 font-mono">
+                          {formatPrice(trip.exit_price)}
+                        </td>
+                        <td className="py-3 px-4 text-right text-gray-900 font-mono">
+                          {formatAmount(trip.amount)}
+                        </td>
+                        <td className="py-3 px-4 text-right text-gray-600 font-mono">
+                          {trip.bars_held}
+                        </td>
+                        <td className="py-3 px-4 text-right text-green-600 font-mono">
+                          {formatPercent(trip.mfe_percent)}
+                        </td>
+                        <td className="py-3 px-4 text-right text-red-600 font-mono">
+                          {formatPercent(trip.mae_percent)}
+                        </td>
+                        <td className={`py-3 px-4 text-right font-mono font-medium ${profitValue > 0 ? 'text-green-600' : profitValue < 0 ? 'text-red-600' : 'text-gray-900'}`}>
+                          {(profitValue > 0 ? '+' : '') + formatNumber(trip.profit, 2)}
+                          <div className="text-xs text-gray-500">{formatPercent(trip.return_percent)}</div>
+                        </td>
+                      </tr>
+                    )
+                  })}
+              </tbody>
+            </table>
+          </div>
+
+          {statistic.round_trips.length > TRADES_PER_PAGE && (
+            <div className="flex items-center justify-between mt-4 pt-4 border-t border-gray-200">
+              <div className="text-sm text-gray-600">
+                Showing {((roundTripPage - 1) * TRADES_PER_PAGE) + 1} to {Math.min(roundTripPage * TRADES_PER_PAGE, statistic.round_trips.length)} of {statistic.round_trips.length} round trips
+              </div>
+              <div className="flex items-center gap-2">
+                <button
+                  onClick={() => setRoundTripPage(p => Math.max(1, p - 1))}
+                  disabled={roundTripPage === 1}
+                  className="p-2 rounded-lg border border-gray-200 hover:bg-gray-50 disabled:opacity-50 disabled:cursor-not-allowed transition-colors"
+                >
+                  <ChevronLeft className="w-4 h-4" />
+                </button>
+                <span className="text-sm text-gray-600">
+                  Page {roundTripPage} of {Math.ceil(statistic.round_trips.length / TRADES_PER_PAGE)}
+                </span>
+                <button
+                  onClick={() => setRoundTripPage(p => Math.min(Math.ceil(statistic.round_trips.length / TRADES_PER_PAGE), p + 1))}
+                  disabled={roundTripPage >= Math.ceil(statistic.round_trips.length / TRADES_PER_PAGE)}
+                  className="p-2 rounded-lg border border-gray-200 hover:bg-gray-50 disabled:opacity-50 disabled:cursor-not-allowed transition-colors"
+                >
+                  <ChevronRight className="w-4 h-4" />
+                </button>
+              </div>
+            </div>
+          )}
+        </div>
+      )}
+
       {statistic.trades.length > 0 && (
         <div className="bg-white rounded-xl border border-gray-200 p-6">
           <div className="flex items-center justify-between mb-4">
@@ -268,8 +440,8 @@ export default function BacktestResult({ statistic, precision }: BacktestResultP
                 {statistic.trades
                   .slice((currentPage - 1) * TRADES_PER_PAGE, currentPage * TRADES_PER_PAGE)
                   .map((trade, idx) => {
-                    const isBuy = trade.trade_type === 'market_buy' || trade.trade_type === 'limit_buy'
-                    const isLimit = trade.trade_type === 'limit_buy' || trade.trade_type === 'limit_sell'
+                    const isBuy = isBuyTrade(trade.trade_type)
+                    const kind = tradeKindLabel(trade.trade_type)
                     const profitValue = Number(trade.profit)
 
                     return (
@@ -282,9 +454,9 @@ export default function BacktestResult({ statistic, precision }: BacktestResultP
                             <span className={`text-xs font-medium px-2 py-1 rounded ${isBuy ? 'bg-green-100 text-green-700' : 'bg-red-100 text-red-700'}`}>
                               {isBuy ? 'BUY' : 'SELL'}
                             </span>
-                            {isLimit && (
+                            {kind !== 'MARKET' && (
                               <span className="text-xs text-gray-500 px-2 py-1 bg-gray-100 rounded">
-                                LIMIT
+                                {kind}
                               </span>
                             )}
                           </div>
@@ -11,6 +11,7 @@ interface CandlestickChartProps {
   symbol?: string
   markers?: SeriesMarker<Time>[]
   markerDetails?: ChartMarkerDetail[]
+  lines?: ChartLine[]
   loading?: boolean
   timeframeOptions?: Timeframe[]
   activeTimeframe?: Timeframe | ''
@@ -18,6 +19,14 @@ interface CandlestickChartProps {
   controls?: ReactNode
 }
 
+export interface ChartLine {
+  id: string
+  title: string
+  data: LineData<Time>[]
+}
+
+const chartLineColors = ['#8b5cf6', '#0ea5e9', '#ec4899', '#14b8a6', '#eab308', '#64748b']
+
 export interface ChartMarkerDetail {
   id: string
   title: string
@@ -153,6 +162,7 @@ export default function CandlestickChart({
   symbol,
   markers,
   markerDetails = [],
+  lines = [],
   loading = false,
   timeframeOptions = [],
   activeTimeframe = '',
@@ -167,6 +177,7 @@ export default function CandlestickChart({
   const volumeSeriesRef = useRef<ISeriesApi<'Histogram'> | null>(null)
   const markersRef = useRef<ISeriesMarkersPluginApi<Time> | null>(null)
   const indicatorSeriesRef = useRef(new Map<IndicatorId, ISeriesApi<'Line'>>())
+  const lineSeriesRef = useRef(new Map<string, ISeriesApi<'Line'>>())
   const drawingPrimitiveRef = useRef(new ChartDrawingPrimitive())
   const drawingsRef = useRef<ChartDrawing[]>([])
   const selectedDrawingIdRef = useRef<string | null>(null)
@@ -510,6 +521,7 @@ export default function CandlestickChart({
     candlestickSeries.attachPrimitive(drawingPrimitive)
     syncDrawingPrimitive()
     const indicatorSeries = indicatorSeriesRef.current
+    const lineSeries = lineSeriesRef.current
 
     const handleResize = () => {
       syncChartSize()
@@ -520,6 +532,7 @@ export default function CandlestickChart({
     return () => {
       window.removeEventListener('resize', handleResize)
       indicatorSeries.clear()
+      lineSeries.clear()
       candlestickSeries.detachPrimitive(drawingPrimitive)
       chart.remove()
     }
@@ -767,6 +780,37 @@ export default function CandlestickChart({
     })
   }, [data, enabledIndicators])
 
+  useEffect(() => {
+    const chart = chartRef.current
+    if (!chart) {
+      return
+    }
+
+    const lineSeries = lineSeriesRef.current
+    const lineIds = new Set(lines.map((line) => line.id))
+    lineSeries.forEach((series, id) => {
+      if (!lineIds.has(id)) {
+        chart.removeSeries(series)
+        lineSeries.delete(id)
+      }
+    })
+
+    lines.forEach((line, index) => {
+      let series = lineSeries.get(line.id)
+      if (!series) {
+        series = chart.addSeries(LineSeries, {
+          color: chartLineColors[index % chartLineColors.length],
+          lineWidth: 2,
+          title: line.title,
+          priceLineVisible: false,
+          lastValueVisible: false,
+        })
+        lineSeries.set(line.id, series)
+      }
+      series.setData(line.data)
+    })
+  }, [lines])
+
   const selectedDrawing = selectedDrawingId
     ? drawings.find((drawing) => drawing.id === selectedDrawingId) ?? null
     : null
@@ -0,0 +1,83 @@
+import { useEffect, useRef } from 'react'
+import { createChart, HistogramSeries, LineSeries, type Time } from 'lightweight-charts'
+import { formatChartTime } from '@/utils/time'
+import type { EquityPoint } from '@/types'
+
+interface EquityChartProps {
+  points: EquityPoint[]
+}
+
+export default function EquityChart({ points }: EquityChartProps) {
+  const containerRef = useRef<HTMLDivElement>(null)
+
+  useEffect(() => {
+    if (!containerRef.current) return
+
+    const chart = createChart(containerRef.current, {
+      layout: {
+        background: { color: '#ffffff' },
+        textColor: '#333',
+      },
+      grid: {
+        vertLines: { color: '#f0f0f0' },
+        horzLines: { color: '#f0f0f0' },
+      },
+      width: containerRef.current.clientWidth,
+      height: 280,
+      localization: {
+        timeFormatter: (timestamp: number) => formatChartTime(timestamp),
+      },
+      timeScale: {
+        timeVisible: true,
+        secondsVisible: false,
+        borderColor: '#e0e0e0',
+      },
+      rightPriceScale: {
+        borderColor: '#e0e0e0',
+      },
+    })
+
+    const equitySeries = chart.addSeries(LineSeries, {
+      color: '#2563eb',
+      lineWidth: 2,
+      title: 'Equity',
+    })
+    equitySeries.setData(points.map(point => ({
+      time: (point.timestamp / 1000) as Time,
+      value: Number(point.equity),
+    })))
+
+    const drawdownSeries = chart.addSeries(HistogramSeries, {
+      color: 'rgba(220, 38, 38, 0.4)',
+      priceScaleId: 'drawdown',
+      title: 'Drawdown',
+    })
+    drawdownSeries.priceScale().applyOptions({
+      scaleMargins: {
+        top: 0.75,
+        bottom: 0,
+      },
+    })
+    drawdownSeries.setData(points.map(point => ({
+      time: (point.timestamp / 1000) as Time,
+      value: -Number(point.drawdown),
+    })))
+
+    chart.timeScale().fitContent()
+
+    const handleResize = () => {
+      if (containerRef.current) {
+        chart.applyOptions({ width: containerRef.current.clientWidth })
+      }
+    }
+
+    window.addEventListener('resize', handleResize)
+
+    return () => {
+      window.removeEventListener('resize', handleResize)
+      chart.remove()
+    }
+  }, [points])
+
+  return <div ref={containerRef} className="w-full" />
+}
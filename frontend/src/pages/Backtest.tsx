@@ -1,14 +1,22 @@
 import { useEffect, useState, useRef, useCallback } from 'react'
-import { Play, Clock, CheckCircle, XCircle, Loader, TrendingUp, TrendingDown } from 'lucide-react'
+import { Play, Pause, Square, Clock, CheckCircle, XCircle, Loader, TrendingUp, TrendingDown, RotateCcw, Ban } from 'lucide-react'
 import { useAppSettings } from '@/lib/appSettings'
-import CandlestickChart, { type ChartMarkerDetail } from '@/components/CandlestickChart'
+import CandlestickChart, { type ChartLine, type ChartMarkerDetail } from '@/components/CandlestickChart'
 import { api } from '@/services/api'
 import { useBacktestStream } from '@/hooks/useBacktestStream'
 import ComboBox from '@/components/ComboBox'
 import BacktestResult from '@/components/BacktestResult'
-import type { Timeframe, BacktestTask, AvailableCandleInfo, Candle, Trade } from '@/types'
+import type { Timeframe, BacktestTask, AvailableCandleInfo, Candle, LogLevel, Trade } from '@/types'
 import type { CandlestickData, HistogramData, SeriesMarker, Time } from 'lightweight-charts'
 import { formatTimestamp } from '@/utils/time'
+import { isBuyTrade, tradeKindLabel } from '@/utils/trade'
+
+const logLevelClassNames: Record<LogLevel, string> = {
+  debug: 'text-gray-400',
+  info: 'text-blue-600',
+  warn: 'text-amber-600',
+  error: 'text-red-600',
+}
 
 export default function Backtest() {
   const settings = useAppSettings()
@@ -24,6 +32,7 @@ export default function Backtest() {
   const [availableData, setAvailableData] = useState<AvailableCandleInfo[]>([])
   const [tradeMarkers, setTradeMarkers] = useState<SeriesMarker<Time>[]>([])
   const [tradeMarkerDetails, setTradeMarkerDetails] = useState<ChartMarkerDetail[]>([])
+  const [plotLines, setPlotLines] = useState<ChartLine[]>([])
   const [chartData, setChartData] = useState<CandlestickData[]>([])
   const [volumeData, setVolumeData] = useState<HistogramData<Time>[]>([])
   const [selectedTaskId, setSelectedTaskId] = useState<string | null>(null)
@@ -97,8 +106,8 @@ export default function Backtest() {
     const details: ChartMarkerDetail[] = []
 
     trades.forEach((trade, index) => {
-      const isBuy = trade.trade_type === 'market_buy' || trade.trade_type === 'limit_buy'
-      const isLimit = trade.trade_type === 'limit_buy' || trade.trade_type === 'limit_sell'
+      const isBuy = isBuyTrade(trade.trade_type)
+      const kind = tradeKindLabel(trade.trade_type)
       const markerId = `${trade.timestamp}-${trade.trade_type}-${index}`
 
       markers.push({
@@ -107,20 +116,21 @@ export default function Backtest() {
         position: isBuy ? 'belowBar' : 'aboveBar',
         color: isBuy ? '#26a69a' : '#ef5350',
         shape: isBuy ? 'arrowUp' : 'arrowDown',
-        text: `${isLimit ? 'LIMIT' : 'MARKET'} ${isBuy ? 'BUY' : 'SELL'} ${trade.amount} @ ${trade.price}`,
+        text: `${kind} ${isBuy ? 'BUY' : 'SELL'} ${trade.amount} @ ${trade.price}`,
       })
 
       const profitValue = trade.profit ? Number(trade.profit) : null
 
       details.push({
         id: markerId,
-        title: `${isLimit ? 'Limit' : 'Market'} ${isBuy ? 'Buy' : 'Sell'}`,
+        title: `${kind} ${isBuy ? 'BUY' : 'SELL'}`,
         accentColor: isBuy ? '#26a69a' : '#ef5350',
         fields: [
           { label: 'Time', value: formatTimestamp(trade.timestamp) },
           { label: 'Price', value: trade.price },
           { label: 'Amount', value: trade.amount },
           { label: 'Fee', value: trade.fee },
+          { label: 'Slippage', value: trade.slippage },
           {
             label: 'Profit',
             value: profitValue === null || Number.isNaN(profitValue)
@@ -137,6 +147,19 @@ export default function Backtest() {
     }
   }
 
+  const convertPlotsToLines = (task: BacktestTask): ChartLine[] => {
+    return (task.output?.plots ?? [])
+      .filter(series => series.symbol === task.symbol)
+      .map(series => ({
+        id: series.name,
+        title: series.name,
+        data: series.points.map(point => ({
+          time: (point.timestamp / 1000) as Time,
+          value: point.value,
+        })),
+      }))
+  }
+
   const handleRunBacktest = useCallback(async (e?: React.MouseEvent) => {
     if (e) {
       e.preventDefault()
@@ -170,6 +193,27 @@ export default function Backtest() {
     }
   }, [selectedStrategy, selectedExchange, selectedSymbol, selectedTimeframe])
 
+  const handleRerun = useCallback(async (taskId: string) => {
+    try {
+      await api.backtest.rerun(taskId)
+    } catch (error) {
+      console.error('Failed to rerun backtest:', error)
+    }
+  }, [])
+
+  const handleTaskControl = useCallback(async (
+    event: React.MouseEvent,
+    action: 'cancel' | 'pause' | 'resume',
+    taskId: string,
+  ) => {
+    event.stopPropagation()
+    try {
+      await api.backtest[action](taskId)
+    } catch (error) {
+      console.error(`Failed to ${action} backtest:`, error)
+    }
+  }, [])
+
   const loadChartForTask = useCallback(async (task: BacktestTask) => {
     if (loadingChartRef.current) return
 
@@ -223,11 +267,14 @@ export default function Backtest() {
     if (selectedTaskId && selectedTaskId !== loadedChartTaskId && !loadingChartRef.current) {
         const task = tasks.find(t => t.id === selectedTaskId)
       if (task && task.status === 'completed' && task.statistic) {
-        const markerData = convertTradesToMarkers(task.statistic.trades)
+        const markerData = convertTradesToMarkers(
+          task.statistic.trades.filter(t => !t.symbol || t.symbol === task.symbol),
+        )
         setChartData([])
         setVolumeData([])
         setTradeMarkers(markerData.markers)
         setTradeMarkerDetails(markerData.details)
+        setPlotLines(convertPlotsToLines(task))
         loadChartForTask(task)
       }
     }
@@ -247,10 +294,14 @@ export default function Backtest() {
         return <Loader className="w-4 h-4 text-yellow-500 animate-spin" />
       case 'running':
         return <Loader className="w-4 h-4 text-blue-500 animate-spin" />
+      case 'paused':
+        return <Pause className="w-4 h-4 text-gray-500" />
       case 'completed':
         return <CheckCircle className="w-4 h-4 text-green-500" />
       case 'failed':
         return <XCircle className="w-4 h-4 text-red-500" />
+      case 'cancelled':
+        return <Ban className="w-4 h-4 text-gray-400" />
     }
   }
 
@@ -369,7 +420,7 @@ export default function Backtest() {
                               {task.name}
                             </p>
                             <p className="text-xs text-gray-500">
-                              {task.symbol} · {task.exchange} · {task.timeframe}
+                              {[task.symbol, ...task.symbols].join(', ')} · {task.exchange} · {task.timeframe}
                             </p>
                           </div>
                           {getTaskStatusIcon(task)}
@@ -381,21 +432,54 @@ export default function Backtest() {
                           </div>
                         )}
 
-                        {task.status === 'running' && (
+                        {(task.status === 'running' || task.status === 'paused') && (
                           <div className="mt-2">
                             <div className="flex items-center justify-between text-xs text-gray-500 mb-1">
-                              <span>Progress</span>
+                              <span>{task.status === 'paused' ? 'Paused' : 'Progress'}</span>
                               <span>{Math.round(task.progress)}%</span>
                             </div>
                             <div className="h-1.5 bg-gray-100 rounded-full overflow-hidden">
                               <div
-                                className="h-full bg-blue-500 transition-all duration-300"
+                                className={`h-full transition-all duration-300 ${task.status === 'paused' ? 'bg-gray-400' : 'bg-blue-500'}`}
                                 style={{ width: `${task.progress}%` }}
                               />
                             </div>
                           </div>
                         )}
 
+                        {['pending', 'compiling', 'running', 'paused'].includes(task.status) && (
+                          <div className="mt-2 flex items-center gap-2 text-xs">
+                            {task.status === 'running' && (
+                              <span
+                                role="button"
+                                onClick={(e) => handleTaskControl(e, 'pause', task.id)}
+                                className="flex items-center gap-1 px-2 py-1 rounded border border-gray-200 text-gray-600 hover:bg-gray-100"
+                              >
+                                <Pause className="w-3 h-3" />
+                                Pause
+                              </span>
+                            )}
+                            {task.status === 'paused' && (
+                              <span
+                                role="button"
+                                onClick={(e) => handleTaskControl(e, 'resume', task.id)}
+                                className="flex items-center gap-1 px-2 py-1 rounded border border-gray-200 text-gray-600 hover:bg-gray-100"
+                              >
+                                <Play className="w-3 h-3" />
+                                Resume
+                              </span>
+                            )}
+                            <span
+                              role="button"
+                              onClick={(e) => handleTaskControl(e, 'cancel', task.id)}
+                              className="flex items-center gap-1 px-2 py-1 rounded border border-gray-200 text-red-600 hover:bg-red-50"
+                            >
+                              <Square className="w-3 h-3" />
+                              Cancel
+                            </span>
+                          </div>
+                        )}
+
                         {task.status === 'completed' && task.statistic && (
                           <div className="mt-3 pt-3 border-t border-gray-100 space-y-2">
                             <div className="flex items-center justify-between text-xs">
@@ -443,11 +527,34 @@ export default function Backtest() {
           {selectedResultTask?.statistic && (
             <div className="mt-6 space-y-6">
               <div className="bg-white rounded-xl border border-gray-200 p-6">
-                <div className="mb-4">
-                  <h2 className="text-xl font-semibold text-gray-900">Backtest Result</h2>
-                  <p className="text-sm text-gray-500 mt-1">
-                    {selectedResultTask.name} · {selectedResultTask.symbol} · {selectedResultTask.exchange} · {selectedResultTask.timeframe}
-                  </p>
+                <div className="mb-4 flex items-start justify-between">
+                  <div>
+                    <h2 className="text-xl font-semibold text-gray-900">Backtest Result</h2>
+                    <p className="text-sm text-gray-500 mt-1">
+                      {selectedResultTask.name} · {selectedResultTask.symbol} · {selectedResultTask.exchange} · {selectedResultTask.timeframe}
+                    </p>
+                    {selectedResultTask.inputs && (
+                      <p className="text-xs text-gray-400 mt-1 font-mono">
+                        source {selectedResultTask.inputs.source_hash.slice(0, 12)} · library {selectedResultTask.inputs.library_hash.slice(0, 12)} · candles {selectedResultTask.inputs.candle_checksum.slice(0, 12)}
+                      </p>
+                    )}
+                    {selectedResultTask.reproduction && (
+                      <p className={`text-xs mt-1 ${selectedResultTask.reproduction.statistic_matches ? 'text-green-600' : 'text-red-600'}`}>
+                        Rerun {selectedResultTask.reproduction.statistic_matches ? 'reproduced' : 'did not reproduce'} the original result
+                        {!selectedResultTask.reproduction.candles_match && ' · candles changed'}
+                        {!selectedResultTask.reproduction.source_unchanged && ' · source edited since'}
+                      </p>
+                    )}
+                  </div>
+                  {selectedResultTask.inputs && (
+                    <button
+                      onClick={() => handleRerun(selectedResultTask.id)}
+                      className="flex items-center gap-2 px-3 py-2 text-sm border border-gray-200 rounded-lg hover:bg-gray-50 transition-colors"
+                    >
+                      <RotateCcw className="w-4 h-4" />
+                      Rerun
+                    </button>
+                  )}
                 </div>
 
                 <div className="border border-gray-200 rounded-lg overflow-hidden bg-white">
@@ -457,9 +564,33 @@ export default function Backtest() {
                     symbol={`${selectedResultTask.symbol} (${selectedResultTask.exchange} - ${selectedResultTask.timeframe})`}
                     markers={tradeMarkers}
                     markerDetails={tradeMarkerDetails}
+                    lines={plotLines}
                     loading={loadingChart}
                   />
                 </div>
+
+                {selectedResultTask.output && selectedResultTask.output.logs.length > 0 && (
+                  <div className="mt-4">
+                    <h3 className="text-sm font-medium text-gray-900 mb-2">
+                      Strategy Log
+                      {selectedResultTask.output.dropped_logs > 0 && (
+                        <span className="ml-2 text-xs font-normal text-gray-400">
+                          {selectedResultTask.output.dropped_logs} later entries not kept
+                        </span>
+                      )}
+                    </h3>
+                    <div className="max-h-64 overflow-y-auto border border-gray-200 rounded-lg font-mono text-xs divide-y divide-gray-100">
+                      {selectedResultTask.output.logs.map((entry, index) => (
+                        <div key={index} className="flex gap-3 px-3 py-1.5">
+                          <span className="text-gray-400 whitespace-nowrap">{formatTimestamp(entry.timestamp)}</span>
+                          <span className={`w-12 uppercase ${logLevelClassNames[entry.level]}`}>{entry.level}</span>
+                          <span className="text-gray-500 whitespace-nowrap">{entry.symbol}</span>
+                          <span className="text-gray-800 break-all">{entry.message}</span>
+                        </div>
+                      ))}
+                    </div>
+                  </div>
+                )}
               </div>
 
               <BacktestResult
@@ -143,6 +143,26 @@ export const api = {
         body: JSON.stringify(request),
       }),
 
+    rerun: (id: string) =>
+      fetchAPI<CreateBacktestTaskResponse>(`/tasks/backtest/${id}/rerun`, {
+        method: 'POST',
+      }),
+
+    cancel: (id: string) =>
+      fetchAPI<void>(`/tasks/backtest/${id}/cancel`, {
+        method: 'POST',
+      }),
+
+    pause: (id: string) =>
+      fetchAPI<void>(`/tasks/backtest/${id}/pause`, {
+        method: 'POST',
+      }),
+
+    resume: (id: string) =>
+      fetchAPI<void>(`/tasks/backtest/${id}/resume`, {
+        method: 'POST',
+      }),
+
     stream: (onEvent: (task: BacktestTask) => void, onError?: (error: Error) => void) => {
       const eventSource = new EventSource(buildApiUrl('/tasks/backtest/stream'))
 
@@ -0,0 +1,34 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { FillModel } from "./FillModel";
+import type { LiquidityConfig } from "./LiquidityConfig";
+import type { MarginConfig } from "./MarginConfig";
+import type { MarketPrecision } from "./MarketPrecision";
+import type { SlippageModel } from "./SlippageModel";
+import type { Timeframe } from "./Timeframe";
+import type { TradingFees } from "./TradingFees";
+import type { JsonValue } from "./serde_json/JsonValue";
+
+/**
+ * Market and simulation settings of a backtest.
+ */
+export type BacktestConfig = { exchange: string, symbol: string, 
+/**
+ * Additional symbols traded alongside `symbol` from one shared balance.
+ */
+symbols: Array<string>, timeframe: Timeframe, 
+/**
+ * Object deserialized into the strategy; its defaults are used when unset.
+ */
+parameters?: JsonValue, start?: number, end?: number, initial_capital: string, 
+/**
+ * Annual rate the Sharpe and Sortino ratios measure excess returns over.
+ */
+risk_free_rate: string, 
+/**
+ * Fees charged on every symbol; the exchange's fees when unset.
+ */
+fees?: TradingFees, 
+/**
+ * Precision of the primary symbol.
+ */
+precision: MarketPrecision, margin: MarginConfig, fill_model: FillModel, slippage_model: SlippageModel, liquidity?: LiquidityConfig, };
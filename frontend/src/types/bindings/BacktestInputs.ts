@@ -0,0 +1,37 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { MarketPrecision } from "./MarketPrecision";
+import type { TradingFees } from "./TradingFees";
+import type { JsonValue } from "./serde_json/JsonValue";
+
+/**
+ * Everything a backtest ran on, recorded so it can be replayed.
+ */
+export type BacktestInputs = { 
+/**
+ * SHA-256 of the strategy package's files and the workspace lock file.
+ */
+source_hash: string, 
+/**
+ * SHA-256 of the compiled library, which is archived under this hash.
+ */
+library_hash: string, 
+/**
+ * Earliest candle of the backtest's timeframe, over all symbols.
+ */
+candles_start: number, 
+/**
+ * Latest candle of the backtest's timeframe, over all symbols.
+ */
+candles_end: number, candle_count: number, 
+/**
+ * SHA-256 of every candle loaded.
+ */
+candle_checksum: string, 
+/**
+ * Fees of each symbol, in the order of `BacktestConfig::all_symbols`.
+ */
+fees: Array<TradingFees>, 
+/**
+ * Precision of each symbol, in the order of `BacktestConfig::all_symbols`.
+ */
+precisions: Array<MarketPrecision>, parameters?: JsonValue, };
@@ -1,4 +1,72 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { EquityPoint } from "./EquityPoint";
+import type { RoundTrip } from "./RoundTrip";
 import type { Trade } from "./Trade";
 
-export type BacktestStatistic = { trades: Array<Trade>, initial_capital: string, total_cost: string, net_profit: string, return_percent: number, max_equity: string, max_drawdown: string, max_drawdown_percent: number, gross_profit: string, gross_loss: string, profit_factor: number, sharpe_ratio: number, total_trades: number, buy_trades: number, sell_trades: number, winning_trades: number, losing_trades: number, win_rate: number, avg_win: string, avg_loss: string, largest_win: string, largest_loss: string, };
+export type BacktestStatistic = { trades: Array<Trade>, initial_capital: string, total_cost: string, net_profit: string, return_percent: number, max_equity: string, max_drawdown: string, max_drawdown_percent: number, gross_profit: string, gross_loss: string, profit_factor: number, 
+/**
+ * Annualized from per-candle equity returns in excess of the risk-free rate.
+ */
+sharpe_ratio: number, 
+/**
+ * Like `sharpe_ratio`, but only returns below the risk-free rate count as risk.
+ */
+sortino_ratio: number, 
+/**
+ * `cagr_percent` divided by `max_drawdown_percent`.
+ */
+calmar_ratio: number, 
+/**
+ * Compound annual growth rate of the closing equity; zero for backtests
+ * spanning less than 30 days.
+ */
+cagr_percent: number, 
+/**
+ * Share of candles that closed with a position open.
+ */
+exposure_percent: number, 
+/**
+ * Mean time from opening a position to closing or reversing it, in
+ * milliseconds.
+ */
+avg_holding_time: number, 
+/**
+ * Longest time the closing equity stayed below an earlier high, in
+ * milliseconds.
+ */
+max_drawdown_duration: number, 
+/**
+ * Return of buying every symbol with an equal share of the capital at its
+ * first open and selling it at the last close, after taker fees.
+ */
+benchmark_return_percent: number, 
+/**
+ * `return_percent` less `benchmark_return_percent`.
+ */
+excess_return_percent: number, 
+/**
+ * Sensitivity of the per-candle equity returns to the benchmark's.
+ */
+beta: number, 
+/**
+ * Annualized return not explained by `beta`, over the risk-free rate.
+ */
+alpha_percent: number, 
+/**
+ * Correlation of the per-candle equity returns with the benchmark's.
+ */
+correlation: number, 
+/**
+ * Annualized mean return over the benchmark divided by its standard
+ * deviation.
+ */
+information_ratio: number, total_trades: number, buy_trades: number, sell_trades: number, winning_trades: number, losing_trades: number, win_rate: number, avg_win: string, avg_loss: string, largest_win: string, largest_loss: string, 
+/**
+ * Positions from entry to exit, in the order they were closed.
+ */
+round_trips: Array<RoundTrip>, 
+/**
+ * Account state at candle closes, downsampled to at most
+ * `MAX_EQUITY_POINTS` points that keep the deepest drawdowns.
+ */
+equity_curve: Array<EquityPoint>, };
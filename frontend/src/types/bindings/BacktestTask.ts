@@ -1,7 +1,44 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { BacktestInputs } from "./BacktestInputs";
 import type { BacktestStatistic } from "./BacktestStatistic";
 import type { BacktestStatus } from "./BacktestStatus";
+import type { FillModel } from "./FillModel";
+import type { LiquidityConfig } from "./LiquidityConfig";
+import type { MarginConfig } from "./MarginConfig";
 import type { MarketPrecision } from "./MarketPrecision";
+import type { Reproduction } from "./Reproduction";
+import type { SlippageModel } from "./SlippageModel";
+import type { StrategyOutput } from "./StrategyOutput";
 import type { Timeframe } from "./Timeframe";
+import type { TradingFees } from "./TradingFees";
+import type { JsonValue } from "./serde_json/JsonValue";
 
-export type BacktestTask = { id: string, status: BacktestStatus, progress: number, name: string, exchange: string, symbol: string, timeframe: Timeframe, precision: MarketPrecision, statistic?: BacktestStatistic, error_message?: string, created_at: number, started_at?: number, completed_at?: number, updated_at: number, };
+export type BacktestTask = { id: string, status: BacktestStatus, progress: number, name: string, inputs?: BacktestInputs, 
+/**
+ * Task this one replays.
+ */
+rerun_of?: string, reproduction?: Reproduction, statistic?: BacktestStatistic, 
+/**
+ * What the strategy logged and plotted, kept when the run fails too.
+ */
+output?: StrategyOutput, error_message?: string, created_at: number, started_at?: number, completed_at?: number, updated_at: number, exchange: string, symbol: string, 
+/**
+ * Additional symbols traded alongside `symbol` from one shared balance.
+ */
+symbols: Array<string>, timeframe: Timeframe, 
+/**
+ * Object deserialized into the strategy; its defaults are used when unset.
+ */
+parameters?: JsonValue, start?: number, end?: number, initial_capital: string, 
+/**
+ * Annual rate the Sharpe and Sortino ratios measure excess returns over.
+ */
+risk_free_rate: string, 
+/**
+ * Fees charged on every symbol; the exchange's fees when unset.
+ */
+fees?: TradingFees, 
+/**
+ * Precision of the primary symbol.
+ */
+precision: MarketPrecision, margin: MarginConfig, fill_model: FillModel, slippage_model: SlippageModel, liquidity?: LiquidityConfig, };
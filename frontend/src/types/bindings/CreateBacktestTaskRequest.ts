@@ -1,4 +1,31 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { FillModel } from "./FillModel";
+import type { LiquidityConfig } from "./LiquidityConfig";
+import type { MarginConfig } from "./MarginConfig";
+import type { MarketPrecision } from "./MarketPrecision";
+import type { SlippageModel } from "./SlippageModel";
 import type { Timeframe } from "./Timeframe";
+import type { TradingFees } from "./TradingFees";
+import type { JsonValue } from "./serde_json/JsonValue";
 
-export type CreateBacktestTaskRequest = { name: string, exchange: string, symbol: string, timeframe: Timeframe, };
+export type CreateBacktestTaskRequest = { name: string, exchange: string, symbol: string, 
+/**
+ * Additional symbols traded alongside `symbol` from one shared balance.
+ */
+symbols?: Array<string>, timeframe: Timeframe, 
+/**
+ * Object deserialized into the strategy's parameters.
+ */
+parameters?: JsonValue, start?: number, end?: number, initial_capital?: string, 
+/**
+ * Annual rate, e.g. `0.04`; zero when unset.
+ */
+risk_free_rate?: string, 
+/**
+ * Overrides the exchange's fees for every symbol.
+ */
+fees?: TradingFees, 
+/**
+ * Overrides the exchange's precision for `symbol`.
+ */
+precision?: MarketPrecision, margin?: MarginConfig, fill_model?: FillModel, slippage_model?: SlippageModel, liquidity?: LiquidityConfig, };
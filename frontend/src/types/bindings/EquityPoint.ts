@@ -0,0 +1,14 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Account state at the close of a candle.
+ */
+export type EquityPoint = { timestamp: number, equity: string, cash: string, 
+/**
+ * Signed value of all positions at their closes.
+ */
+position_value: string, 
+/**
+ * Distance below the highest equity of earlier points.
+ */
+drawdown: string, };
@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { Timeframe } from "./Timeframe";
+
+/**
+ * Order in which a candle's prices are assumed to have traded when matching
+ * resting orders.
+ */
+export type FillModel = "open_high_low_close" | "open_low_high_close" | "pessimistic" | { "lower_timeframe": Timeframe };
@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { JsonValue } from "./serde_json/JsonValue";
+
+export type GetParametersResponse = { 
+/**
+ * JSON schema of the strategy's parameters; absent when it takes none.
+ */
+schema?: JsonValue, };
@@ -0,0 +1,10 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Caps how much of a candle's volume resting limit orders may fill.
+ */
+export type LiquidityConfig = { 
+/**
+ * Fraction of each candle's volume available to limit and stop-limit fills.
+ */
+max_volume_fraction: string, };
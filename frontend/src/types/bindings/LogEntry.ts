@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { LogLevel } from "./LogLevel";
+
+export type LogEntry = { 
+/**
+ * Open time of the candle the strategy was handling.
+ */
+timestamp: number, level: LogLevel, symbol: string, message: string, };
@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Statistic the runs are ranked by.
+ */
+export type OptimizationMetric = "net_profit" | "return_percent" | "profit_factor" | "sharpe_ratio" | "sortino_ratio" | "calmar_ratio" | "win_rate" | "max_drawdown_percent";
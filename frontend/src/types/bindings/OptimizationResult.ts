@@ -0,0 +1,9 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { BacktestStatistic } from "./BacktestStatistic";
+import type { JsonValue } from "./serde_json/JsonValue";
+
+/**
+ * Outcome of one parameter combination. The statistic omits the trades,
+ * round trips and equity curve.
+ */
+export type OptimizationResult = { parameters: JsonValue, value?: number, statistic?: BacktestStatistic, error_message?: string, };
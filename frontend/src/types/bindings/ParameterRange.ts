@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { JsonValue } from "./serde_json/JsonValue";
+
+/**
+ * Values a strategy parameter takes during an optimization.
+ */
+export type ParameterRange = { "type": "values", values: Array<JsonValue>, } | { "type": "range", start: number, end: number, step: number, };
@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { PlotPoint } from "./PlotPoint";
+
+/**
+ * Values a strategy plotted under one name for one symbol.
+ */
+export type PlotSeries = { name: string, symbol: string, points: Array<PlotPoint>, };
@@ -0,0 +1,19 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * How a rerun compares with the task it replayed.
+ */
+export type Reproduction = { 
+/**
+ * Whether the candles loaded again have the recorded checksum.
+ */
+candles_match: boolean, 
+/**
+ * Whether the statistic equals the original one.
+ */
+statistic_matches: boolean, 
+/**
+ * Whether the strategy source still has the recorded hash. The archived
+ * library is replayed either way.
+ */
+source_unchanged: boolean, };
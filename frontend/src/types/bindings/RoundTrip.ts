@@ -0,0 +1,40 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * A position from the trade that opened it to the one that closed or
+ * reversed it. Excursions are measured on the fill prices and on the highs
+ * and lows of the candles strictly between entry and exit.
+ */
+export type RoundTrip = { symbol: string, is_long: boolean, entry_time: number, exit_time: number, 
+/**
+ * Average price of the fills that opened or added to the position.
+ */
+entry_price: string, 
+/**
+ * Average price of the fills that reduced the position.
+ */
+exit_price: string, 
+/**
+ * Total amount opened.
+ */
+amount: string, fees: string, 
+/**
+ * Realized profit after fees.
+ */
+profit: string, 
+/**
+ * `profit` as a share of the entry value.
+ */
+return_percent: number, 
+/**
+ * Largest move in the position's favour from `entry_price`.
+ */
+mfe_percent: number, 
+/**
+ * Largest move against the position from `entry_price`; zero or negative.
+ */
+mae_percent: number, 
+/**
+ * Candles of the symbol from the entry candle to the exit candle.
+ */
+bars_held: number, };
@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * How parameter combinations are chosen from the ranges.
+ */
+export type SearchMethod = { "method": "grid" } | { "method": "random", samples: number, seed?: number, };
@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * How far a market execution moves away from the quoted price.
+ */
+export type SlippageModel = "none" | { "fixed_bps": { bps: string, } } | { "range_percent": { percent: string, } } | { "volume_participation": { impact: string, } };
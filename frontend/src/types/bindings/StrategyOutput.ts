@@ -0,0 +1,21 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { LogEntry } from "./LogEntry";
+import type { PlotSeries } from "./PlotSeries";
+
+/**
+ * What a strategy logged and plotted during a backtest.
+ */
+export type StrategyOutput = { 
+/**
+ * The first `MAX_LOG_ENTRIES` entries, in the order they were logged.
+ */
+logs: Array<LogEntry>, 
+/**
+ * Entries logged after `logs` was full.
+ */
+dropped_logs: number, 
+/**
+ * Series in the order they were first plotted, each downsampled to at
+ * most `MAX_PLOT_POINTS` points.
+ */
+plots: Array<PlotSeries>, };
@@ -1,4 +1,18 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { SlippageModel } from "./SlippageModel";
 import type { TradeType } from "./TradeType";
 
-export type Trade = { timestamp: number, trade_type: TradeType, price: string, amount: string, fee: string, profit?: string, };
+export type Trade = { timestamp: number, symbol: string, trade_type: TradeType, price: string, amount: string, fee: string, 
+/**
+ * Per-unit price move against the trade caused by slippage.
+ */
+slippage: string, 
+/**
+ * Slippage model the trade was filled under; `none` for maker fills.
+ */
+slippage_model: SlippageModel, 
+/**
+ * Resting order the trade executed, if any. A partially filled order
+ * produces one trade per execution.
+ */
+order_id?: string, profit?: string, };
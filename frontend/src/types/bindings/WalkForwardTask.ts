@@ -0,0 +1,50 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { BacktestStatus } from "./BacktestStatus";
+import type { EquityPoint } from "./EquityPoint";
+import type { FillModel } from "./FillModel";
+import type { LiquidityConfig } from "./LiquidityConfig";
+import type { MarginConfig } from "./MarginConfig";
+import type { MarketPrecision } from "./MarketPrecision";
+import type { OptimizationMetric } from "./OptimizationMetric";
+import type { ParameterRange } from "./ParameterRange";
+import type { SearchMethod } from "./SearchMethod";
+import type { SlippageModel } from "./SlippageModel";
+import type { Timeframe } from "./Timeframe";
+import type { TradingFees } from "./TradingFees";
+import type { WalkForwardWindow } from "./WalkForwardWindow";
+import type { JsonValue } from "./serde_json/JsonValue";
+
+export type WalkForwardTask = { id: string, status: BacktestStatus, progress: number, name: string, ranges: { [key in string]?: ParameterRange }, search: SearchMethod, metric: OptimizationMetric, 
+/**
+ * Primary-symbol candles each optimization is run on.
+ */
+in_sample_candles: number, 
+/**
+ * Primary-symbol candles each winner is run on; windows advance by this much.
+ */
+out_of_sample_candles: number, windows: Array<WalkForwardWindow>, 
+/**
+ * Out-of-sample equity of all windows, each starting from the previous
+ * window's final equity.
+ */
+equity_curve: Array<EquityPoint>, error_message?: string, created_at: number, started_at?: number, completed_at?: number, updated_at: number, exchange: string, symbol: string, 
+/**
+ * Additional symbols traded alongside `symbol` from one shared balance.
+ */
+symbols: Array<string>, timeframe: Timeframe, 
+/**
+ * Object deserialized into the strategy; its defaults are used when unset.
+ */
+parameters?: JsonValue, start?: number, end?: number, initial_capital: string, 
+/**
+ * Annual rate the Sharpe and Sortino ratios measure excess returns over.
+ */
+risk_free_rate: string, 
+/**
+ * Fees charged on every symbol; the exchange's fees when unset.
+ */
+fees?: TradingFees, 
+/**
+ * Precision of the primary symbol.
+ */
+precision: MarketPrecision, margin: MarginConfig, fill_model: FillModel, slippage_model: SlippageModel, liquidity?: LiquidityConfig, };
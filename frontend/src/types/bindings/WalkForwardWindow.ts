@@ -0,0 +1,16 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { BacktestStatistic } from "./BacktestStatistic";
+import type { OptimizationResult } from "./OptimizationResult";
+
+/**
+ * One in-sample optimization and the out-of-sample run of its winner.
+ */
+export type WalkForwardWindow = { in_sample_start: number, 
+/**
+ * Also the start of the out-of-sample period.
+ */
+out_of_sample_start: number, out_of_sample_end: number, 
+/**
+ * Best in-sample run; its parameters are used out of sample.
+ */
+in_sample: OptimizationResult, out_of_sample: BacktestStatistic, };
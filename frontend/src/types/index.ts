@@ -1,5 +1,7 @@
 export * from './bindings/AddStrategyRequest'
 export * from './bindings/AvailableCandleInfo'
+export * from './bindings/BacktestConfig'
+export * from './bindings/BacktestInputs'
 export * from './bindings/BacktestStatistic'
 export * from './bindings/BacktestStatus'
 export * from './bindings/BacktestTask'
@@ -8,7 +10,12 @@ export * from './bindings/CreateBacktestTaskRequest'
 export * from './bindings/CreateBacktestTaskResponse'
 export * from './bindings/CreateFetchCandlesTaskRequest'
 export * from './bindings/CreateFetchCandlesTaskResponse'
+export * from './bindings/CreateOptimizationTaskRequest'
+export * from './bindings/CreateOptimizationTaskResponse'
+export * from './bindings/CreateWalkForwardTaskRequest'
+export * from './bindings/CreateWalkForwardTaskResponse'
 export * from './bindings/DeleteSourceQuery'
+export * from './bindings/EquityPoint'
 export * from './bindings/ErrorResponse'
 export * from './bindings/ExchangeQuery'
 export * from './bindings/FetchCandlesResult'
@@ -16,14 +23,35 @@ export * from './bindings/FetchCandlesStatus'
 export * from './bindings/FetchCandlesTask'
 export * from './bindings/FileNode'
 export * from './bindings/FileNodeType'
+export * from './bindings/FillModel'
 export * from './bindings/GetCandlesQuery'
+export * from './bindings/GetParametersQuery'
+export * from './bindings/GetParametersResponse'
 export * from './bindings/GetSourceQuery'
 export * from './bindings/GetSourceResponse'
+export * from './bindings/LiquidityConfig'
 export * from './bindings/ListStrategiesResponse'
+export * from './bindings/LogEntry'
+export * from './bindings/LogLevel'
+export * from './bindings/MarginConfig'
 export * from './bindings/MarketPrecision'
 export * from './bindings/MoveSourceQuery'
+export * from './bindings/OptimizationMetric'
+export * from './bindings/OptimizationResult'
+export * from './bindings/OptimizationTask'
 export * from './bindings/OrderType'
+export * from './bindings/ParameterRange'
+export * from './bindings/PlotPoint'
+export * from './bindings/PlotSeries'
+export * from './bindings/Reproduction'
+export * from './bindings/RoundTrip'
 export * from './bindings/SaveSourceQuery'
+export * from './bindings/SearchMethod'
+export * from './bindings/SlippageModel'
+export * from './bindings/StrategyOutput'
 export * from './bindings/Timeframe'
 export * from './bindings/Trade'
 export * from './bindings/TradeType'
+export * from './bindings/TradingFees'
+export * from './bindings/WalkForwardTask'
+export * from './bindings/WalkForwardWindow'
@@ -0,0 +1,10 @@
+import type { TradeType } from '@/types'
+
+export function isBuyTrade(tradeType: TradeType): boolean {
+  return tradeType.endsWith('_buy')
+}
+
+export function tradeKindLabel(tradeType: TradeType): string {
+  const kind = tradeType.slice(0, tradeType.lastIndexOf('_'))
+  return kind.replace(/_/g, ' ').toUpperCase()
+}
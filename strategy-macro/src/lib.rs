@@ -1,22 +1,191 @@
 use proc_macro::TokenStream;
 use quote::quote;
-use syn::{parse_macro_input, DeriveInput};
+use syn::{parse_macro_input, DeriveInput, Ident};
 
+/// Exports a strategy from a plugin crate.
+///
+/// `#[strategy]` builds the strategy with `Default::default()`. `#[strategy(parameters)]`
+/// additionally deserializes the backtest's parameter object into the struct and exports
+/// its JSON schema, so the struct must implement `Deserialize` and `schemars::JsonSchema`.
+///
+/// The exported functions form fettle's versioned C plugin interface, see
+/// `fettle::strategy::plugin`. The server calls the strategy only through them,
+/// and they turn its panics into errors before returning.
 #[proc_macro_attribute]
-pub fn strategy(_attr: TokenStream, item: TokenStream) -> TokenStream {
+pub fn strategy(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut parameters = false;
+    let attr_parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("parameters") {
+            parameters = true;
+            Ok(())
+        } else {
+            Err(meta.error("unsupported strategy property"))
+        }
+    });
+    parse_macro_input!(attr with attr_parser);
+
     let input: DeriveInput = parse_macro_input!(item as DeriveInput);
     let name = &input.ident;
 
-    const PLUGIN_CREATE_FUNCTION_NAME: &'static str = "_plugin_create";
-    let func_name = syn::Ident::new(PLUGIN_CREATE_FUNCTION_NAME, name.span());
+    const PLUGIN_ABI_VERSION_FUNCTION_NAME: &str = "_plugin_abi_version";
+    const PLUGIN_BUILD_ID_FUNCTION_NAME: &str = "_plugin_build_id";
+    const PLUGIN_PARAMETERS_FUNCTION_NAME: &str = "_plugin_parameters";
+    const PLUGIN_CREATE_FUNCTION_NAME: &str = "_plugin_create";
+    const PLUGIN_DESTROY_FUNCTION_NAME: &str = "_plugin_destroy";
+    const PLUGIN_FREE_STRING_FUNCTION_NAME: &str = "_plugin_free_string";
+    const PLUGIN_TIMEFRAMES_FUNCTION_NAME: &str = "_plugin_timeframes";
+    const PLUGIN_LOOKBACK_FUNCTION_NAME: &str = "_plugin_lookback";
+    const PLUGIN_ON_START_FUNCTION_NAME: &str = "_plugin_on_start";
+    const PLUGIN_TICK_FUNCTION_NAME: &str = "_plugin_tick";
+    const PLUGIN_ON_ORDER_FILLED_FUNCTION_NAME: &str = "_plugin_on_order_filled";
+    const PLUGIN_ON_ORDER_CANCELLED_FUNCTION_NAME: &str = "_plugin_on_order_cancelled";
+    const PLUGIN_ON_END_FUNCTION_NAME: &str = "_plugin_on_end";
+    let abi_version_name = Ident::new(PLUGIN_ABI_VERSION_FUNCTION_NAME, name.span());
+    let build_id_name = Ident::new(PLUGIN_BUILD_ID_FUNCTION_NAME, name.span());
+    let parameters_name = Ident::new(PLUGIN_PARAMETERS_FUNCTION_NAME, name.span());
+    let create_name = Ident::new(PLUGIN_CREATE_FUNCTION_NAME, name.span());
+    let destroy_name = Ident::new(PLUGIN_DESTROY_FUNCTION_NAME, name.span());
+    let free_string_name = Ident::new(PLUGIN_FREE_STRING_FUNCTION_NAME, name.span());
+    let timeframes_name = Ident::new(PLUGIN_TIMEFRAMES_FUNCTION_NAME, name.span());
+    let lookback_name = Ident::new(PLUGIN_LOOKBACK_FUNCTION_NAME, name.span());
+    let on_start_name = Ident::new(PLUGIN_ON_START_FUNCTION_NAME, name.span());
+    let tick_name = Ident::new(PLUGIN_TICK_FUNCTION_NAME, name.span());
+    let on_order_filled_name = Ident::new(PLUGIN_ON_ORDER_FILLED_FUNCTION_NAME, name.span());
+    let on_order_cancelled_name = Ident::new(PLUGIN_ON_ORDER_CANCELLED_FUNCTION_NAME, name.span());
+    let on_end_name = Ident::new(PLUGIN_ON_END_FUNCTION_NAME, name.span());
+
+    let (create_body, parameters_body) = if parameters {
+        (
+            quote! {
+                if parameters.is_empty() {
+                    Ok(::std::default::Default::default())
+                } else {
+                    ::serde_json::from_str::<#name>(parameters)
+                        .map_err(|e| format!("Invalid strategy parameters: {}", e))
+                }
+            },
+            quote! {
+                let schema = ::schemars::schema_for!(#name);
+                ::serde_json::to_string(&schema).ok()
+            },
+        )
+    } else {
+        (
+            quote! {
+                let _ = parameters;
+                Ok(<#name as ::std::default::Default>::default())
+            },
+            quote! {
+                None
+            },
+        )
+    };
 
     let expanded = quote! {
         #input
 
         #[unsafe(no_mangle)]
-        pub fn #func_name() -> *mut dyn ::fettle::Strategy {
-            let strategy = <#name as ::std::default::Default>::default();
-            Box::into_raw(Box::new(strategy))
+        pub extern "C" fn #abi_version_name() -> u32 {
+            ::fettle::strategy::plugin::ABI_VERSION
+        }
+
+        #[unsafe(no_mangle)]
+        pub extern "C" fn #build_id_name() -> *const ::std::ffi::c_char {
+            ::fettle::strategy::plugin::build_id().as_ptr()
+        }
+
+        #[unsafe(no_mangle)]
+        pub unsafe extern "C" fn #parameters_name(
+            schema: *mut *mut ::std::ffi::c_char,
+        ) -> *mut ::std::ffi::c_char {
+            unsafe {
+                ::fettle::strategy::plugin::parameters(schema, || {
+                    #parameters_body
+                })
+            }
+        }
+
+        #[unsafe(no_mangle)]
+        pub unsafe extern "C" fn #create_name(
+            parameters: *const ::std::ffi::c_char,
+            strategy: *mut *mut ::fettle::strategy::plugin::PluginStrategy,
+        ) -> *mut ::std::ffi::c_char {
+            unsafe {
+                ::fettle::strategy::plugin::create(parameters, strategy, |parameters| {
+                    #create_body
+                })
+            }
+        }
+
+        #[unsafe(no_mangle)]
+        pub unsafe extern "C" fn #destroy_name(
+            strategy: *mut ::fettle::strategy::plugin::PluginStrategy,
+        ) {
+            unsafe { ::fettle::strategy::plugin::destroy(strategy) }
+        }
+
+        #[unsafe(no_mangle)]
+        pub unsafe extern "C" fn #free_string_name(string: *mut ::std::ffi::c_char) {
+            unsafe { ::fettle::strategy::plugin::free_string(string) }
+        }
+
+        #[unsafe(no_mangle)]
+        pub unsafe extern "C" fn #timeframes_name(
+            strategy: *const ::fettle::strategy::plugin::PluginStrategy,
+            json: *mut *mut ::std::ffi::c_char,
+        ) -> *mut ::std::ffi::c_char {
+            unsafe { ::fettle::strategy::plugin::timeframes(strategy, json) }
+        }
+
+        #[unsafe(no_mangle)]
+        pub unsafe extern "C" fn #lookback_name(
+            strategy: *const ::fettle::strategy::plugin::PluginStrategy,
+            json: *mut *mut ::std::ffi::c_char,
+        ) -> *mut ::std::ffi::c_char {
+            unsafe { ::fettle::strategy::plugin::lookback(strategy, json) }
+        }
+
+        #[unsafe(no_mangle)]
+        pub unsafe extern "C" fn #on_start_name(
+            strategy: *mut ::fettle::strategy::plugin::PluginStrategy,
+            context: *const ::fettle::strategy::StrategyContext,
+        ) -> *mut ::std::ffi::c_char {
+            unsafe { ::fettle::strategy::plugin::on_start(strategy, context) }
+        }
+
+        #[unsafe(no_mangle)]
+        pub unsafe extern "C" fn #tick_name(
+            strategy: *mut ::fettle::strategy::plugin::PluginStrategy,
+            context: *mut ::fettle::strategy::StrategyContext,
+        ) -> *mut ::std::ffi::c_char {
+            unsafe { ::fettle::strategy::plugin::tick(strategy, context) }
+        }
+
+        #[unsafe(no_mangle)]
+        pub unsafe extern "C" fn #on_order_filled_name(
+            strategy: *mut ::fettle::strategy::plugin::PluginStrategy,
+            context: *mut ::fettle::strategy::StrategyContext,
+            order: *const ::fettle::strategy::Order,
+            trade: *const ::fettle::strategy::Trade,
+        ) -> *mut ::std::ffi::c_char {
+            unsafe { ::fettle::strategy::plugin::on_order_filled(strategy, context, order, trade) }
+        }
+
+        #[unsafe(no_mangle)]
+        pub unsafe extern "C" fn #on_order_cancelled_name(
+            strategy: *mut ::fettle::strategy::plugin::PluginStrategy,
+            context: *mut ::fettle::strategy::StrategyContext,
+            order: *const ::fettle::strategy::Order,
+        ) -> *mut ::std::ffi::c_char {
+            unsafe { ::fettle::strategy::plugin::on_order_cancelled(strategy, context, order) }
+        }
+
+        #[unsafe(no_mangle)]
+        pub unsafe extern "C" fn #on_end_name(
+            strategy: *mut ::fettle::strategy::plugin::PluginStrategy,
+            context: *const ::fettle::strategy::StrategyContext,
+        ) -> *mut ::std::ffi::c_char {
+            unsafe { ::fettle::strategy::plugin::on_end(strategy, context) }
         }
     };
 
@@ -0,0 +1,19 @@
+//! A strategy plugin that panics in `tick`, loaded by fettle's tests to check
+//! that a plugin's panics come back as errors.
+
+use fettle::{AppError, AppResult, Strategy, StrategyContext, strategy};
+
+#[strategy]
+#[derive(Default)]
+struct Panicking;
+
+impl Strategy for Panicking {
+    fn tick(&mut self, ctx: &mut StrategyContext) -> AppResult<()> {
+        let candles = ctx.candles();
+        panic!("no candle at {}", candles.len());
+    }
+
+    fn on_end(&mut self, _ctx: &StrategyContext) -> AppResult<()> {
+        Err(AppError::Strategy("ended without trading".into()))
+    }
+}